@@ -0,0 +1,48 @@
+// insert_writes.rs
+// © 2024 Epic Mickey Library
+
+// Times many small INSERT mode writes against one splice_region with the same inserts, to show
+// that every INSERT write moves the tail of the data. Run with `cargo bench`.
+
+#![allow(clippy::needless_return)]
+
+use std::time::{Duration, Instant};
+
+use epic_mickey_lib_rs::file_manipulator::{EndianType, FileManipulator, WriteMode};
+
+const BASE_SIZE: usize = 200_000;
+const INSERT_COUNTS: [usize; 3] = [5_000, 10_000, 20_000];
+
+fn time<T>(function: impl FnOnce() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let result = function();
+    return (start.elapsed(), result);
+}
+
+fn main() {
+    for count in INSERT_COUNTS {
+        // spread the inserts over the data, front to back
+        let offsets: Vec<usize> = (0..count).map(|i| i * BASE_SIZE / count).collect();
+
+        let (write_time, written) = time(|| {
+            let mut fm = FileManipulator::new(vec![0; BASE_SIZE], EndianType::BIG, WriteMode::INSERT);
+            fm.reserve(count * 2);
+            // each earlier insert shifts the later offsets by two bytes
+            for (i, offset) in offsets.iter().enumerate() {
+                fm.seek(offset + i * 2);
+                fm.write(&[0xAB, 0xCD]);
+            }
+            return fm.into_data();
+        });
+
+        let (splice_time, spliced) = time(|| {
+            let mut fm = FileManipulator::new(vec![0; BASE_SIZE], EndianType::BIG, WriteMode::OVERWRITE);
+            let inserts: Vec<(usize, &[u8])> = offsets.iter().map(|offset| (*offset, &[0xAB, 0xCD][..])).collect();
+            fm.splice_region(&inserts);
+            return fm.into_data();
+        });
+
+        assert_eq!(written, spliced);
+        println!("{:>6} inserts into {} bytes: write {:>10.3?}, splice_region {:>10.3?}", count, BASE_SIZE, write_time, splice_time);
+    }
+}
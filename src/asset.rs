@@ -0,0 +1,200 @@
+// asset.rs
+// © 2024 Epic Mickey Library
+
+use crate::{collectible_database, dct, file_manipulator, scene_file};
+
+/// A game file format that converts between its binary form and JSON.
+/// 
+/// The inherent methods on each format take slightly different argument types; this trait gives
+/// them one shape so tooling can work with any of them. Call through the trait
+/// (`Asset::to_json(&dct)` or `<dct::DCT as Asset>::from_json(json)`), since inherent methods with
+/// the same name take priority in method call syntax.
+pub trait Asset: Sized {
+    /// The file extension of the binary form, lowercase and without the dot.
+    const EXTENSION: &'static str;
+
+    fn from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Self;
+    /// Like `from_binary`, but returns an error instead of panicking on malformed data.
+    fn try_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<Self, Box<dyn std::error::Error>>;
+    fn to_binary(&self, endian_type: file_manipulator::EndianType) -> Vec<u8>;
+    fn from_json(json: &str) -> Self;
+    fn to_json(&self) -> String;
+    fn merge_in_json(&mut self, json: &str);
+}
+
+impl Asset for dct::DCT {
+    const EXTENSION: &'static str = "dct";
+
+    fn from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Self {
+        return dct::DCT::from_slice(data, endian_type);
+    }
+
+    fn try_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<Self, Box<dyn std::error::Error>> {
+        // retail formats aren't in KNOWN_FORMATS yet, so only the layout is checked
+        return Ok(dct::DCT::try_from_binary_with_formats(data.to_vec(), endian_type, &[])?);
+    }
+
+    fn to_binary(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        return dct::DCT::to_binary(self, endian_type);
+    }
+
+    fn from_json(json: &str) -> Self {
+        return dct::DCT::from_json(json.to_string());
+    }
+
+    fn to_json(&self) -> String {
+        return dct::DCT::to_json(self);
+    }
+
+    fn merge_in_json(&mut self, json: &str) {
+        dct::DCT::merge_in_json(self, json.to_string());
+    }
+}
+
+impl Asset for scene_file::SceneFile {
+    const EXTENSION: &'static str = "bin";
+
+    fn from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Self {
+        return scene_file::SceneFile::from_binary(data, endian_type);
+    }
+
+    fn try_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<Self, Box<dyn std::error::Error>> {
+        // like from_binary, a scene with an unknown version is kept as raw data
+        return match scene_file::SceneFile::try_from_binary(data, endian_type) {
+            Err(scene_file::SceneFileError::UnknownVersion { .. }) => Ok(scene_file::SceneFile::from_binary(data, endian_type)),
+            result => Ok(result?)
+        };
+    }
+
+    fn to_binary(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        return scene_file::SceneFile::to_binary(self, endian_type);
+    }
+
+    fn from_json(json: &str) -> Self {
+        return scene_file::SceneFile::from_json(json);
+    }
+
+    fn to_json(&self) -> String {
+        return scene_file::SceneFile::to_json(self);
+    }
+
+    fn merge_in_json(&mut self, json: &str) {
+        scene_file::SceneFile::merge_in_json(self, json);
+    }
+}
+
+impl Asset for collectible_database::CollectibleDatabase {
+    const EXTENSION: &'static str = "clb";
+
+    fn from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Self {
+        return collectible_database::CollectibleDatabase::from_slice(data, endian_type);
+    }
+
+    fn try_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<Self, Box<dyn std::error::Error>> {
+        return Ok(collectible_database::CollectibleDatabase::try_from_binary(data, endian_type)?);
+    }
+
+    fn to_binary(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        return collectible_database::CollectibleDatabase::to_binary(self, endian_type);
+    }
+
+    fn from_json(json: &str) -> Self {
+        return collectible_database::CollectibleDatabase::from_json(json);
+    }
+
+    fn to_json(&self) -> String {
+        return collectible_database::CollectibleDatabase::to_json(self);
+    }
+
+    fn merge_in_json(&mut self, json: &str) {
+        collectible_database::CollectibleDatabase::merge_in_json(self, json);
+    }
+}
+
+type Decompiler = fn(&[u8], file_manipulator::EndianType) -> String;
+type TryDecompiler = fn(&[u8], file_manipulator::EndianType) -> Result<String, Box<dyn std::error::Error>>;
+type Compiler = fn(&str, file_manipulator::EndianType) -> Vec<u8>;
+
+fn decompile<A: Asset>(data: &[u8], endian_type: file_manipulator::EndianType) -> String {
+    return A::from_binary(data, endian_type).to_json();
+}
+
+fn try_decompile<A: Asset>(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<String, Box<dyn std::error::Error>> {
+    return Ok(A::try_from_binary(data, endian_type)?.to_json());
+}
+
+fn compile<A: Asset>(json: &str, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+    return A::from_json(json).to_binary(endian_type);
+}
+
+/// Converts the binary data of a file to JSON, picking the format from the file's extension.
+/// Returns `None` if no `Asset` handles the extension.
+pub fn decompile_by_extension(extension: &str, data: &[u8], endian_type: file_manipulator::EndianType) -> Option<String> {
+    let extension = extension.to_lowercase();
+    let decompilers: [(&str, Decompiler); 3] = [
+        (dct::DCT::EXTENSION, decompile::<dct::DCT>),
+        (scene_file::SceneFile::EXTENSION, decompile::<scene_file::SceneFile>),
+        (collectible_database::CollectibleDatabase::EXTENSION, decompile::<collectible_database::CollectibleDatabase>),
+    ];
+    for (asset_extension, decompiler) in decompilers {
+        if asset_extension == extension {
+            return Some(decompiler(data, endian_type));
+        }
+    }
+    return None;
+}
+
+/// Like `decompile_by_extension`, but returns the error instead of panicking when the data can't
+/// be read as the format its extension names. Returns `None` if no `Asset` handles the extension.
+pub fn try_decompile_by_extension(extension: &str, data: &[u8], endian_type: file_manipulator::EndianType) -> Option<Result<String, Box<dyn std::error::Error>>> {
+    let extension = extension.to_lowercase();
+    let decompilers: [(&str, TryDecompiler); 3] = [
+        (dct::DCT::EXTENSION, try_decompile::<dct::DCT>),
+        (scene_file::SceneFile::EXTENSION, try_decompile::<scene_file::SceneFile>),
+        (collectible_database::CollectibleDatabase::EXTENSION, try_decompile::<collectible_database::CollectibleDatabase>),
+    ];
+    for (asset_extension, decompiler) in decompilers {
+        if asset_extension == extension {
+            return Some(decompiler(data, endian_type));
+        }
+    }
+    return None;
+}
+
+/// Converts JSON back to the binary form of the format with the given extension, the inverse of
+/// `decompile_by_extension`. Returns `None` if no `Asset` handles the extension.
+pub fn compile_by_extension(extension: &str, json: &str, endian_type: file_manipulator::EndianType) -> Option<Vec<u8>> {
+    let extension = extension.to_lowercase();
+    let compilers: [(&str, Compiler); 3] = [
+        (dct::DCT::EXTENSION, compile::<dct::DCT>),
+        (scene_file::SceneFile::EXTENSION, compile::<scene_file::SceneFile>),
+        (collectible_database::CollectibleDatabase::EXTENSION, compile::<collectible_database::CollectibleDatabase>),
+    ];
+    for (asset_extension, compiler) in compilers {
+        if asset_extension == extension {
+            return Some(compiler(json, endian_type));
+        }
+    }
+    return None;
+}
+
+/// Parses `data` as `A`, writes it back with the same byte order and compares the result with the
+/// input. Returns the offset of the first byte that differs, or the length of the shorter of the
+/// two if one is a prefix of the other. Malformed data still panics while parsing.
+pub fn assert_roundtrip<A: Asset>(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<(), usize> {
+    let repacked = A::from_binary(data, endian_type).to_binary(endian_type);
+    return match first_difference(data, &repacked) {
+        Some(offset) => Err(offset),
+        None => Ok(())
+    };
+}
+
+pub(crate) fn first_difference(original: &[u8], repacked: &[u8]) -> Option<usize> {
+    if let Some(offset) = original.iter().zip(repacked).position(|(a, b)| a != b) {
+        return Some(offset);
+    }
+    if original.len() != repacked.len() {
+        return Some(original.len().min(repacked.len()));
+    }
+    return None;
+}
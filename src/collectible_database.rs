@@ -1,10 +1,61 @@
 // collectible_database.rs
 // © 2024 Epic Mickey Library
 
-use std::{fs::File, io::{Read, Write}};
+use std::{collections::HashSet, fs::File, io::{Read, Write}};
+use serde::{Deserialize, Serialize};
 use serde_json;
+use crate::asset;
 use crate::file_manipulator;
 
+/// A problem with a collectible or extra found by `validate`.
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// A field the game needs is empty.
+    EmptyField { entry: String, field: &'static str },
+    /// A string is longer than a JPS string can hold (`file_manipulator::MAX_JPS_STRING_LENGTH`).
+    StringTooLong { entry: String, field: &'static str, length: usize },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValidationError::EmptyField { entry, field } => write!(f, "{} has an empty {}", entry, field),
+            ValidationError::StringTooLong { entry, field, length } => write!(f, "{} of {} is {} bytes long, the most that fits is {}", field, entry, length, file_manipulator::MAX_JPS_STRING_LENGTH),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Why `try_from_binary` could not read a collectible database.
+#[derive(Debug, PartialEq)]
+pub enum ReadError {
+    /// The collectible and extra tables run past the end of the data.
+    Truncated,
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadError::Truncated => write!(f, "The collectible database runs past the end of the data"),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+// checks the fields of one collectible or extra, given as (name, value, required)
+fn validate_fields(entry: &str, fields: &[(&'static str, &str, bool)], errors: &mut Vec<ValidationError>) {
+    for (field, value, required) in fields {
+        if *required && value.is_empty() {
+            errors.push(ValidationError::EmptyField { entry: entry.to_string(), field });
+        }
+        if value.len() > file_manipulator::MAX_JPS_STRING_LENGTH {
+            errors.push(ValidationError::StringTooLong { entry: entry.to_string(), field, length: value.len() });
+        }
+    }
+}
+
 /// A collectible in the game.
 /// 
 /// # Fields
@@ -12,7 +63,9 @@ use crate::file_manipulator;
 /// * `type_` - The type of the collectible.
 /// * `dev_name` - The developer name of the collectible (dialog key).
 /// * `icon_path` - The path to the icon of the collectible.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Collectible {
+    #[serde(rename = "type")]
     pub type_: String,
     pub dev_name: String,
     pub icon_path: String,
@@ -32,7 +85,7 @@ impl Collectible {
         fm.w_str_jps(&self.type_);
         fm.w_str_jps(&self.dev_name);
         fm.w_str_jps(&self.icon_path);
-        return fm.get_data().clone();
+        return fm.into_data();
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
@@ -42,20 +95,64 @@ impl Collectible {
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut map = serde_json::Map::new();
-        map.insert("type".to_string(), serde_json::Value::String(self.type_.clone()));
-        map.insert("dev_name".to_string(), serde_json::Value::String(self.dev_name.clone()));
-        map.insert("icon_path".to_string(), serde_json::Value::String(self.icon_path.clone()));
-        return serde_json::Value::Object(map);
+        return serde_json::to_value(self).unwrap();
     }
 
     pub fn from_dict(dict: serde_json::Value) -> Collectible {
-        let type_ = dict["type"].as_str().unwrap().to_string();
-        let dev_name = dict["dev_name"].as_str().unwrap().to_string();
-        let icon_path = dict["icon_path"].as_str().unwrap().to_string();
-        return Collectible::new(type_, dev_name, icon_path);
+        return serde_json::from_value(dict).unwrap();
+    }
+
+    /// Checks that `dev_name` is not empty and that every string fits in a JPS string.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.validate_as("collectible", &mut errors);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        return Err(errors);
+    }
+
+    fn validate_as(&self, entry: &str, errors: &mut Vec<ValidationError>) {
+        validate_fields(entry, &[("type", &self.type_, false), ("dev_name", &self.dev_name, true), ("icon_path", &self.icon_path, false)], errors);
+    }
+}
+
+/// Builds a `Collectible` by field name. The fields other than `dev_name` default to empty.
+/// 
+/// # Example
+/// 
+/// ```
+/// # use epic_mickey_lib_rs::collectible_database::CollectibleBuilder;
+/// let collectible = CollectibleBuilder::new("PIN_A")
+///     .type_("Pin")
+///     .icon_path("icons/pin_a.tga")
+///     .build();
+/// assert!(collectible.validate().is_ok());
+/// ```
+pub struct CollectibleBuilder {
+    collectible: Collectible
+}
+
+impl CollectibleBuilder {
+    pub fn new(dev_name: &str) -> CollectibleBuilder {
+        return CollectibleBuilder {
+            collectible: Collectible::new(String::new(), dev_name.to_string(), String::new())
+        };
+    }
+
+    pub fn type_(mut self, type_: &str) -> CollectibleBuilder {
+        self.collectible.type_ = type_.to_string();
+        return self;
+    }
+
+    pub fn icon_path(mut self, icon_path: &str) -> CollectibleBuilder {
+        self.collectible.icon_path = icon_path.to_string();
+        return self;
     }
 
+    pub fn build(self) -> Collectible {
+        return self.collectible;
+    }
 }
 
 /// An extra in the game.
@@ -66,8 +163,10 @@ impl Collectible {
 /// * `type_` - The type of the extra.
 /// * `thumbnail_path` - The path to the thumbnail image of the extra.
 /// * `asset_path` - The path to the asset of the extra (e.g. a movie).
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Extra {
     pub global_state: String,
+    #[serde(rename = "type")]
     pub type_: String,
     pub thumbnail_path: String,
     pub asset_path: String,
@@ -89,7 +188,7 @@ impl Extra {
         fm.w_str_jps(&self.type_);
         fm.w_str_jps(&self.thumbnail_path);
         fm.w_str_jps(&self.asset_path);
-        return fm.get_data().clone();
+        return fm.into_data();
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
@@ -100,20 +199,68 @@ impl Extra {
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut map = serde_json::Map::new();
-        map.insert("global_state".to_string(), serde_json::Value::String(self.global_state.clone()));
-        map.insert("type".to_string(), serde_json::Value::String(self.type_.clone()));
-        map.insert("thumbnail_path".to_string(), serde_json::Value::String(self.thumbnail_path.clone()));
-        map.insert("asset_path".to_string(), serde_json::Value::String(self.asset_path.clone()));
-        return serde_json::Value::Object(map);
+        return serde_json::to_value(self).unwrap();
     }
 
     pub fn from_dict(dict: serde_json::Value) -> Extra {
-        let global_state = dict["global_state"].as_str().unwrap().to_string();
-        let type_ = dict["type"].as_str().unwrap().to_string();
-        let thumbnail_path = dict["thumbnail_path"].as_str().unwrap().to_string();
-        let asset_path = dict["asset_path"].as_str().unwrap().to_string();
-        return Extra::new(global_state, type_, thumbnail_path, asset_path);
+        return serde_json::from_value(dict).unwrap();
+    }
+
+    /// Checks that `asset_path` is not empty and that every string fits in a JPS string.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.validate_as("extra", &mut errors);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        return Err(errors);
+    }
+
+    fn validate_as(&self, entry: &str, errors: &mut Vec<ValidationError>) {
+        validate_fields(entry, &[("global_state", &self.global_state, false), ("type", &self.type_, false), ("thumbnail_path", &self.thumbnail_path, false), ("asset_path", &self.asset_path, true)], errors);
+    }
+}
+
+/// Builds an `Extra` by field name. The fields other than `asset_path` default to empty.
+/// 
+/// # Example
+/// 
+/// ```
+/// # use epic_mickey_lib_rs::collectible_database::ExtraBuilder;
+/// let extra = ExtraBuilder::new("movies/intro.thp")
+///     .type_("Movie")
+///     .thumbnail_path("thumbnails/intro.tga")
+///     .build();
+/// assert_eq!(extra.global_state, "");
+/// ```
+pub struct ExtraBuilder {
+    extra: Extra
+}
+
+impl ExtraBuilder {
+    pub fn new(asset_path: &str) -> ExtraBuilder {
+        return ExtraBuilder {
+            extra: Extra::new(String::new(), String::new(), String::new(), asset_path.to_string())
+        };
+    }
+
+    pub fn global_state(mut self, global_state: &str) -> ExtraBuilder {
+        self.extra.global_state = global_state.to_string();
+        return self;
+    }
+
+    pub fn type_(mut self, type_: &str) -> ExtraBuilder {
+        self.extra.type_ = type_.to_string();
+        return self;
+    }
+
+    pub fn thumbnail_path(mut self, thumbnail_path: &str) -> ExtraBuilder {
+        self.extra.thumbnail_path = thumbnail_path.to_string();
+        return self;
+    }
+
+    pub fn build(self) -> Extra {
+        return self.extra;
     }
 }
 
@@ -163,19 +310,102 @@ impl CollectibleDatabase {
         self.extras.push(extra);
     }
 
+    pub fn get_collectible_by_dev_name(&self, dev_name: &str) -> Option<&Collectible> {
+        return self.collectibles.iter().find(|collectible| collectible.dev_name == dev_name);
+    }
+
+    pub fn get_collectible_by_dev_name_mut(&mut self, dev_name: &str) -> Option<&mut Collectible> {
+        return self.collectibles.iter_mut().find(|collectible| collectible.dev_name == dev_name);
+    }
+
+    pub fn remove_collectible_by_dev_name(&mut self, dev_name: &str) -> Option<Collectible> {
+        let index = self.collectibles.iter().position(|collectible| collectible.dev_name == dev_name)?;
+        return Some(self.collectibles.remove(index));
+    }
+
+    pub fn get_extra_by_asset_path(&self, asset_path: &str) -> Option<&Extra> {
+        return self.extras.iter().find(|extra| extra.asset_path == asset_path);
+    }
+
+    pub fn get_extra_by_asset_path_mut(&mut self, asset_path: &str) -> Option<&mut Extra> {
+        return self.extras.iter_mut().find(|extra| extra.asset_path == asset_path);
+    }
+
+    pub fn remove_extra_by_asset_path(&mut self, asset_path: &str) -> Option<Extra> {
+        let index = self.extras.iter().position(|extra| extra.asset_path == asset_path)?;
+        return Some(self.extras.remove(index));
+    }
+
+    /// Removes collectibles and extras that are exact copies of an earlier one, keeping the order
+    /// of the rest.
+    pub fn dedup(&mut self) {
+        let mut seen_collectibles: HashSet<Collectible> = HashSet::new();
+        self.collectibles.retain(|collectible| seen_collectibles.insert(collectible.clone()));
+        let mut seen_extras: HashSet<Extra> = HashSet::new();
+        self.extras.retain(|extra| seen_extras.insert(extra.clone()));
+    }
+
+    /// Appends the collectibles and extras of `other` that this database doesn't already have an
+    /// exact copy of. The version is left as it is.
+    pub fn merge(&mut self, other: &CollectibleDatabase) {
+        let mut seen_collectibles: HashSet<Collectible> = self.collectibles.iter().cloned().collect();
+        for collectible in &other.collectibles {
+            if seen_collectibles.insert(collectible.clone()) {
+                self.collectibles.push(collectible.clone());
+            }
+        }
+        let mut seen_extras: HashSet<Extra> = self.extras.iter().cloned().collect();
+        for extra in &other.extras {
+            if seen_extras.insert(extra.clone()) {
+                self.extras.push(extra.clone());
+            }
+        }
+    }
+
+    /// Replaces every `icon_path`, `thumbnail_path` and `asset_path` with what `f` returns for it.
+    pub fn rewrite_paths(&mut self, f: impl Fn(&str) -> String) {
+        for collectible in &mut self.collectibles {
+            collectible.icon_path = f(&collectible.icon_path);
+        }
+        for extra in &mut self.extras {
+            extra.thumbnail_path = f(&extra.thumbnail_path);
+            extra.asset_path = f(&extra.asset_path);
+        }
+    }
+
+    /// Replaces `old` with `new` at the start of every path that is `old` or inside the folder
+    /// `old`, for when a folder has moved. Either `/` or `\` separates folders.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::collectible_database::{CollectibleBuilder, CollectibleDatabase};
+    /// let mut database = CollectibleDatabase::new(1);
+    /// database.add_collectible(CollectibleBuilder::new("Pin01").icon_path("UI/Pins/Pin01.tga").build());
+    /// database.add_collectible(CollectibleBuilder::new("Pin02").icon_path("UI/PinsOld/Pin02.tga").build());
+    /// database.replace_path_prefix("UI/Pins", "UI/Collectibles/Pins");
+    /// assert_eq!(database.collectibles[0].icon_path, "UI/Collectibles/Pins/Pin01.tga");
+    /// assert_eq!(database.collectibles[1].icon_path, "UI/PinsOld/Pin02.tga");
+    /// ```
+    pub fn replace_path_prefix(&mut self, old: &str, new: &str) {
+        self.rewrite_paths(|path| {
+            match path.strip_prefix(old) {
+                Some(rest) if rest.is_empty() || old.ends_with(['/', '\\']) || rest.starts_with(['/', '\\']) => return format!("{}{}", new, rest),
+                _ => return path.to_string()
+            }
+        });
+    }
+
     pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
-        let endian_type_clone = endian_type.clone();
-        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type_clone, file_manipulator::WriteMode::OVERWRITE);
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
         fm.w_u32(self.version);
         fm.w_u32(self.collectibles.len() as u32);
         for collectible in &self.collectibles {
-            fm.write(&collectible.pack(endian_type.clone()));
+            fm.write(&collectible.pack(endian_type));
         }
         fm.w_u32(self.extras.len() as u32);
         for extra in &self.extras {
-            fm.write(&extra.pack(endian_type.clone()));
+            fm.write(&extra.pack(endian_type));
         }
-        return fm.get_data().clone();
+        return fm.into_data();
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
@@ -198,6 +428,39 @@ impl CollectibleDatabase {
         return self.pack(endian_type);
     }
 
+    /// Validates every collectible and extra, returning all the problems found. Each error names
+    /// its entry by position, such as "collectible 3".
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        for (i, collectible) in self.collectibles.iter().enumerate() {
+            collectible.validate_as(&format!("collectible {}", i), &mut errors);
+        }
+        for (i, extra) in self.extras.iter().enumerate() {
+            extra.validate_as(&format!("extra {}", i), &mut errors);
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        return Err(errors);
+    }
+
+    /// Like `to_binary`, but runs `validate` first and returns its errors instead of writing an
+    /// invalid database.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::collectible_database::{CollectibleBuilder, CollectibleDatabase, ExtraBuilder, ValidationError};
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// let mut database = CollectibleDatabase::new(1);
+    /// database.add_collectible(CollectibleBuilder::new("PIN_A").build());
+    /// database.add_extra(ExtraBuilder::new("").build());
+    /// let errors = database.to_binary_checked(EndianType::BIG).err().unwrap();
+    /// assert_eq!(errors, vec![ValidationError::EmptyField { entry: "extra 0".to_string(), field: "asset_path" }]);
+    /// ```
+    pub fn to_binary_checked(&self, endian_type: file_manipulator::EndianType) -> Result<Vec<u8>, Vec<ValidationError>> {
+        self.validate()?;
+        return Ok(self.to_binary(endian_type));
+    }
+
     pub fn to_binary_path(&self, path: String, endian_type: file_manipulator::EndianType) {
         let mut file = File::create(path).unwrap();
         file.write_all(&self.to_binary(endian_type)).unwrap();
@@ -220,7 +483,19 @@ impl CollectibleDatabase {
     }
 
     pub fn to_json(&self) -> String {
-        return serde_json::to_string_pretty(&self.to_dict()).unwrap();
+        return self.to_json_with(true);
+    }
+
+    /// Like `to_json`, but without indentation or line breaks.
+    pub fn to_json_compact(&self) -> String {
+        return self.to_json_with(false);
+    }
+
+    pub fn to_json_with(&self, pretty: bool) -> String {
+        if pretty {
+            return serde_json::to_string_pretty(&self.to_dict()).unwrap();
+        }
+        return serde_json::to_string(&self.to_dict()).unwrap();
     }
 
     pub fn to_json_path(&self, path: String) {
@@ -235,6 +510,46 @@ impl CollectibleDatabase {
         return collectible_database;
     }
 
+    /// Like `from_binary`, but borrows the data. It is copied for now.
+    pub fn from_slice(data: &[u8], endian_type: file_manipulator::EndianType) -> CollectibleDatabase {
+        return CollectibleDatabase::from_binary(data.to_vec(), endian_type);
+    }
+
+    /// Like `from_slice`, but returns `ReadError::Truncated` instead of panicking when the tables
+    /// run past the end of the data.
+    pub fn try_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<CollectibleDatabase, ReadError> {
+        if walk_layout(data, endian_type).is_none() {
+            return Err(ReadError::Truncated);
+        }
+        return Ok(CollectibleDatabase::from_slice(data, endian_type));
+    }
+
+    /// Guesses the endianness of a collectible database.
+    /// 
+    /// The layout is walked in both byte orders without panicking: the collectible count, three JPS
+    /// strings per collectible, the extra count and four JPS strings per extra. A byte order whose
+    /// walk stays inside the buffer beats one that runs off the end, then one that ends exactly at
+    /// the end of the data wins, then the one that reads the smaller version. Ties go to big endian.
+    /// Pass the endian to `from_binary` yourself for files with trailing padding, or when both byte
+    /// orders happen to produce a valid layout.
+    pub fn detect_endian(data: &[u8]) -> file_manipulator::EndianType {
+        let score = |endian_type: file_manipulator::EndianType| {
+            let end = walk_layout(data, endian_type);
+            let version = file_manipulator::read_u32_at(data, 0, endian_type).unwrap_or(u32::MAX);
+            return (end.is_some(), end == Some(data.len()), std::cmp::Reverse(version));
+        };
+        if score(file_manipulator::EndianType::BIG) >= score(file_manipulator::EndianType::LITTLE) {
+            return file_manipulator::EndianType::BIG;
+        }
+        return file_manipulator::EndianType::LITTLE;
+    }
+
+    /// Reads a database after guessing its endianness with `detect_endian`, returning both.
+    pub fn from_binary_autodetect(data: Vec<u8>) -> (CollectibleDatabase, file_manipulator::EndianType) {
+        let endian_type = CollectibleDatabase::detect_endian(&data);
+        return (CollectibleDatabase::from_binary(data, endian_type), endian_type);
+    }
+
     pub fn from_binary_path(path: String, endian_type: file_manipulator::EndianType) -> CollectibleDatabase {
         // check if the file exists
         if std::path::Path::new(&path).exists() {
@@ -249,11 +564,16 @@ impl CollectibleDatabase {
     pub fn from_dict(dict: serde_json::Value) -> CollectibleDatabase {
         let version = dict["version"].as_u64().unwrap() as u32;
         let mut collectible_database = CollectibleDatabase::new(version);
-        for collectible in dict["collectibles"].as_array().unwrap() {
-            collectible_database.add_collectible(Collectible::from_dict(collectible.clone()));
+        // a missing or null section is an empty list
+        if let Some(collectibles) = dict["collectibles"].as_array() {
+            for collectible in collectibles {
+                collectible_database.add_collectible(Collectible::from_dict(collectible.clone()));
+            }
         }
-        for extra in dict["extras"].as_array().unwrap() {
-            collectible_database.add_extra(Extra::from_dict(extra.clone()));
+        if let Some(extras) = dict["extras"].as_array() {
+            for extra in extras {
+                collectible_database.add_extra(Extra::from_dict(extra.clone()));
+            }
         }
         return collectible_database;
     }
@@ -272,5 +592,128 @@ impl CollectibleDatabase {
         file.read_to_string(&mut json).unwrap();
         return CollectibleDatabase::from_json(&json);
     }
-    
-}
\ No newline at end of file
+
+    /// Merges a database dict into this one. Collectibles replace the one with the same `dev_name`
+    /// and extras the one with the same `asset_path`; the rest are appended. A `version` in the dict
+    /// overwrites the current one.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::collectible_database::{Collectible, CollectibleDatabase};
+    /// let mut database = CollectibleDatabase::new(1);
+    /// database.add_collectible(Collectible::new("Pin".to_string(), "PIN_A".to_string(), "old.tga".to_string()));
+    /// let mut patch = CollectibleDatabase::new(1);
+    /// patch.add_collectible(Collectible::new("Pin".to_string(), "PIN_A".to_string(), "new.tga".to_string()));
+    /// patch.add_collectible(Collectible::new("Pin".to_string(), "PIN_B".to_string(), "b.tga".to_string()));
+    /// database.merge_in_dict(patch.to_dict());
+    /// assert_eq!(database.collectibles.len(), 2);
+    /// assert_eq!(database.collectibles[0].icon_path, "new.tga");
+    /// assert_eq!(database.collectibles[1].dev_name, "PIN_B");
+    /// ```
+    pub fn merge_in_dict(&mut self, dict: serde_json::Value) {
+        if let Some(version) = dict["version"].as_u64() {
+            self.version = version as u32;
+        }
+        if let Some(collectibles) = dict["collectibles"].as_array() {
+            for collectible in collectibles {
+                let collectible = Collectible::from_dict(collectible.clone());
+                match self.get_collectible_by_dev_name_mut(&collectible.dev_name) {
+                    Some(self_collectible) => *self_collectible = collectible,
+                    None => self.collectibles.push(collectible)
+                }
+            }
+        }
+        if let Some(extras) = dict["extras"].as_array() {
+            for extra in extras {
+                let extra = Extra::from_dict(extra.clone());
+                match self.get_extra_by_asset_path_mut(&extra.asset_path) {
+                    Some(self_extra) => *self_extra = extra,
+                    None => self.extras.push(extra)
+                }
+            }
+        }
+    }
+
+    pub fn merge_in_json(&mut self, json: &str) {
+        self.merge_in_dict(serde_json::from_str(json).unwrap());
+    }
+
+    pub fn merge_in_json_path(&mut self, path: String) {
+        // check if the file exists
+        if !std::path::Path::new(&path).exists() {
+            panic!("File does not exist: {}", path);
+        }
+        let json = std::fs::read_to_string(path).unwrap();
+        self.merge_in_json(&json);
+    }
+}
+
+/// Parses a binary collectible database and writes it back, returning the offset of the first
+/// byte where the output differs from `data`. See `asset::assert_roundtrip`.
+pub fn assert_roundtrip(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<(), usize> {
+    return asset::assert_roundtrip::<CollectibleDatabase>(data, endian_type);
+}
+
+// skips one JPS string (size, length, null-terminated text, 4 byte alignment) and returns the new offset
+fn skip_str_jps(data: &[u8], offset: usize) -> Option<usize> {
+    let text_start = offset + 2;
+    let text_length = data.get(text_start..)?.iter().position(|byte| *byte == 0)?;
+    let end = offset + (text_length + 3).next_multiple_of(4);
+    if end > data.len() {
+        return None;
+    }
+    return Some(end);
+}
+
+// walks the whole database layout, returning where it ends or None if it runs off the data
+fn walk_layout(data: &[u8], endian_type: file_manipulator::EndianType) -> Option<usize> {
+    let mut offset = 4;
+    for strings_per_entry in [3, 4] {
+        let count = file_manipulator::read_u32_at(data, offset, endian_type)?;
+        offset += 4;
+        for _ in 0..count {
+            for _ in 0..strings_per_entry {
+                offset = skip_str_jps(data, offset)?;
+            }
+        }
+    }
+    return Some(offset);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_manipulator::EndianType;
+
+    #[test]
+    fn loads_json_without_extras() {
+        let json = r#"{"version": 2, "collectibles": [{"type": "Pin", "dev_name": "PIN_A", "icon_path": "icons/pin_a.tga"}]}"#;
+        let collectible_database = CollectibleDatabase::from_json(json);
+        assert_eq!(collectible_database.version, 2);
+        assert_eq!(collectible_database.collectibles.len(), 1);
+        assert!(collectible_database.extras.is_empty());
+
+        let repacked = CollectibleDatabase::from_binary(collectible_database.pack(EndianType::BIG), EndianType::BIG);
+        assert!(repacked.collectibles == collectible_database.collectibles);
+        assert!(repacked.extras.is_empty());
+    }
+
+    #[test]
+    fn detects_the_endian_it_was_packed_in() {
+        let json = r#"{"version": 2, "collectibles": [{"type": "Pin", "dev_name": "PIN_A", "icon_path": "icons/pin_a.tga"}],
+            "extras": [{"global_state": "", "type": "Art", "thumbnail_path": "thumbs/art.tga", "asset_path": "art/art.tga"}]}"#;
+        let collectible_database = CollectibleDatabase::from_json(json);
+        let big = collectible_database.pack(EndianType::BIG);
+        let little = collectible_database.pack(EndianType::LITTLE);
+        assert!(CollectibleDatabase::detect_endian(&big) == EndianType::BIG);
+        assert!(CollectibleDatabase::detect_endian(&little) == EndianType::LITTLE);
+    }
+
+    #[test]
+    fn ambiguous_or_short_data_detects_as_big_endian() {
+        // version 0 and no entries reads the same in both byte orders
+        assert!(CollectibleDatabase::detect_endian(&[0; 12]) == EndianType::BIG);
+        // too short for the counts in either byte order
+        assert!(CollectibleDatabase::detect_endian(&[0, 0, 0]) == EndianType::BIG);
+        assert!(CollectibleDatabase::detect_endian(&[]) == EndianType::BIG);
+    }
+}
@@ -1,18 +1,143 @@
 // dct.rs
 // © 2024 Epic Mickey Library
 
-use std::{fs::File, io::{Read, Write}};
+use std::{collections::HashMap, fs::File, io::{Read, Write}};
+use serde::{Deserialize, Serialize};
 use serde_json;
+use crate::asset;
 use crate::file_manipulator;
 
+// size of the fixed header before the dialog entry table
+const HEADER_SIZE: u32 = 32;
+// size of one dialog entry (hashed key, line offset, zero)
+const DIALOG_ENTRY_SIZE: u32 = 12;
+// size of one footer entry (line offset, number)
+const FOOTER_ENTRY_SIZE: u32 = 8;
+// position of the end offset field in the header
+const END_OFFSET_POSITION: u32 = 24;
+// the end offset is stored relative to its own field minus this bias
+const END_OFFSET_BIAS: u32 = 9;
+
+/// The raw bytes that start the trailer after the footer table, written the same in both byte orders.
+pub const FOOTER_TRAILER_MARKER: [u8; 4] = [0xDF, 0xFF, 0xFF, 0xFF];
+/// The u32 values that follow `FOOTER_TRAILER_MARKER`, in the byte order of the file.
+pub const FOOTER_TRAILER_VALUES: [u32; 3] = [11, 12, 0];
+// size of the marker and values together
+const FOOTER_TRAILER_SIZE: u32 = 16;
+
+/// A magic and version pair that `DCT::try_from_binary_with_formats` accepts.
+/// 
+/// # Fields
+/// 
+/// * `magic` - The 4 character magic at the start of the file
+/// * `version1` - The first version field
+/// * `version2` - The second version field
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DctFormat {
+    pub magic: &'static str,
+    pub version1: u32,
+    pub version2: u32
+}
+
+/// The DCT formats `try_from_binary` accepts. Only the format this library's own examples and
+/// tests write is listed; the formats of retail files have not been checked yet, so `from_binary`
+/// and the `Asset` conversions accept any magic of 4 printable characters with any versions. Pass
+/// other formats to check to `try_from_binary_with_formats`.
+pub const KNOWN_FORMATS: &[DctFormat] = &[
+    DctFormat { magic: "DCT ", version1: 1, version2: 2 }
+];
+
+/// An error produced while working with a DCT file.
+#[derive(Debug)]
+pub enum DctError {
+    /// A dialog entry with this hashed key already exists.
+    DuplicateKey(u32),
+    /// The footer table is not followed by the trailer at this offset.
+    MalformedFooter(usize),
+    /// The table's `hash_seed` is 0, so its keys can't be hashed.
+    ZeroHashSeed,
+    /// This key hashes to 0, which marks an empty slot.
+    EmptySlotKey(String),
+    /// The data is not a DCT file, for this reason.
+    NotADct(String),
+    /// The magic is known, but not with these versions (`version1`, `version2`).
+    UnsupportedDctVersion(u32, u32),
+    /// The magic is not exactly 4 bytes long, so it would shift the rest of the header.
+    InvalidMagic(String),
+    /// A DCT or an entry given as a dict can't be read, for this reason (which says which field or
+    /// entry).
+    InvalidEntry(String),
+    /// A CSV row, numbered from 1, can't be read, for this reason.
+    InvalidCsv { row: usize, reason: String },
+}
+
+impl std::fmt::Display for DctError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DctError::DuplicateKey(hashed_key) => write!(f, "Duplicate hashed key: {}", hashed_key),
+            DctError::MalformedFooter(offset) => write!(f, "Malformed DCT footer: no trailer at offset {}", offset),
+            DctError::ZeroHashSeed => write!(f, "The DCT hash seed is 0, was the header loaded?"),
+            DctError::EmptySlotKey(key) => write!(f, "Key hashes to 0, which marks an empty slot: {}", key),
+            DctError::NotADct(reason) => write!(f, "Not a DCT file: {}", reason),
+            DctError::UnsupportedDctVersion(version1, version2) => write!(f, "Unsupported DCT version: {}, {}", version1, version2),
+            DctError::InvalidMagic(magic) => write!(f, "DCT magic must be 4 bytes, \"{}\" is {}", magic, magic.len()),
+            DctError::InvalidEntry(reason) => write!(f, "Invalid DCT entry: {}", reason),
+            DctError::InvalidCsv { row, reason } => write!(f, "Invalid CSV row {}: {}", row, reason),
+        }
+    }
+}
+
+impl std::error::Error for DctError {}
+
+// say which entry an InvalidEntry error is about
+fn entry_error(path: String, error: DctError) -> DctError {
+    return match error {
+        DctError::InvalidEntry(reason) => DctError::InvalidEntry(format!("{}: {}", path, reason)),
+        error => error
+    };
+}
+
+/// What to do when a merge brings in a dialog entry whose hashed key is already in the table.
+/// 
+/// # Variants
+/// 
+/// * `Error` - Fail the merge without changing anything.
+/// * `KeepFirst` - Keep the text already in the table.
+/// * `KeepLast` - Overwrite with the incoming text (the behavior of `merge_in_dict`).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DuplicateKeyPolicy {
+    Error,
+    KeepFirst,
+    KeepLast,
+}
+
+/// What `DCT::upsert` did.
+/// 
+/// # Variants
+/// 
+/// * `Inserted` - A new dialog entry was appended.
+/// * `Updated` - The text of the entry with the same hashed key was replaced.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UpsertOutcome {
+    Inserted,
+    Updated,
+}
+
 /// Represents a single entry in a DCT file.
 /// 
+/// An entry with a hashed key of 0 is an empty slot. Empty slots keep their position in the table
+/// and are serialized to JSON as `{"empty": true}`.
+/// 
 /// # Fields
 /// 
 /// * `hashed_key` - The hashed key of the entry.
+/// * `key` - The plaintext key, if it is known. Only the hash is stored in the binary file.
 /// * `text` - The text of the entry.
+#[derive(Serialize, Deserialize)]
 pub struct DialogEntry {
     pub hashed_key: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
     pub text: String
 }
 
@@ -20,21 +145,75 @@ impl DialogEntry {
     pub fn new(hashed_key: u32, text: String) -> DialogEntry {
         DialogEntry {
             hashed_key,
+            key: None,
             text
         }
     }
 
+    pub fn empty() -> DialogEntry {
+        return DialogEntry::new(0, "".to_owned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.hashed_key == 0;
+    }
+
     pub fn to_dict(&self) -> serde_json::Value {
         let mut dict = serde_json::Map::new();
-        dict.insert("hashed_key".to_owned(), serde_json::Value::Number(serde_json::Number::from(self.hashed_key)));
-        dict.insert("text".to_owned(), serde_json::Value::String(self.text.clone()));
-        return serde_json::Value::Object(dict);
+        if self.is_empty() {
+            dict.insert("empty".to_owned(), serde_json::Value::Bool(true));
+            return serde_json::Value::Object(dict);
+        }
+        return serde_json::to_value(self).unwrap();
     }
 
     pub fn from_dict(dict: serde_json::Value) -> DialogEntry {
-        let hashed_key = dict["hashed_key"].as_u64().unwrap() as u32;
-        let text = dict["text"].as_str().unwrap().to_owned();
-        return DialogEntry::new(hashed_key, text);
+        return DialogEntry::from_dict_with_key_names(dict, &HashMap::new());
+    }
+
+    /// Like `from_dict`, but returns `InvalidEntry` instead of panicking when a field is missing
+    /// or has the wrong type.
+    pub fn try_from_dict(dict: serde_json::Value) -> Result<DialogEntry, DctError> {
+        return DialogEntry::try_from_dict_with_key_names(dict, &HashMap::new());
+    }
+
+    /// Like `from_dict`, but an entry may give only its plaintext `key`, which is looked up in
+    /// `key_names` to find the hash. Panics if the key is not in `key_names`, see
+    /// `try_from_dict_with_key_names`.
+    pub fn from_dict_with_key_names(dict: serde_json::Value, key_names: &HashMap<u32, String>) -> DialogEntry {
+        return match DialogEntry::try_from_dict_with_key_names(dict, key_names) {
+            Ok(dialog_entry) => dialog_entry,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `from_dict_with_key_names`, but returns `InvalidEntry` instead of panicking when the
+    /// key is not in `key_names` or a field is missing or has the wrong type.
+    /// 
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use epic_mickey_lib_rs::dct::{DctError, DialogEntry};
+    /// let key_names = HashMap::from([(0x1234, "HUB_GUS_01".to_string())]);
+    /// let known = serde_json::json!({ "key": "HUB_GUS_01", "text": "Hi" });
+    /// assert_eq!(DialogEntry::try_from_dict_with_key_names(known, &key_names).unwrap().hashed_key, 0x1234);
+    /// let unknown = serde_json::json!({ "key": "HUB_GUS_02", "text": "Hi" });
+    /// assert!(matches!(DialogEntry::try_from_dict_with_key_names(unknown, &key_names), Err(DctError::InvalidEntry(_))));
+    /// ```
+    pub fn try_from_dict_with_key_names(mut dict: serde_json::Value, key_names: &HashMap<u32, String>) -> Result<DialogEntry, DctError> {
+        // empty slot marker
+        if dict["empty"].as_bool() == Some(true) {
+            return Ok(DialogEntry::empty());
+        }
+        if dict["hashed_key"].is_null() {
+            if let Some(key) = dict["key"].as_str() {
+                let hashed_key = match key_names.iter().find(|(_, name)| name.as_str() == key) {
+                    Some((hashed_key, _)) => *hashed_key,
+                    None => return Err(DctError::InvalidEntry(format!("unknown dialog key \"{}\"", key)))
+                };
+                dict["hashed_key"] = serde_json::Value::from(hashed_key);
+            }
+        }
+        return serde_json::from_value(dict).map_err(|error| DctError::InvalidEntry(error.to_string()));
     }
 }
 
@@ -44,6 +223,7 @@ impl DialogEntry {
 /// 
 /// * `number` - The number of the entry.
 /// * `text` - The text of the entry.
+#[derive(Serialize, Deserialize)]
 pub struct FooterEntry {
     pub number: u32,
     pub text: String
@@ -58,19 +238,61 @@ impl FooterEntry {
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut dict = serde_json::Map::new();
-        dict.insert("number".to_owned(), serde_json::Value::Number(serde_json::Number::from(self.number)));
-        dict.insert("text".to_owned(), serde_json::Value::String(self.text.clone()));
-        return serde_json::Value::Object(dict);
+        return serde_json::to_value(self).unwrap();
     }
 
     pub fn from_dict(dict: serde_json::Value) -> FooterEntry {
-        let number = dict["number"].as_u64().unwrap() as u32;
-        let text = dict["text"].as_str().unwrap().to_owned();
-        return FooterEntry::new(number, text);
+        return serde_json::from_value(dict).unwrap();
+    }
+
+    /// Like `from_dict`, but returns `InvalidEntry` instead of panicking when a field is missing
+    /// or has the wrong type.
+    pub fn try_from_dict(dict: serde_json::Value) -> Result<FooterEntry, DctError> {
+        return serde_json::from_value(dict).map_err(|error| DctError::InvalidEntry(error.to_string()));
     }
 }
 
+/// Summary counts of a binary DCT, see `DCT::stats_from_binary`.
+/// 
+/// # Fields
+/// 
+/// * `dialog_entries` - The number of dialog entries, including empty slots.
+/// * `empty_entries` - The number of empty slots.
+/// * `footer_entries` - The number of footer entries.
+/// * `hash_seed` - The hash seed from the header.
+/// * `text_bytes` - The total length in bytes of the dialog and footer texts, without terminators.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DctStats {
+    pub dialog_entries: u32,
+    pub empty_entries: u32,
+    pub footer_entries: u32,
+    pub hash_seed: u32,
+    pub text_bytes: u64
+}
+
+/// Line lengths of the dialog entries of a DCT, in characters, made by `DCT::text_length_stats`.
+/// Empty slots are not counted.
+/// 
+/// # Fields
+/// 
+/// * `min` - The length of the shortest line, 0 if there are none.
+/// * `max` - The length of the longest line, 0 if there are none.
+/// * `mean` - The average line length, 0 if there are none.
+/// * `histogram` - The number of lines of each length.
+/// * `over_threshold` - The hashed keys of the lines longer than the threshold, in table order.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LengthStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub histogram: std::collections::BTreeMap<usize, u32>,
+    pub over_threshold: Vec<u32>
+}
+
+/// Hashes a plaintext dialog key with a DCT's hash seed. The game's key hash is not known, so
+/// this library doesn't implement it; `hash_key`, `verify_keys` and `upsert` take one of these.
+pub type KeyHasher = fn(u32, &str) -> u32;
+
 /// Represents a DCT file.
 /// 
 /// # Fields
@@ -103,6 +325,41 @@ impl DCT {
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
+        if let Err(error) = self.try_unpack(fm, &[]) {
+            panic!("{}", error);
+        }
+    }
+
+    // check the header before trusting its counts and offsets, an empty formats accepts any magic and versions
+    fn check_header(data: &[u8], endian_type: file_manipulator::EndianType, formats: &[DctFormat]) -> Result<(), DctError> {
+        if data.len() < HEADER_SIZE as usize {
+            return Err(DctError::NotADct(format!("{} bytes is shorter than the {} byte header", data.len(), HEADER_SIZE)));
+        }
+        let magic = &data[0..4];
+        if !magic.iter().all(|byte| (0x20..=0x7E).contains(byte)) {
+            return Err(DctError::NotADct(format!("the magic {:02X?} is not text", magic)));
+        }
+        let magic = String::from_utf8_lossy(magic);
+        let version1 = file_manipulator::read_u32_at(data, 4, endian_type).unwrap();
+        let version2 = file_manipulator::read_u32_at(data, 12, endian_type).unwrap();
+        if !formats.is_empty() {
+            if !formats.iter().any(|format| format.magic == magic) {
+                return Err(DctError::NotADct(format!("unknown magic \"{}\"", magic)));
+            }
+            if !formats.iter().any(|format| format.magic == magic && format.version1 == version1 && format.version2 == version2) {
+                return Err(DctError::UnsupportedDctVersion(version1, version2));
+            }
+        }
+        let num_dialog_entries = file_manipulator::read_u32_at(data, 16, endian_type).unwrap() as u64;
+        let table_end = HEADER_SIZE as u64 + num_dialog_entries * DIALOG_ENTRY_SIZE as u64;
+        if table_end > data.len() as u64 {
+            return Err(DctError::NotADct(format!("{} dialog entries don't fit in {} bytes", num_dialog_entries, data.len())));
+        }
+        return Ok(());
+    }
+
+    fn try_unpack(&mut self, fm: &mut file_manipulator::FileManipulator, formats: &[DctFormat]) -> Result<(), DctError> {
+        DCT::check_header(fm.get_data(), *fm.get_endian(), formats)?;
         self.magic = fm.r_str(4);
         self.version1 = fm.r_u32();
         self.hash_seed = fm.r_u32();
@@ -112,7 +369,8 @@ impl DCT {
 
         fm.move_pos(4);
 
-        let footer_offset = fm.tell() as u32 + fm.r_u32() + 9;
+        // the end offset points just past the footer table (stored as a possibly negative relative offset)
+        let footer_offset = (END_OFFSET_POSITION + END_OFFSET_BIAS).wrapping_add(fm.r_u32());
 
         let mut has_footer = false;
         let footer_switch = fm.r_u32();
@@ -120,46 +378,106 @@ impl DCT {
             has_footer = true;
         }
 
-        let mut current_data_offset = fm.tell();
-
         self.dialog_entries = Vec::new();
         for _ in 0..num_dialog_entries {
             let hashed_key = fm.r_u32();
             if hashed_key == 0 {
                 fm.move_pos(8);
                 // add empty dialog entry
-                self.dialog_entries.push(DialogEntry::new(0, "".to_owned()));
-                current_data_offset = fm.tell();
+                self.dialog_entries.push(DialogEntry::empty());
                 continue;
             }
-            let line_offset = fm.tell() as u32 + fm.r_u32() + 1;
-            let line_zero = fm.r_u32();
-            current_data_offset = fm.tell();
-            fm.seek(line_offset as usize);
-            let line_text = fm.r_str_null();
-            self.dialog_entries.push(DialogEntry::new(hashed_key, line_text));
-            fm.seek(current_data_offset);
+            let line_offset = (fm.tell() as u32 + 1).wrapping_add(fm.r_u32());
+            let _line_zero = fm.r_u32();
+            if line_offset as usize >= fm.size() {
+                return Err(DctError::NotADct(format!("the line of {} starts past the end, at {}", hashed_key, line_offset)));
+            }
+            let mut line_fm = fm.save_position();
+            line_fm.seek_read(line_offset as usize);
+            self.dialog_entries.push(DialogEntry::new(hashed_key, line_fm.r_str_null()));
         }
         self.footer_entries = Vec::new();
         if has_footer {
+            if footer_offset as usize > fm.size() {
+                return Err(DctError::NotADct(format!("the footer table ends past the end, at {}", footer_offset)));
+            }
             while fm.tell() < footer_offset as usize {
-                let footer_line_offset = fm.tell() as u32 + fm.r_u32() + 1;
+                let footer_line_offset = (fm.tell() as u32 + 1).wrapping_add(fm.r_u32());
                 let footer_line_id = fm.r_u32();
-                current_data_offset = fm.tell();
-                fm.seek(footer_line_offset as usize);
-                let footer_line_text = fm.r_str_null();
-                self.footer_entries.push(FooterEntry::new(footer_line_id, footer_line_text));
-                fm.seek(current_data_offset);
+                if footer_line_offset as usize >= fm.size() {
+                    return Err(DctError::NotADct(format!("the footer line {} starts past the end, at {}", footer_line_id, footer_line_offset)));
+                }
+                let mut line_fm = fm.save_position();
+                line_fm.seek_read(footer_line_offset as usize);
+                self.footer_entries.push(FooterEntry::new(footer_line_id, line_fm.r_str_null()));
+            }
+            if !DCT::has_footer_trailer(fm.get_data(), fm.tell(), *fm.get_endian()) {
+                return Err(DctError::MalformedFooter(fm.tell()));
+            }
+        }
+        return Ok(());
+    }
+
+    fn has_footer_trailer(data: &[u8], offset: usize, endian_type: file_manipulator::EndianType) -> bool {
+        if data.get(offset..offset + 4) != Some(&FOOTER_TRAILER_MARKER[..]) {
+            return false;
+        }
+        for (i, value) in FOOTER_TRAILER_VALUES.iter().enumerate() {
+            if file_manipulator::read_u32_at(data, offset + 4 + i * 4, endian_type) != Some(*value) {
+                return false;
             }
         }
+        return true;
     }
 
-    pub fn pack(&self) -> Vec<u8> {
-        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), file_manipulator::EndianType::LITTLE, file_manipulator::WriteMode::OVERWRITE);
+    /// Writes the DCT. The dialog and footer tables come first and the strings after them, so
+    /// no table slot and string byte overlap for any number of entries.
+    /// 
+    /// ```
+    /// use epic_mickey_lib_rs::dct::{DCT, DialogEntry, FooterEntry};
+    /// use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// 
+    /// for count in 0..40u32 {
+    ///     for with_footer in [false, true] {
+    ///         let dialog_entries = (0..count).map(|i| match i % 5 {
+    ///             4 => DialogEntry::empty(),
+    ///             _ => DialogEntry::new(i + 1, "line ".repeat(i as usize % 7))
+    ///         }).collect();
+    ///         let footer_entries = if with_footer { (0..count % 4 + 1).map(|i| FooterEntry::new(i, format!("footer {}", i))).collect() } else { Vec::new() };
+    ///         let dct = DCT::new("DCT ".to_string(), 1, 0, 1, dialog_entries, footer_entries);
+    ///         let data = dct.pack(EndianType::BIG);
+    ///         let read = DCT::from_binary(data.clone(), EndianType::BIG);
+    ///         assert_eq!(read.pack(EndianType::BIG), data);
+    ///         for (entry, read_entry) in dct.dialog_entries.iter().zip(&read.dialog_entries) {
+    ///             assert_eq!((entry.hashed_key, &entry.text), (read_entry.hashed_key, &read_entry.text));
+    ///         }
+    ///         assert_eq!(read.footer_entries.len(), dct.footer_entries.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        return match self.try_pack(endian_type) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    fn check_magic(magic: &str) -> Result<(), DctError> {
+        if magic.len() != 4 {
+            return Err(DctError::InvalidMagic(magic.to_string()));
+        }
+        return Ok(());
+    }
+
+    fn try_pack(&self, endian_type: file_manipulator::EndianType) -> Result<Vec<u8>, DctError> {
+        DCT::check_magic(&self.magic)?;
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
 
         let num_dialog_entries = self.dialog_entries.len() as u32;
         let num_footer_entries = self.footer_entries.len() as u32;
-        let end_offset = (num_dialog_entries * 12) + (num_footer_entries * 8) - 1;
+        let table_end = HEADER_SIZE + (num_dialog_entries * DIALOG_ENTRY_SIZE) + (num_footer_entries * FOOTER_ENTRY_SIZE);
+        // wraps to a negative relative offset for an empty table
+        let end_offset = table_end.wrapping_sub(END_OFFSET_POSITION + END_OFFSET_BIAS);
 
         fm.w_str(&self.magic);
         fm.w_u32(self.version1);
@@ -177,8 +495,12 @@ impl DCT {
             fm.w_u32(0);
         }
 
+        // the strings start one byte past the tables and the footer trailer; without a footer the
+        // trailer's space is left zeroed, so the layout is the same either way
+        let heap_start = table_end + FOOTER_TRAILER_SIZE + 1;
+
         let mut current_data_offset = fm.tell();
-        let mut current_line_offset = end_offset + 50;
+        let mut current_line_offset = heap_start;
 
         for dialog_entry in &self.dialog_entries {
             fm.seek(current_data_offset);
@@ -210,23 +532,37 @@ impl DCT {
                 current_line_offset = fm.tell() as u32;
                 fm.seek(current_data_offset);
             }
-            fm.write(&[0xDF, 0xFF, 0xFF, 0xFF]);
-            fm.w_u32(11);
-            fm.w_u32(12);
-            fm.w_u32(0);
+            fm.write(&FOOTER_TRAILER_MARKER);
+            for value in FOOTER_TRAILER_VALUES {
+                fm.w_u32(value);
+            }
         }
+        assert!(fm.tell() < heap_start as usize, "DCT tables end at {}, past the string heap at {}", fm.tell(), heap_start);
 
         // return data
-        return fm.get_data().to_vec()
+        return Ok(fm.into_data());
     }
 
-    pub fn to_binary(&self) -> Vec<u8> {
-        return self.pack();
+    pub fn to_binary(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        return self.pack(endian_type);
     }
 
-    pub fn to_binary_path(&self, path: String) {
+    /// Like `to_binary`, but returns `DctError::InvalidMagic` instead of panicking when `magic`
+    /// is not exactly 4 bytes.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::dct::{DCT, DctError};
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// let dct = DCT::new("DCT".to_string(), 1, 0x1234, 1, vec![], vec![]);
+    /// assert!(matches!(dct.try_to_binary(EndianType::BIG), Err(DctError::InvalidMagic(_))));
+    /// ```
+    pub fn try_to_binary(&self, endian_type: file_manipulator::EndianType) -> Result<Vec<u8>, DctError> {
+        return self.try_pack(endian_type);
+    }
+
+    pub fn to_binary_path(&self, path: String, endian_type: file_manipulator::EndianType) {
         let mut file = File::create(path).unwrap();
-        file.write_all(&self.pack()).unwrap();
+        file.write_all(&self.to_binary(endian_type)).unwrap();
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
@@ -249,7 +585,19 @@ impl DCT {
     }
 
     pub fn to_json(&self) -> String {
-        return serde_json::to_string_pretty(&self.to_dict()).unwrap();
+        return self.to_json_with(true);
+    }
+
+    /// Like `to_json`, but without indentation or line breaks.
+    pub fn to_json_compact(&self) -> String {
+        return self.to_json_with(false);
+    }
+
+    pub fn to_json_with(&self, pretty: bool) -> String {
+        if pretty {
+            return serde_json::to_string_pretty(&self.to_dict()).unwrap();
+        }
+        return serde_json::to_string(&self.to_dict()).unwrap();
     }
 
     pub fn to_json_path(&self, path: String) {
@@ -257,14 +605,156 @@ impl DCT {
         file.write_all(self.to_json().as_bytes()).unwrap();
     }
 
-    pub fn from_binary(data: Vec<u8>) -> DCT {
-        let mut fm = file_manipulator::FileManipulator::new(data, file_manipulator::EndianType::LITTLE, file_manipulator::WriteMode::OVERWRITE);
+    /// Guesses the endianness of a binary DCT from its dialog entry count.
+    /// 
+    /// The count is read both ways and the reading whose entry table fits inside the data wins.
+    /// If both fit, the smaller count is chosen. Falls back to little endian for data too short to hold a header.
+    pub fn detect_endian(data: &[u8]) -> file_manipulator::EndianType {
+        if data.len() < 20 {
+            return file_manipulator::EndianType::LITTLE;
+        }
+        let count_bytes = [data[16], data[17], data[18], data[19]];
+        let little_count = u32::from_le_bytes(count_bytes) as u64;
+        let big_count = u32::from_be_bytes(count_bytes) as u64;
+        let table_space = data.len() as u64 - 20;
+        let little_fits = little_count * 12 <= table_space;
+        let big_fits = big_count * 12 <= table_space;
+        if big_fits && (!little_fits || big_count < little_count) {
+            return file_manipulator::EndianType::BIG;
+        }
+        return file_manipulator::EndianType::LITTLE;
+    }
+
+    pub fn from_binary(data: Vec<u8>, endian_type: file_manipulator::EndianType) -> DCT {
+        let mut fm = file_manipulator::FileManipulator::new(data, endian_type, file_manipulator::WriteMode::OVERWRITE);
         let mut dct = DCT::new("".to_owned(), 0, 0, 0, Vec::new(), Vec::new());
         dct.unpack(&mut fm);
         return dct;
     }
 
-    pub fn from_binary_path(path: String) -> DCT {
+    /// Like `from_binary`, but borrows the data. It is copied for now.
+    pub fn from_slice(data: &[u8], endian_type: file_manipulator::EndianType) -> DCT {
+        return DCT::from_binary(data.to_vec(), endian_type);
+    }
+
+    /// Like `from_binary`, but checks the format against `KNOWN_FORMATS` and returns an error
+    /// instead of panicking: `NotADct` when the header doesn't describe a DCT or has an unknown
+    /// magic, `UnsupportedDctVersion` for a known magic with unknown versions, and
+    /// `MalformedFooter` when a file with a footer is missing the trailer after the footer table.
+    pub fn try_from_binary(data: Vec<u8>, endian_type: file_manipulator::EndianType) -> Result<DCT, DctError> {
+        return DCT::try_from_binary_with_formats(data, endian_type, KNOWN_FORMATS);
+    }
+
+    /// Like `try_from_binary`, accepting only the magic and version pairs in `formats` (any, if it
+    /// is empty). A file with another magic is `NotADct` and one with a listed magic but other
+    /// versions is `UnsupportedDctVersion`.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::dct::{DCT, DctError, DctFormat};
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// // a made-up format, not one taken from the game
+    /// const FORMATS: &[DctFormat] = &[DctFormat { magic: "TEST", version1: 1, version2: 2 }];
+    /// let data = DCT::new("TEST".to_string(), 1, 0x1234, 1, vec![], vec![]).to_binary(EndianType::BIG);
+    /// assert!(matches!(DCT::try_from_binary_with_formats(data.clone(), EndianType::BIG, FORMATS), Err(DctError::UnsupportedDctVersion(1, 1))));
+    /// assert!(DCT::try_from_binary_with_formats(data, EndianType::BIG, &[]).is_ok());
+    /// assert!(matches!(DCT::try_from_binary_with_formats(b"PK\x03\x04 not a dct, but long enough".to_vec(), EndianType::BIG, FORMATS), Err(DctError::NotADct(_))));
+    /// ```
+    pub fn try_from_binary_with_formats(data: Vec<u8>, endian_type: file_manipulator::EndianType, formats: &[DctFormat]) -> Result<DCT, DctError> {
+        let mut fm = file_manipulator::FileManipulator::new(data, endian_type, file_manipulator::WriteMode::OVERWRITE);
+        let mut dct = DCT::new("".to_owned(), 0, 0, 0, Vec::new(), Vec::new());
+        dct.try_unpack(&mut fm, formats)?;
+        return Ok(dct);
+    }
+
+    pub fn from_binary_autodetect(data: Vec<u8>) -> DCT {
+        let endian_type = DCT::detect_endian(&data);
+        return DCT::from_binary(data, endian_type);
+    }
+
+    /// Measures the dialog lines in characters and lists the hashed keys of the ones longer than
+    /// `threshold`, to check translations against the space the game has for them.
+    pub fn text_length_stats(&self, threshold: usize) -> LengthStats {
+        let mut stats = LengthStats { min: 0, max: 0, mean: 0.0, histogram: std::collections::BTreeMap::new(), over_threshold: Vec::new() };
+        let mut total = 0;
+        let mut count = 0;
+        for dialog_entry in self.dialog_entries.iter().filter(|dialog_entry| !dialog_entry.is_empty()) {
+            let length = dialog_entry.text.chars().count();
+            stats.min = if count == 0 { length } else { stats.min.min(length) };
+            stats.max = stats.max.max(length);
+            *stats.histogram.entry(length).or_insert(0) += 1;
+            if length > threshold {
+                stats.over_threshold.push(dialog_entry.hashed_key);
+            }
+            total += length;
+            count += 1;
+        }
+        if count > 0 {
+            stats.mean = total as f64 / count as f64;
+        }
+        return stats;
+    }
+
+    /// Counts the entries and text bytes of a binary DCT without decoding its strings, which is
+    /// much faster than `from_binary` for summarizing many files. Panics if the data is truncated,
+    /// see `try_stats_from_binary`.
+    pub fn stats_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> DctStats {
+        return match DCT::try_stats_from_binary(data, endian_type) {
+            Ok(stats) => stats,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `stats_from_binary`, but returns `NotADct` instead of panicking when the entry table
+    /// or a string is past the end of the data.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::dct::{DCT, DctError, DialogEntry};
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// let mut data = DCT::new("DCT ".to_string(), 1, 0x1234, 2, vec![DialogEntry::new(1, "Hi".to_string())], vec![]).to_binary(EndianType::BIG);
+    /// assert_eq!(DCT::try_stats_from_binary(&data, EndianType::BIG).unwrap().text_bytes, 2);
+    /// // claim far more entries than there is data for
+    /// data[16] = 0x10;
+    /// assert!(matches!(DCT::try_stats_from_binary(&data, EndianType::BIG), Err(DctError::NotADct(_))));
+    /// ```
+    pub fn try_stats_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<DctStats, DctError> {
+        DCT::check_header(data, endian_type, &[])?;
+        return DCT::read_stats(data, endian_type).ok_or(DctError::NotADct(format!("a string or footer entry is past the end of the {} bytes", data.len())));
+    }
+
+    // the header has been checked, so only the strings and footer can be out of range
+    fn read_stats(data: &[u8], endian_type: file_manipulator::EndianType) -> Option<DctStats> {
+        let read_u32 = |offset: usize| file_manipulator::read_u32_at(data, offset, endian_type);
+        // length of the null-terminated string at a stored relative offset
+        let text_length = |offset_position: usize| -> Option<u64> {
+            let line_offset = (offset_position as u32 + 1).wrapping_add(read_u32(offset_position)?) as usize;
+            let text = data.get(line_offset..)?;
+            return Some(text.iter().position(|byte| *byte == 0).unwrap_or(text.len()) as u64);
+        };
+        let hash_seed = read_u32(8)?;
+        let dialog_entries = read_u32(16)?;
+        let footer_offset = (END_OFFSET_POSITION + END_OFFSET_BIAS).wrapping_add(read_u32(END_OFFSET_POSITION as usize)?) as usize;
+        let has_footer = read_u32(28)? == 1;
+        let mut stats = DctStats { dialog_entries, empty_entries: 0, footer_entries: 0, hash_seed, text_bytes: 0 };
+        let mut position = HEADER_SIZE as usize;
+        for _ in 0..dialog_entries {
+            if read_u32(position)? == 0 {
+                stats.empty_entries += 1;
+            } else {
+                stats.text_bytes += text_length(position + 4)?;
+            }
+            position += DIALOG_ENTRY_SIZE as usize;
+        }
+        if has_footer {
+            while position < footer_offset {
+                stats.text_bytes += text_length(position)?;
+                stats.footer_entries += 1;
+                position += FOOTER_ENTRY_SIZE as usize;
+            }
+        }
+        return Some(stats);
+    }
+
+    pub fn from_binary_path(path: String, endian_type: file_manipulator::EndianType) -> DCT {
         // check if file exists
         if !std::path::Path::new(&path).exists() {
             panic!("File does not exist!");
@@ -272,23 +762,181 @@ impl DCT {
         let mut file = File::open(path).unwrap();
         let mut data = Vec::new();
         file.read_to_end(&mut data).unwrap();
-        return DCT::from_binary(data);
+        return DCT::from_binary(data, endian_type);
     }
 
     pub fn from_dict(dict: serde_json::Value) -> DCT {
-        let magic = dict["magic"].as_str().unwrap().to_owned();
-        let version1 = dict["version1"].as_u64().unwrap() as u32;
-        let hash_seed = dict["hash_seed"].as_u64().unwrap() as u32;
-        let version2 = dict["version2"].as_u64().unwrap() as u32;
+        return DCT::from_dict_with_key_names(dict, &HashMap::new());
+    }
+
+    /// Like `from_dict`, but dialog entries may be written with only their plaintext `key` when it
+    /// is in `key_names`. Known names are also attached to entries that only give `hashed_key`.
+    pub fn from_dict_with_key_names(dict: serde_json::Value, key_names: &HashMap<u32, String>) -> DCT {
+        return match DCT::try_from_dict_with_key_names(dict, key_names) {
+            Ok(dct) => dct,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `from_dict_with_key_names`, but returns an error instead of panicking: `InvalidMagic`,
+    /// or `InvalidEntry` for a missing or mistyped field, an entry that can't be read or a
+    /// plaintext key that is not in `key_names`.
+    pub fn try_from_dict_with_key_names(dict: serde_json::Value, key_names: &HashMap<u32, String>) -> Result<DCT, DctError> {
+        let magic = dict["magic"].as_str().ok_or(DctError::InvalidEntry("magic is missing or not a string".to_string()))?.to_owned();
+        DCT::check_magic(&magic)?;
+        let header_field = |field: &str| -> Result<u32, DctError> {
+            return dict[field].as_u64().and_then(|value| u32::try_from(value).ok())
+                .ok_or(DctError::InvalidEntry(format!("{} is missing or not a u32", field)));
+        };
+        let version1 = header_field("version1")?;
+        let hash_seed = header_field("hash_seed")?;
+        let version2 = header_field("version2")?;
+        let dialog_dicts = dict["dialog_entries"].as_array().ok_or(DctError::InvalidEntry("dialog_entries is missing or not a list".to_string()))?;
         let mut dialog_entries = Vec::new();
-        for dialog_entry in dict["dialog_entries"].as_array().unwrap() {
-            dialog_entries.push(DialogEntry::from_dict(dialog_entry.clone()));
+        for (index, dialog_entry) in dialog_dicts.iter().enumerate() {
+            let dialog_entry = DialogEntry::try_from_dict_with_key_names(dialog_entry.clone(), key_names)
+                .map_err(|error| entry_error(format!("dialog_entries[{}]", index), error))?;
+            dialog_entries.push(dialog_entry);
         }
+        // a missing footer is an empty one
         let mut footer_entries = Vec::new();
-        for footer_entry in dict["footer_entries"].as_array().unwrap() {
-            footer_entries.push(FooterEntry::from_dict(footer_entry.clone()));
+        if let Some(footer_dicts) = dict["footer_entries"].as_array() {
+            for (index, footer_entry) in footer_dicts.iter().enumerate() {
+                let footer_entry = FooterEntry::try_from_dict(footer_entry.clone())
+                    .map_err(|error| entry_error(format!("footer_entries[{}]", index), error))?;
+                footer_entries.push(footer_entry);
+            }
         }
-        return DCT::new(magic, version1, hash_seed, version2, dialog_entries, footer_entries);
+        let mut dct = DCT::new(magic, version1, hash_seed, version2, dialog_entries, footer_entries);
+        dct.apply_key_names(key_names);
+        return Ok(dct);
+    }
+
+    /// Hashes a plaintext dialog key with this table's `hash_seed` using `hasher`.
+    pub fn hash_key(&self, key: &str, hasher: KeyHasher) -> u32 {
+        return hasher(self.hash_seed, key);
+    }
+
+    /// Hashes a list of candidate keys and returns every pair that would share a hashed key: two
+    /// different candidates with the same hash, and a candidate whose hash is already used by an
+    /// entry in the table. For the latter, the second string is the entry's plaintext key when it
+    /// is known, otherwise its hashed key in decimal. Candidates that are the known key of the entry
+    /// they hash to are not reported. A candidate that hashes to 0, which marks an empty slot, is
+    /// paired with "0".
+    pub fn verify_keys(&self, keys: &[String], hasher: KeyHasher) -> Vec<(String, String)> {
+        let mut collisions = Vec::new();
+        let mut candidates_by_hash: HashMap<u32, Vec<&String>> = HashMap::new();
+        let mut hashed_candidates = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for key in keys {
+            if !seen.insert(key) {
+                continue;
+            }
+            let hashed_key = self.hash_key(key, hasher);
+            let candidates = candidates_by_hash.entry(hashed_key).or_default();
+            for candidate in candidates.iter() {
+                collisions.push(((*candidate).clone(), key.clone()));
+            }
+            candidates.push(key);
+            hashed_candidates.push((hashed_key, key));
+        }
+        let mut entries_by_hash: HashMap<u32, Vec<&DialogEntry>> = HashMap::new();
+        for dialog_entry in &self.dialog_entries {
+            entries_by_hash.entry(dialog_entry.hashed_key).or_default().push(dialog_entry);
+        }
+        for (hashed_key, key) in hashed_candidates {
+            if hashed_key == 0 {
+                // a zero hash would be read as an empty slot
+                collisions.push((key.clone(), "0".to_string()));
+                continue;
+            }
+            for dialog_entry in entries_by_hash.get(&hashed_key).into_iter().flatten() {
+                if dialog_entry.key.as_ref() == Some(key) {
+                    continue;
+                }
+                let existing = dialog_entry.key.clone().unwrap_or(dialog_entry.hashed_key.to_string());
+                collisions.push((key.clone(), existing));
+            }
+        }
+        return collisions;
+    }
+
+    /// Attaches known plaintext keys to the dialog entries with matching hashes, so they are
+    /// written to JSON next to `hashed_key`. Entries that already have a key are left alone.
+    /// Returns how many entries got a key.
+    pub fn apply_key_names(&mut self, key_names: &HashMap<u32, String>) -> usize {
+        let mut applied = 0;
+        for dialog_entry in &mut self.dialog_entries {
+            if dialog_entry.is_empty() || dialog_entry.key.is_some() {
+                continue;
+            }
+            if let Some(name) = key_names.get(&dialog_entry.hashed_key) {
+                dialog_entry.key = Some(name.clone());
+                applied += 1;
+            }
+        }
+        return applied;
+    }
+
+    /// Returns the known plaintext keys of the dialog entries, to save as a key list.
+    pub fn key_names(&self) -> HashMap<u32, String> {
+        let mut key_names = HashMap::new();
+        for dialog_entry in &self.dialog_entries {
+            if let Some(key) = &dialog_entry.key {
+                key_names.insert(dialog_entry.hashed_key, key.clone());
+            }
+        }
+        return key_names;
+    }
+
+    /// Sets the text of the dialog entry whose plaintext key is `key`. Keys are only known after
+    /// `apply_key_names` or from JSON, so returns false if no entry has this key.
+    pub fn set_line_from_key(&mut self, key: &str, text: String) -> bool {
+        for dialog_entry in &mut self.dialog_entries {
+            if dialog_entry.key.as_deref() == Some(key) {
+                dialog_entry.text = text;
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Hashes `key` with this table's `hash_seed` and sets the text of the entry with that hash,
+    /// appending a new entry if there is none. The entry's plaintext key is set to `key` either
+    /// way. Fails without changing anything if the seed is 0 or if the key hashes to 0.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::dct::{DCT, DctError, UpsertOutcome};
+    /// // a stand-in, not the game's algorithm
+    /// let hasher = |seed: u32, key: &str| key.bytes().fold(seed, |hash, byte| hash.wrapping_mul(31) ^ byte as u32);
+    /// let mut dct = DCT::new("DCT ".to_string(), 1, 0x1234, 2, vec![], vec![]);
+    /// assert_eq!(dct.upsert("HUB_GUS_01", "Hello!", hasher).unwrap(), UpsertOutcome::Inserted);
+    /// assert_eq!(dct.upsert("HUB_GUS_01", "Hello again!", hasher).unwrap(), UpsertOutcome::Updated);
+    /// assert_eq!(dct.dialog_entries.len(), 1);
+    /// assert_eq!(dct.dialog_entries[0].text, "Hello again!");
+    /// 
+    /// dct.hash_seed = 0;
+    /// assert!(matches!(dct.upsert("HUB_GUS_02", "Bye!", hasher), Err(DctError::ZeroHashSeed)));
+    /// ```
+    pub fn upsert(&mut self, key: &str, text: &str, hasher: KeyHasher) -> Result<UpsertOutcome, DctError> {
+        if self.hash_seed == 0 {
+            return Err(DctError::ZeroHashSeed);
+        }
+        let hashed_key = self.hash_key(key, hasher);
+        if hashed_key == 0 {
+            return Err(DctError::EmptySlotKey(key.to_string()));
+        }
+        for dialog_entry in &mut self.dialog_entries {
+            if dialog_entry.hashed_key == hashed_key {
+                dialog_entry.key = Some(key.to_string());
+                dialog_entry.text = text.to_string();
+                return Ok(UpsertOutcome::Updated);
+            }
+        }
+        let mut dialog_entry = DialogEntry::new(hashed_key, text.to_string());
+        dialog_entry.key = Some(key.to_string());
+        self.dialog_entries.push(dialog_entry);
+        return Ok(UpsertOutcome::Inserted);
     }
 
     pub fn set_line_from_hash(&mut self, hashed_key: u32, text: String) {
@@ -305,7 +953,110 @@ impl DCT {
         self.dialog_entries.push(DialogEntry::new(hashed_key, text));
     }
 
+    /// Removes the first dialog entry with the given hashed key. Returns whether an entry was removed.
+    pub fn remove_line(&mut self, hashed_key: u32) -> bool {
+        // empty slots are not addressable by key
+        if hashed_key == 0 {
+            return false;
+        }
+        match self.dialog_entries.iter().position(|dialog_entry| dialog_entry.hashed_key == hashed_key) {
+            Some(index) => {
+                self.dialog_entries.remove(index);
+                return true;
+            },
+            None => return false
+        }
+    }
+
+    /// Removes the first footer entry with the given number. Returns whether an entry was removed.
+    pub fn remove_footer(&mut self, number: u32) -> bool {
+        match self.footer_entries.iter().position(|footer_entry| footer_entry.number == number) {
+            Some(index) => {
+                self.footer_entries.remove(index);
+                return true;
+            },
+            None => return false
+        }
+    }
+
+    /// Removes every footer entry. The file is then packed without a footer or trailer.
+    pub fn clear_footer(&mut self) {
+        self.footer_entries.clear();
+    }
+
+    /// Replaces the footer entries, leaving the dialog entries as they are.
+    pub fn set_footer(&mut self, footer_entries: Vec<FooterEntry>) {
+        self.footer_entries = footer_entries;
+    }
+
+    /// Sorts the dialog entries by hashed key. Empty (zero key) slots are kept and moved to the end.
+    pub fn sort_entries(&mut self) {
+        self.dialog_entries.sort_by_key(|dialog_entry| (dialog_entry.hashed_key == 0, dialog_entry.hashed_key));
+    }
+
+    /// Returns every non-zero hashed key that appears more than once, in order of first repeat.
+    pub fn find_duplicate_keys(&self) -> Vec<u32> {
+        let mut seen = std::collections::HashSet::new();
+        let mut duplicates = Vec::new();
+        for dialog_entry in &self.dialog_entries {
+            if dialog_entry.is_empty() {
+                continue;
+            }
+            if !seen.insert(dialog_entry.hashed_key) && !duplicates.contains(&dialog_entry.hashed_key) {
+                duplicates.push(dialog_entry.hashed_key);
+            }
+        }
+        return duplicates;
+    }
+
+    /// Merges a dict like the one `to_dict` writes into the table. Entries with a known hashed key
+    /// get the new text in place. New entries take the slot they have in the dict when it is empty
+    /// or past the end, and go at the end otherwise. Empty slots in the dict only extend the table.
+    /// Panics if the magic or an entry is invalid, see `merge_in_dict_with_policy`.
     pub fn merge_in_dict(&mut self, dict: serde_json::Value) {
+        if let Err(error) = self.merge_in_dict_with_policy(dict, DuplicateKeyPolicy::KeepLast) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Like `merge_in_dict`, with `policy` deciding what happens to an incoming entry whose hashed
+    /// key is already in the table. Returns `InvalidMagic`, `InvalidEntry` (for an entry with a
+    /// missing or mistyped field) or, with `DuplicateKeyPolicy::Error`, `DuplicateKey`, and leaves
+    /// the table unchanged when it does.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::dct::{DCT, DctError, DuplicateKeyPolicy};
+    /// let mut dct = DCT::new("DCT ".to_string(), 1, 0x1234, 2, vec![], vec![]);
+    /// let dict = serde_json::json!({ "dialog_entries": [{ "hashed_key": 1, "text": "Hi" }, { "hashed_key": 2, "text": 3 }] });
+    /// let error = dct.merge_in_dict_with_policy(dict, DuplicateKeyPolicy::KeepLast).unwrap_err();
+    /// assert!(matches!(&error, DctError::InvalidEntry(reason) if reason.starts_with("dialog_entries[1]: ")));
+    /// assert!(dct.dialog_entries.is_empty());
+    /// ```
+    pub fn merge_in_dict_with_policy(&mut self, dict: serde_json::Value, policy: DuplicateKeyPolicy) -> Result<(), DctError> {
+        if let Some(magic) = dict["magic"].as_str() {
+            DCT::check_magic(magic)?;
+        }
+        // read every entry and check for collisions up front so a failed merge leaves the table untouched
+        let mut dialog_entries = Vec::new();
+        for (index, dialog_entry) in dict["dialog_entries"].as_array().into_iter().flatten().enumerate() {
+            let dialog_entry = DialogEntry::try_from_dict(dialog_entry.clone())
+                .map_err(|error| entry_error(format!("dialog_entries[{}]", index), error))?;
+            dialog_entries.push(dialog_entry);
+        }
+        let mut footer_entries = Vec::new();
+        for (index, footer_entry) in dict["footer_entries"].as_array().into_iter().flatten().enumerate() {
+            let footer_entry = FooterEntry::try_from_dict(footer_entry.clone())
+                .map_err(|error| entry_error(format!("footer_entries[{}]", index), error))?;
+            footer_entries.push(footer_entry);
+        }
+        if policy == DuplicateKeyPolicy::Error {
+            let mut keys: std::collections::HashSet<u32> = self.dialog_entries.iter().map(|dialog_entry| dialog_entry.hashed_key).collect();
+            for dialog_entry in &dialog_entries {
+                if !dialog_entry.is_empty() && !keys.insert(dialog_entry.hashed_key) {
+                    return Err(DctError::DuplicateKey(dialog_entry.hashed_key));
+                }
+            }
+        }
         // if there is a magic, overwrite it
         if dict["magic"].is_string() {
             self.magic = dict["magic"].as_str().unwrap().to_owned();
@@ -323,20 +1074,41 @@ impl DCT {
             self.version2 = dict["version2"].as_u64().unwrap() as u32;
         }
         // if there are dialog_entries, overwrite any existing ones, or add new ones
-        if dict["dialog_entries"].is_array() {
-            for dialog_entry in dict["dialog_entries"].as_array().unwrap() {
-                let hashed_key = dialog_entry["hashed_key"].as_u64().unwrap() as u32;
-                let text = dialog_entry["text"].as_str().unwrap().to_owned();
-                self.set_line_from_hash(hashed_key, text);
+        for (index, dialog_entry) in dialog_entries.into_iter().enumerate() {
+            // empty slots are positional, so they only extend the table up to their index
+            if dialog_entry.is_empty() {
+                self.pad_to_slot(index + 1);
+                continue;
+            }
+            match self.dialog_entries.iter().position(|self_entry| self_entry.hashed_key == dialog_entry.hashed_key) {
+                Some(_) if policy == DuplicateKeyPolicy::KeepFirst => {},
+                Some(position) => {
+                    let self_entry = &mut self.dialog_entries[position];
+                    self_entry.text = dialog_entry.text;
+                    // keep a plaintext key that came with the merged entry
+                    if dialog_entry.key.is_some() {
+                        self_entry.key = dialog_entry.key;
+                    }
+                },
+                // a new entry goes in the same slot as in the dict, or at the end if that slot is taken
+                None => {
+                    self.pad_to_slot(index);
+                    match self.dialog_entries.get(index) {
+                        Some(self_entry) if self_entry.is_empty() => self.dialog_entries[index] = dialog_entry,
+                        _ => self.dialog_entries.push(dialog_entry)
+                    }
+                }
             }
         }
         // if there are footer_entries, append them
-        if dict["footer_entries"].is_array() {
-            for footer_entry in dict["footer_entries"].as_array().unwrap() {
-                let number = footer_entry["number"].as_u64().unwrap() as u32;
-                let text = footer_entry["text"].as_str().unwrap().to_owned();
-                self.footer_entries.push(FooterEntry::new(number, text));
-            }
+        self.footer_entries.extend(footer_entries);
+        return Ok(());
+    }
+
+    // add empty slots until the table has `index` entries
+    fn pad_to_slot(&mut self, index: usize) {
+        while self.dialog_entries.len() < index {
+            self.dialog_entries.push(DialogEntry::empty());
         }
     }
 
@@ -371,4 +1143,306 @@ impl DCT {
         file.read_to_string(&mut json).unwrap();
         return DCT::from_json(json);
     }
-}
\ No newline at end of file
+
+    /// Exports the entries as CSV. Dialog entries come first under a `hashed_key,text` header,
+    /// followed by a blank line and the footer entries under a `number,text` header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        csv.push_str("hashed_key,text\n");
+        for dialog_entry in &self.dialog_entries {
+            csv.push_str(&format!("{},{}\n", dialog_entry.hashed_key, csv_escape(&dialog_entry.text)));
+        }
+        if !self.footer_entries.is_empty() {
+            csv.push_str("\nnumber,text\n");
+            for footer_entry in &self.footer_entries {
+                csv.push_str(&format!("{},{}\n", footer_entry.number, csv_escape(&footer_entry.text)));
+            }
+        }
+        return csv;
+    }
+
+    pub fn to_csv_path(&self, path: String) {
+        let mut file = File::create(path).unwrap();
+        file.write_all(self.to_csv().as_bytes()).unwrap();
+    }
+
+    /// Builds a DCT from CSV in the layout written by `to_csv`. The header fields are not part of the CSV and must be supplied.
+    /// Panics if a row is invalid, see `try_from_csv`.
+    pub fn from_csv(csv: &str, magic: String, version1: u32, hash_seed: u32, version2: u32) -> DCT {
+        return match DCT::try_from_csv(csv, magic, version1, hash_seed, version2) {
+            Ok(dct) => dct,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `from_csv`, but returns `InvalidCsv` with the number of the row (counted from 1, a
+    /// quoted field with line breaks is one row) instead of panicking when a row doesn't have
+    /// two fields or doesn't start with a number.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::dct::{DCT, DctError};
+    /// let csv = "hashed_key,text\n1,Hello\n\"2\",\"Two\nlines\"\nthree,Bye\n";
+    /// let error = DCT::try_from_csv(csv, "DCT ".to_string(), 1, 0x1234, 2).err().unwrap();
+    /// assert!(matches!(error, DctError::InvalidCsv { row: 4, .. }));
+    /// ```
+    pub fn try_from_csv(csv: &str, magic: String, version1: u32, hash_seed: u32, version2: u32) -> Result<DCT, DctError> {
+        let mut dialog_entries = Vec::new();
+        let mut footer_entries = Vec::new();
+        let mut in_footer = false;
+        for (index, row) in csv_parse_rows(csv).into_iter().enumerate() {
+            // skip blank lines
+            if row.len() == 1 && row[0].is_empty() {
+                continue;
+            }
+            if row.len() != 2 {
+                return Err(DctError::InvalidCsv { row: index + 1, reason: format!("expected 2 fields, found {}", row.len()) });
+            }
+            if row[0] == "hashed_key" {
+                in_footer = false;
+                continue;
+            }
+            if row[0] == "number" {
+                in_footer = true;
+                continue;
+            }
+            let number = match row[0].trim().parse::<u32>() {
+                Ok(number) => number,
+                Err(_) => return Err(DctError::InvalidCsv { row: index + 1, reason: format!("\"{}\" is not a number", row[0]) })
+            };
+            if in_footer {
+                footer_entries.push(FooterEntry::new(number, row[1].clone()));
+            } else {
+                dialog_entries.push(DialogEntry::new(number, row[1].clone()));
+            }
+        }
+        return Ok(DCT::new(magic, version1, hash_seed, version2, dialog_entries, footer_entries));
+    }
+
+    pub fn from_csv_path(path: String, magic: String, version1: u32, hash_seed: u32, version2: u32) -> DCT {
+        // check if file exists
+        if !std::path::Path::new(&path).exists() {
+            panic!("File does not exist!");
+        }
+        let mut file = File::open(path).unwrap();
+        let mut csv = String::new();
+        file.read_to_string(&mut csv).unwrap();
+        return DCT::from_csv(&csv, magic, version1, hash_seed, version2);
+    }
+}
+
+/// Parses a binary DCT and writes it back, returning the offset of the first byte where the
+/// output differs from `data`. See `asset::assert_roundtrip`.
+pub fn assert_roundtrip(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<(), usize> {
+    return asset::assert_roundtrip::<DCT>(data, endian_type);
+}
+
+// quote a CSV field if it contains a comma, quote or line break
+fn csv_escape(text: &str) -> String {
+    if text.contains(',') || text.contains('"') || text.contains('\n') || text.contains('\r') {
+        return format!("\"{}\"", text.replace('"', "\"\""));
+    }
+    return text.to_owned();
+}
+
+// split CSV into rows of fields, honouring quoted fields that span lines
+fn csv_parse_rows(csv: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {},
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            _ => field.push(c)
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    return rows;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_manipulator::EndianType;
+
+    // a little endian DCT written out field by field: three dialog slots (the middle one empty)
+    // and one footer entry
+    const FIXTURE: [u8; 101] = [
+        // magic, version1, hash_seed, version2
+        b'D', b'C', b'T', b' ', 1, 0, 0, 0, 0x34, 0x12, 0, 0, 2, 0, 0, 0,
+        // dialog entry count, 1, end offset (76 - 33), footer switch
+        3, 0, 0, 0, 1, 0, 0, 0, 43, 0, 0, 0, 1, 0, 0, 0,
+        // "Hi" at 93, relative to the offset field at 36 minus one
+        0x11, 0x11, 0x11, 0x11, 56, 0, 0, 0, 0, 0, 0, 0,
+        // empty slot
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        // "Yo" at 96
+        0x22, 0x22, 0x22, 0x22, 35, 0, 0, 0, 0, 0, 0, 0,
+        // footer "F" at 99, number 7
+        30, 0, 0, 0, 7, 0, 0, 0,
+        // trailer
+        0xDF, 0xFF, 0xFF, 0xFF, 11, 0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0,
+        // one byte before the strings
+        0,
+        b'H', b'i', 0, b'Y', b'o', 0, b'F', 0,
+    ];
+
+    #[test]
+    fn fixture_round_trips_byte_for_byte() {
+        let dct = DCT::try_from_binary(FIXTURE.to_vec(), EndianType::LITTLE).unwrap();
+        assert_eq!((dct.magic.as_str(), dct.version1, dct.hash_seed, dct.version2), ("DCT ", 1, 0x1234, 2));
+        let texts: Vec<(u32, &str)> = dct.dialog_entries.iter().map(|entry| (entry.hashed_key, entry.text.as_str())).collect();
+        assert_eq!(texts, [(0x11111111, "Hi"), (0, ""), (0x22222222, "Yo")]);
+        assert_eq!((dct.footer_entries[0].number, dct.footer_entries[0].text.as_str()), (7, "F"));
+        assert_eq!(dct.pack(EndianType::LITTLE), FIXTURE);
+    }
+
+    // the FIXTURE entries without the footer, laid out the way the original packer did: the
+    // strings start 17 bytes past the entry table even though there is no trailer there
+    const NO_FOOTER_FIXTURE: [u8; 91] = [
+        b'D', b'C', b'T', b' ', 1, 0, 0, 0, 0x34, 0x12, 0, 0, 2, 0, 0, 0,
+        // dialog entry count, 1, end offset (68 - 33), footer switch
+        3, 0, 0, 0, 1, 0, 0, 0, 35, 0, 0, 0, 0, 0, 0, 0,
+        // "Hi" at 85
+        0x11, 0x11, 0x11, 0x11, 48, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        // "Yo" at 88
+        0x22, 0x22, 0x22, 0x22, 27, 0, 0, 0, 0, 0, 0, 0,
+        // the unused space for a trailer, and the byte before the strings
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        b'H', b'i', 0, b'Y', b'o', 0,
+    ];
+
+    #[test]
+    fn packs_without_a_footer_in_the_original_layout() {
+        let mut dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        dct.footer_entries.clear();
+        assert_eq!(dct.pack(EndianType::LITTLE), NO_FOOTER_FIXTURE);
+        assert_eq!(DCT::from_binary(NO_FOOTER_FIXTURE.to_vec(), EndianType::LITTLE).pack(EndianType::LITTLE), NO_FOOTER_FIXTURE);
+    }
+
+    #[test]
+    fn strings_never_overlap_the_tables() {
+        for count in 0..40u32 {
+            for with_footer in [false, true] {
+                let dialog_entries = (0..count).map(|i| match i % 5 {
+                    4 => DialogEntry::empty(),
+                    _ => DialogEntry::new(i + 1, "line".repeat(i as usize % 3))
+                }).collect();
+                let footer_entries = if with_footer { (0..count % 4 + 1).map(|i| FooterEntry::new(i, "footer".to_string())).collect() } else { Vec::new() };
+                let dct = DCT::new("DCT ".to_string(), 1, 1, 2, dialog_entries, footer_entries);
+                let data = dct.pack(EndianType::BIG);
+                let footer_count = dct.footer_entries.len() as u32;
+                let mut table_end = HEADER_SIZE + count * DIALOG_ENTRY_SIZE + footer_count * FOOTER_ENTRY_SIZE;
+                if with_footer {
+                    table_end += FOOTER_TRAILER_SIZE;
+                    assert_eq!(&data[table_end as usize - FOOTER_TRAILER_SIZE as usize..][..4], FOOTER_TRAILER_MARKER);
+                }
+                let read_u32 = |offset: u32| file_manipulator::read_u32_at(&data, offset as usize, EndianType::BIG).unwrap();
+                let dialog_offsets = (0..count).map(|i| HEADER_SIZE + i * DIALOG_ENTRY_SIZE).filter(|position| read_u32(*position) != 0).map(|position| position + 4);
+                let footer_offsets = (0..footer_count).map(|i| HEADER_SIZE + count * DIALOG_ENTRY_SIZE + i * FOOTER_ENTRY_SIZE);
+                for offset_position in dialog_offsets.chain(footer_offsets) {
+                    let line_offset = offset_position + 1 + read_u32(offset_position);
+                    assert!(line_offset > table_end, "{} entries: a string at {} is inside the tables, which end at {}", count, line_offset, table_end);
+                }
+                let read = DCT::from_binary(data.clone(), EndianType::BIG);
+                assert_eq!(read.pack(EndianType::BIG), data);
+            }
+        }
+    }
+
+    #[test]
+    fn fixture_round_trips_big_endian() {
+        let dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        let data = dct.pack(EndianType::BIG);
+        assert!(DCT::detect_endian(&data) == EndianType::BIG);
+        assert_eq!(DCT::from_binary(data.clone(), EndianType::BIG).pack(EndianType::BIG), data);
+        assert_eq!(DCT::from_binary(data, EndianType::BIG).pack(EndianType::LITTLE), FIXTURE);
+    }
+
+    #[test]
+    fn stats_match_the_fixture() {
+        let stats = DCT::stats_from_binary(&FIXTURE, EndianType::LITTLE);
+        assert_eq!(stats, DctStats { dialog_entries: 3, empty_entries: 1, footer_entries: 1, hash_seed: 0x1234, text_bytes: 5 });
+    }
+
+    #[test]
+    fn interior_empty_slots_round_trip_through_json() {
+        let dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        let json = dct.to_json();
+        assert!(json.contains("\"empty\": true"));
+        assert_eq!(DCT::from_json(json).pack(EndianType::LITTLE), FIXTURE);
+    }
+
+    fn keys(dct: &DCT) -> Vec<u32> {
+        return dct.dialog_entries.iter().map(|entry| entry.hashed_key).collect();
+    }
+
+    #[test]
+    fn merge_keeps_slot_indices() {
+        let mut dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        // a new key in the empty slot fills it instead of going to the end
+        dct.merge_in_dict(serde_json::json!({ "dialog_entries": [{ "empty": true }, { "hashed_key": 5, "text": "New" }] }));
+        assert_eq!(keys(&dct), [0x11111111, 5, 0x22222222]);
+
+        // a new key past the end is padded out to its slot
+        let mut dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        dct.merge_in_dict(serde_json::json!({ "dialog_entries": [{ "empty": true }, { "empty": true }, { "empty": true }, { "empty": true }, { "hashed_key": 6, "text": "Far" }] }));
+        assert_eq!(keys(&dct), [0x11111111, 0, 0x22222222, 0, 6]);
+
+        // an existing key is updated where it is, and the empty slot stays
+        let mut dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        dct.merge_in_dict(serde_json::json!({ "dialog_entries": [{ "hashed_key": 0x22222222u32, "text": "Hey" }] }));
+        assert_eq!(keys(&dct), [0x11111111, 0, 0x22222222]);
+        assert_eq!(dct.dialog_entries[2].text, "Hey");
+
+        // merging a file into itself changes nothing
+        let mut dct = DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE);
+        dct.merge_in_dict(DCT::from_binary(FIXTURE.to_vec(), EndianType::LITTLE).to_dict());
+        dct.footer_entries.truncate(1);
+        assert_eq!(dct.pack(EndianType::LITTLE), FIXTURE);
+    }
+
+    // a stand-in for the game's hash that makes collisions easy to build: the sum of the bytes
+    fn byte_sum(seed: u32, key: &str) -> u32 {
+        return key.bytes().fold(seed, |hash, byte| hash.wrapping_add(byte as u32));
+    }
+
+    #[test]
+    fn verify_keys_reports_every_collision() {
+        let mut dct = DCT::new("DCT ".to_string(), 1, 1, 2, vec![], vec![]);
+        dct.upsert("AB", "known", byte_sum).unwrap();
+        dct.dialog_entries.push(DialogEntry::new(byte_sum(1, "CD"), "unnamed".to_string()));
+        let keys: Vec<String> = ["BA", "AB", "AC", "CA", "BA", "DC"].iter().map(|key| key.to_string()).collect();
+        let collisions = dct.verify_keys(&keys, byte_sum);
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        let unnamed = byte_sum(1, "CD").to_string();
+        assert_eq!(collisions, [
+            pair("BA", "AB"), pair("AC", "CA"),
+            pair("BA", "AB"), pair("DC", &unnamed)
+        ]);
+    }
+}
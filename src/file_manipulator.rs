@@ -11,15 +11,49 @@ pub enum EndianType {
     LITTLE,
 }
 
+/// What `write` does with the bytes at the current position.
+/// 
+/// * `OVERWRITE` - replace them, growing the data if the write runs past the end
+/// * `INSERT` - shift them back to make room. Every write moves the whole tail of the data, so
+///   building a file out of many small inserts is quadratic. Pack in OVERWRITE mode, or collect the
+///   inserts and apply them together with `splice_region`.
 #[derive(Clone, Copy, PartialEq)]
 pub enum WriteMode {
     OVERWRITE,
     INSERT,
 }
 
+/// How `w_bool` stores a bool in its four bytes. `r_bool` reads either form.
+/// 
+/// * `AllBytes` - true is `FF FF FF FF`, false is `00 00 00 00`
+/// * `LeastSignificantByte` - the bool is written as a u32 of 1 or 0 in the current endian
+#[derive(Clone, Copy, PartialEq)]
+pub enum BoolRepresentation {
+    AllBytes,
+    LeastSignificantByte,
+}
+
+/// How the string readers decode text, and how the writers encode it.
+/// 
+/// * `Utf8` - text must be valid UTF-8, anything else panics
+/// * `Utf8Lossy` - invalid UTF-8 is replaced with U+FFFD, text is written as UTF-8
+/// * `Windows1252` - every byte is one Windows-1252 character (a superset of Latin-1), and text is
+///   written the same way. Writing a character Windows-1252 has no byte for panics.
+/// * `Utf8OrWindows1252` - valid UTF-8 is read as UTF-8, anything else as Windows-1252. Text is
+///   written as UTF-8, so a string that was read as Windows-1252 doesn't keep its bytes.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StringEncoding {
+    Utf8,
+    Utf8Lossy,
+    Windows1252,
+    Utf8OrWindows1252,
+}
+
 pub struct FileManipulator {
     pub endian: EndianType,
     pub write_mode: WriteMode,
+    pub bool_representation: BoolRepresentation,
+    pub string_encoding: StringEncoding,
     pub data: Vec<u8>,
     pub pos: usize,
 }
@@ -29,6 +63,8 @@ impl FileManipulator {
         FileManipulator {
             endian,
             write_mode,
+            bool_representation: BoolRepresentation::AllBytes,
+            string_encoding: StringEncoding::Utf8,
             data,
             pos: 0,
         }
@@ -63,14 +99,43 @@ impl FileManipulator {
             }
             WriteMode::INSERT => {
                 let pos = self.pos;
-                let mut data = self.data.split_off(pos);
-                self.data.extend_from_slice(buffer);
-                self.data.append(&mut data);
+                self.data.splice(pos..pos, buffer.iter().copied());
                 self.move_pos(buffer.len() as isize);
             }
         }
     }
 
+    /// Inserts several buffers in one pass, each at an offset into the data as it is before the
+    /// call. Buffers with the same offset keep their order. The position moves past any bytes
+    /// inserted at or before it, like an INSERT mode `write` does. Works in either write mode.
+    pub fn splice_region(&mut self, inserts: &[(usize, &[u8])]) {
+        let mut inserts = inserts.to_vec();
+        inserts.sort_by_key(|(offset, _)| *offset);
+        let inserted: usize = inserts.iter().map(|(_, buffer)| buffer.len()).sum();
+        let mut data = Vec::with_capacity(self.data.len() + inserted);
+        let mut copied = 0;
+        let mut pos = self.pos;
+        for (offset, buffer) in inserts {
+            if offset > self.data.len() {
+                panic!("Invalid insert offset: {}", offset);
+            }
+            data.extend_from_slice(&self.data[copied..offset]);
+            data.extend_from_slice(buffer);
+            copied = offset;
+            if offset <= self.pos {
+                pos += buffer.len();
+            }
+        }
+        data.extend_from_slice(&self.data[copied..]);
+        self.data = data;
+        self.pos = pos;
+    }
+
+    /// Reserves room for at least `additional` more bytes, to avoid reallocating while writing.
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
     pub fn get_struct_order_prefix(&self) -> &str {
         match self.endian {
             EndianType::BIG => ">",
@@ -191,23 +256,28 @@ impl FileManipulator {
     }
 
     pub fn r_str(&mut self, length: usize) -> String {
+        let start = self.pos;
         let mut buffer = Vec::new();
         for _ in 0..length {
             let byte = self.read_byte();
             buffer.push(byte);
         }
-        return String::from_utf8(buffer).unwrap()
+        return self.decode_str(buffer, start)
     }
 
     pub fn r_str_jps(&mut self) -> String {
-        let size = self.r_u8();
-        let text_length = self.r_u8();
+        let start = self.pos;
+        let _size = self.r_u8();
+        let _text_length = self.r_u8();
         let text = self.r_str_null();
-        self.align(4);
+        // skip the padding the same way w_str_jps writes it, relative to the start of the string
+        let end = start + jps_string_size(text.len());
+        self.move_pos(end as isize - self.pos as isize);
         return text
     }
 
     pub fn r_str_null(&mut self) -> String {
+        let start = self.pos;
         let mut buffer = Vec::new();
         loop {
             let byte = self.read_byte();
@@ -216,17 +286,50 @@ impl FileManipulator {
             }
             buffer.push(byte);
         }
-        return String::from_utf8(buffer).unwrap()
+        return self.decode_str(buffer, start)
     }
 
-    pub fn r_bool(&mut self) -> bool {
-        // if its FF FF FF FF, return true, else return false
-        let mut buffer = Vec::new();
-        for _ in 0..4 {
-            let byte = self.read_byte();
-            buffer.push(byte);
+    /// Reads a null terminated string, replacing invalid UTF-8 with U+FFFD whatever the
+    /// `string_encoding` is.
+    pub fn r_str_null_lossy(&mut self) -> String {
+        let encoding = self.string_encoding;
+        self.string_encoding = StringEncoding::Utf8Lossy;
+        let text = self.r_str_null();
+        self.string_encoding = encoding;
+        return text
+    }
+
+    // decode the bytes of a string read from `start`
+    fn decode_str(&self, buffer: Vec<u8>, start: usize) -> String {
+        match self.string_encoding {
+            StringEncoding::Utf8 => match String::from_utf8(buffer) {
+                Ok(text) => return text,
+                Err(error) => {
+                    panic!("Invalid UTF-8 in the string at {}: {}", start, error);
+                }
+            },
+            StringEncoding::Utf8Lossy => return String::from_utf8_lossy(&buffer).into_owned(),
+            StringEncoding::Windows1252 => return decode_windows_1252(&buffer),
+            StringEncoding::Utf8OrWindows1252 => match String::from_utf8(buffer) {
+                Ok(text) => return text,
+                Err(error) => return decode_windows_1252(error.as_bytes())
+            }
+        }
+    }
+
+    fn encode_str(&self, text: &str) -> Vec<u8> {
+        if self.string_encoding == StringEncoding::Windows1252 {
+            return encode_windows_1252(text);
         }
-        return buffer == vec![255, 255, 255, 255]
+        return text.as_bytes().to_vec()
+    }
+
+    /// Reads a four byte bool. Any nonzero value is true, so both `FF FF FF FF` and `01 00 00 00`
+    /// style bools read correctly in either endian.
+    pub fn r_bool(&mut self) -> bool {
+        let mut buffer = [0; 4];
+        self.read(&mut buffer);
+        return buffer != [0, 0, 0, 0]
     }
 
     pub fn w_u8(&mut self, data: u8) {
@@ -342,43 +445,93 @@ impl FileManipulator {
     }
 
     pub fn w_str(&mut self, text: &str) {
-        self.write(text.as_bytes());
+        let bytes = self.encode_str(text);
+        self.write(&bytes);
     }
 
+    /// Writes a JPS string: a size byte, a length byte, the text and a null terminator, padded with
+    /// zeros to a multiple of 4 bytes. The length counts the terminator, except for an empty
+    /// string, whose length is 0 (it still takes 4 bytes).
+    /// 
+    /// The size is a single byte, so the text can be at most `MAX_JPS_STRING_LENGTH` (249) bytes
+    /// long. Longer text panics instead of writing a corrupt size.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::{FileManipulator, EndianType, WriteMode};
+    /// let mut fm = FileManipulator::new(Vec::new(), EndianType::BIG, WriteMode::OVERWRITE);
+    /// fm.w_str_jps("");
+    /// fm.w_u32(7);
+    /// assert_eq!(fm.get_data(), &vec![4, 0, 0, 0, 0, 0, 0, 7]);
+    /// fm.seek(0);
+    /// assert_eq!(fm.r_str_jps(), "");
+    /// assert_eq!(fm.r_u32(), 7);
+    /// ```
+    /// 
+    /// ```should_panic
+    /// # use epic_mickey_lib_rs::file_manipulator::{FileManipulator, EndianType, WriteMode};
+    /// let mut fm = FileManipulator::new(Vec::new(), EndianType::BIG, WriteMode::OVERWRITE);
+    /// fm.w_str_jps(&"a".repeat(300));
+    /// ```
     pub fn w_str_jps(&mut self, text: &str) {
-        let mut text_length = text.len();
+        let bytes = self.encode_str(text);
+        if bytes.len() > MAX_JPS_STRING_LENGTH {
+            panic!("JPS string is {} bytes long, the most that fits is {}: {}", bytes.len(), MAX_JPS_STRING_LENGTH, text);
+        }
+        let mut text_length = bytes.len();
         if text_length > 0 {
             text_length += 1;
         }
-        let mut size = text_length + 2;
-        while (size % 4) != 0 {
-            size += 1;
-        }
+        let size = jps_string_size(bytes.len());
         self.w_u8(size as u8);
         self.w_u8(text_length as u8);
-        self.w_str_null(text);
-        
-        let mut padding = 1;
-        if text_length > 0 {
-            padding = size - text_length - 2;
-        }
-        for _ in 0..padding {
-            self.write_byte(0);
-        }
+        self.write(&bytes);
+        // the null terminator and the padding after it
+        let padding = size - 2 - bytes.len();
+        self.write(&vec![0; padding]);
     }
 
     pub fn w_str_null(&mut self, text: &str) {
-        self.write(text.as_bytes());
+        let bytes = self.encode_str(text);
+        self.write(&bytes);
         self.write_byte(0);
     }
 
     pub fn w_bool(&mut self, value: bool) {
-        let num = if value { 255 } else { 0 };
-        for _ in 0..4 {
-            self.write_byte(num);
+        match self.bool_representation {
+            BoolRepresentation::AllBytes => {
+                let num = if value { 255 } else { 0 };
+                self.write(&[num; 4]);
+            }
+            BoolRepresentation::LeastSignificantByte => {
+                self.w_u32(value as u32);
+            }
         }
     }
 
+    pub fn set_bool_representation(&mut self, bool_representation: BoolRepresentation) {
+        self.bool_representation = bool_representation;
+    }
+
+    /// Sets how strings are read and written, see `StringEncoding`.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::{FileManipulator, EndianType, WriteMode, StringEncoding};
+    /// let mut fm = FileManipulator::new(b"caf\xE9\0\x93hi\x94\0".to_vec(), EndianType::BIG, WriteMode::OVERWRITE);
+    /// fm.set_string_encoding(StringEncoding::Utf8OrWindows1252);
+    /// assert_eq!(fm.r_str_null(), "caf\u{e9}");
+    /// assert_eq!(fm.r_str_null(), "\u{201c}hi\u{201d}");
+    /// fm.seek(0);
+    /// assert_eq!(fm.r_str_null_lossy(), "caf\u{fffd}");
+    /// 
+    /// let mut fm = FileManipulator::new(Vec::new(), EndianType::BIG, WriteMode::OVERWRITE);
+    /// fm.set_string_encoding(StringEncoding::Windows1252);
+    /// fm.w_str_null("\u{20ac}5");
+    /// assert_eq!(fm.get_data(), b"\x805\0");
+    /// ```
+    pub fn set_string_encoding(&mut self, string_encoding: StringEncoding) {
+        self.string_encoding = string_encoding;
+    }
+
     pub fn flip_endian(&mut self) {
         self.endian = match self.endian {
             EndianType::BIG => EndianType::LITTLE,
@@ -394,12 +547,32 @@ impl FileManipulator {
         self.pos = new_pos as usize;
     }
 
+    #[deprecated(note = "use align_read to skip padding or align_write to write it")]
     pub fn align(&mut self, num: usize) {
-        let pos = self.pos;
-        if (pos % num) != 0 {
-            let padding = num - (pos % num);
-            self.move_pos(padding as isize);
-        }
+        self.align_read(num);
+    }
+
+    /// Moves the position forward to the next multiple of `num`, skipping the padding. Panics if
+    /// that is past the end of the data.
+    pub fn align_read(&mut self, num: usize) {
+        let padding = (num - self.pos % num) % num;
+        self.move_pos(padding as isize);
+    }
+
+    /// Writes zeros up to the next multiple of `num`, so it can be used at the end of the data.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::{FileManipulator, EndianType, WriteMode};
+    /// let mut fm = FileManipulator::new(Vec::new(), EndianType::BIG, WriteMode::OVERWRITE);
+    /// fm.w_u8(1);
+    /// fm.align_write(4);
+    /// fm.align_write(4);
+    /// assert_eq!(fm.get_data(), &vec![1, 0, 0, 0]);
+    /// assert_eq!(fm.tell(), 4);
+    /// ```
+    pub fn align_write(&mut self, num: usize) {
+        let padding = (num - self.pos % num) % num;
+        self.write(&vec![0; padding]);
     }
 
     pub fn pad(&mut self, amount: usize) {
@@ -471,11 +644,46 @@ impl FileManipulator {
         self.pos = pos;
     }
 
+    /// Saves the current position and returns a guard that moves back to it when dropped. The
+    /// guard derefs to the FileManipulator, so reads through it can seek anywhere in between.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::{FileManipulator, EndianType, WriteMode};
+    /// let mut fm = FileManipulator::new(b"\0\0\0\x08name\0".to_vec(), EndianType::BIG, WriteMode::OVERWRITE);
+    /// fm.seek_read(3);
+    /// {
+    ///     let mut guard = fm.save_position();
+    ///     guard.seek_read(4);
+    ///     assert_eq!(guard.r_str_null(), "name");
+    /// }
+    /// assert_eq!(fm.tell(), 3);
+    /// assert_eq!(fm.r_u8(), 8);
+    /// ```
+    pub fn save_position(&mut self) -> PositionGuard<'_> {
+        let pos = self.pos;
+        return PositionGuard { fm: self, pos };
+    }
+
+    /// Sets the current position for reading. Unlike `seek`, a position past the end of the data
+    /// panics instead of growing the data, so a bad offset in a malformed file is caught where it
+    /// is followed.
+    pub fn seek_read(&mut self, pos: usize) {
+        if pos > self.data.len() {
+            panic!("Invalid position: {} is past the end of the data ({} bytes)", pos, self.data.len());
+        }
+        self.pos = pos;
+    }
+
     // get the data
     pub fn get_data(&self) -> &Vec<u8> {
         &self.data
     }
 
+    /// Consumes the manipulator and returns its data without copying it
+    pub fn into_data(self) -> Vec<u8> {
+        return self.data;
+    }
+
     pub fn get_endian(&self) -> &EndianType {
         &self.endian
     }
@@ -485,4 +693,76 @@ impl FileManipulator {
     }
 }
 
+/// Moves a FileManipulator back to a saved position when dropped, made by
+/// `FileManipulator::save_position`.
+pub struct PositionGuard<'a> {
+    fm: &'a mut FileManipulator,
+    pos: usize,
+}
 
+impl std::ops::Deref for PositionGuard<'_> {
+    type Target = FileManipulator;
+
+    fn deref(&self) -> &FileManipulator {
+        return self.fm;
+    }
+}
+
+impl std::ops::DerefMut for PositionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut FileManipulator {
+        return self.fm;
+    }
+}
+
+impl Drop for PositionGuard<'_> {
+    fn drop(&mut self) {
+        self.fm.pos = self.pos;
+    }
+}
+
+/// The longest text, in bytes, that `w_str_jps` can write. The size byte of a JPS string counts
+/// the size and length bytes, the null terminator and the padding, and 249 bytes of text give a
+/// size of 252, the largest multiple of 4 that fits in a byte.
+pub const MAX_JPS_STRING_LENGTH: usize = 249;
+
+// bytes a JPS string takes: size and length bytes, the text and its null terminator, padded to 4
+fn jps_string_size(text_length: usize) -> usize {
+    return (text_length + 3).next_multiple_of(4);
+}
+
+// what Windows-1252 has at 0x80 to 0x9F, where Latin-1 has control characters. The five bytes it
+// leaves undefined map to the control character of the same value, so they survive a round trip
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| match byte {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+        _ => char::from(*byte)
+    }).collect();
+}
+
+fn encode_windows_1252(text: &str) -> Vec<u8> {
+    return text.chars().map(|character| {
+        if let Some(index) = WINDOWS_1252_HIGH.iter().position(|high| *high == character) {
+            return 0x80 + index as u8;
+        }
+        match u8::try_from(character) {
+            Ok(byte) if !(0x80..=0x9F).contains(&byte) => return byte,
+            _ => panic!("{:?} can't be written in Windows-1252: {}", character, text)
+        }
+    }).collect();
+}
+
+// read a u32 straight from a slice, None if it runs past the end
+pub(crate) fn read_u32_at(data: &[u8], offset: usize, endian_type: EndianType) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().unwrap();
+    return match endian_type {
+        EndianType::BIG => Some(u32::from_be_bytes(bytes)),
+        EndianType::LITTLE => Some(u32::from_le_bytes(bytes)),
+    };
+}
@@ -1,9 +1,13 @@
 // lib.rs
 // © 2024 Epic Mickey Library
 
+#![allow(clippy::needless_return)]
+
 extern crate flate2;
+extern crate serde;
 extern crate serde_json;
 
+pub mod asset;
 pub mod collectible_database;
 pub mod dct;
 pub mod file_manipulator;
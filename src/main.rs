@@ -0,0 +1,282 @@
+// main.rs
+// © 2024 Epic Mickey Library
+
+#![allow(clippy::needless_return)]
+
+use std::process::ExitCode;
+
+use epic_mickey_lib_rs::asset::{self, Asset};
+use epic_mickey_lib_rs::collectible_database::CollectibleDatabase;
+use epic_mickey_lib_rs::dct::DCT;
+use epic_mickey_lib_rs::file_manipulator::{EndianType, StringEncoding};
+use epic_mickey_lib_rs::packfile::{CompressionKind, EndianDependentString, Packfile, VirtualFile};
+use epic_mickey_lib_rs::scene_file::SceneFile;
+
+// written next to extracted files so `pack` can restore the file types, compression, order,
+// byte order and layout
+const MANIFEST_NAME: &str = "_packfile.json";
+
+const USAGE: &str = "usage: epic-mickey-lib-rs <command> <input> <output> [options]
+
+commands:
+  extract <pak> <outdir> [--endian big|little] [--decompile]
+      extract every file in a packfile, optionally converting dct/bin/clb files to JSON
+  pack <dir> <pak> [--endian big|little]
+      rebuild a packfile from a directory made by extract (uses its _packfile.json)
+  dct2json <dct> <json> [--endian big|little]
+  json2dct <json> <dct> [--endian big|little]
+  scene2json <bin> <json> [--endian big|little]
+  json2scene <json> <bin> [--endian big|little]
+  clb2json <clb> <json> [--endian big|little]
+  json2clb <json> <clb> [--endian big|little]
+
+options:
+  --endian big|little  byte order of the binary file (default: detected when reading a pak,
+                       dct, clb or version 2 scene, the one recorded by extract or *2json
+                       when writing one, otherwise big; version 1 scenes need it)
+  --decompile          write dct, bin and clb files from a pak as JSON";
+
+/// Parsed command line arguments
+/// 
+/// # Fields
+/// 
+/// * `command` - The subcommand
+/// * `input` - The first positional argument
+/// * `output` - The second positional argument
+/// * `endian_type` - The byte order given with `--endian`, if any
+/// * `decompile` - Whether `--decompile` was given
+struct Arguments {
+    command: String,
+    input: String,
+    output: String,
+    endian_type: Option<EndianType>,
+    decompile: bool,
+}
+
+fn parse_arguments(args: &[String]) -> Result<Arguments, String> {
+    let mut positional = Vec::new();
+    let mut endian_type = None;
+    let mut decompile = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--endian" => {
+                let value = iter.next().ok_or("--endian needs a value".to_string())?;
+                endian_type = Some(endian_from_name(&value.to_lowercase()).ok_or(format!("invalid endian \"{}\", expected big or little", value))?);
+            },
+            "--decompile" => decompile = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg.clone())
+        }
+    }
+    if positional.len() != 3 {
+        return Err(format!("expected a command and 2 paths, got {} arguments", positional.len()));
+    }
+    let output = positional.pop().unwrap();
+    let input = positional.pop().unwrap();
+    let command = positional.pop().unwrap();
+    return Ok(Arguments { command, input, output, endian_type, decompile });
+}
+
+fn endian_from_name(name: &str) -> Option<EndianType> {
+    return match name {
+        "big" => Some(EndianType::BIG),
+        "little" => Some(EndianType::LITTLE),
+        _ => None
+    };
+}
+
+fn endian_name(endian_type: EndianType) -> &'static str {
+    return match endian_type {
+        EndianType::BIG => "big",
+        EndianType::LITTLE => "little"
+    };
+}
+
+// the byte order given with --endian, else the one recorded in a manifest or JSON file, else big
+fn endian_or_recorded(arguments: &Arguments, dict: &serde_json::Value) -> Result<EndianType, String> {
+    if let Some(endian_type) = arguments.endian_type {
+        return Ok(endian_type);
+    }
+    return match dict["endian"].as_str() {
+        Some(name) => endian_from_name(name).ok_or(format!("invalid endian \"{}\" in {}", name, arguments.input)),
+        None => Ok(EndianType::BIG)
+    };
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    return std::fs::read(path).map_err(|error| format!("could not read {}: {}", path, error));
+}
+
+fn read_text_file(path: &str) -> Result<String, String> {
+    return std::fs::read_to_string(path).map_err(|error| format!("could not read {}: {}", path, error));
+}
+
+fn write_file(path: &str, data: &[u8]) -> Result<(), String> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent).map_err(|error| format!("could not create {}: {}", parent.display(), error))?;
+        }
+    }
+    return std::fs::write(path, data).map_err(|error| format!("could not write {}: {}", path, error));
+}
+
+// the library reports malformed files by panicking, turn that into an error message
+fn catch_panic<T>(description: &str, function: impl FnOnce() -> T) -> Result<T, String> {
+    return std::panic::catch_unwind(std::panic::AssertUnwindSafe(function)).map_err(|payload| {
+        let message = match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => payload.downcast_ref::<&str>().map(|message| message.to_string()).unwrap_or_default()
+        };
+        return format!("could not {}: {}", description, message);
+    });
+}
+
+fn packfile_endian(data: &[u8]) -> EndianType {
+    if data.starts_with(b"PAK ") {
+        return EndianType::LITTLE;
+    }
+    return EndianType::BIG;
+}
+
+fn extract(arguments: &Arguments) -> Result<(), String> {
+    let data = read_file(&arguments.input)?;
+    let endian_type = arguments.endian_type.unwrap_or(packfile_endian(&data));
+    let packfile = Packfile::try_from_binary(data).map_err(|error| format!("could not read the packfile: {}", error))?;
+    // the manifest is written next to the extracted files, it must not replace one of them
+    if packfile.paths().iter().any(|path| path.replace('\\', "/").eq_ignore_ascii_case(MANIFEST_NAME)) {
+        return Err(format!("the packfile has a file named {}, which extract writes its manifest to", MANIFEST_NAME));
+    }
+    if packfile.trailing_bytes > 0 {
+        eprintln!("warning: {} bytes after the last file in {} are not extracted", packfile.trailing_bytes, arguments.input);
+    }
+    std::fs::create_dir_all(&arguments.output).map_err(|error| format!("could not create {}: {}", arguments.output, error))?;
+    let mut errors = Vec::new();
+    if arguments.decompile {
+        errors = catch_panic("extract the packfile", || packfile.extract_decompiled(arguments.output.clone(), endian_type, true))?
+            .map_err(|error| format!("could not extract the packfile: {}", error))?;
+    } else {
+        catch_panic("extract the packfile", || packfile.extract(arguments.output.clone()))?;
+    }
+    let mut manifest = packfile.to_dict_stripped();
+    manifest["endian"] = serde_json::Value::from(endian_name(endian_type));
+    write_file(&format!("{}/{}", arguments.output, MANIFEST_NAME), serde_json::to_string_pretty(&manifest).unwrap().as_bytes())?;
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        return Err(format!("{} files could not be extracted:\n{}", errors.len(), messages.join("\n")));
+    }
+    return Ok(());
+}
+
+fn pack(arguments: &Arguments) -> Result<(), String> {
+    let manifest_path = format!("{}/{}", arguments.input, MANIFEST_NAME);
+    let manifest: serde_json::Value = serde_json::from_str(&read_text_file(&manifest_path)?)
+        .map_err(|error| format!("invalid manifest {}: {}", manifest_path, error))?;
+    let endian_type = endian_or_recorded(arguments, &manifest)?;
+    let entries = manifest["files"].as_array().ok_or(format!("manifest {} has no files list", manifest_path))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry["path"].as_str().ok_or(format!("manifest entry without a path: {}", entry))?;
+        let disk_path = format!("{}/{}", arguments.input, path);
+        let data = if std::path::Path::new(&disk_path).exists() {
+            read_file(&disk_path)?
+        } else {
+            // fall back to a decompiled copy made by extract --decompile
+            let json = read_text_file(&format!("{}.json", disk_path))?;
+            let extension = path.rsplit('.').next().unwrap_or("");
+            catch_panic(&format!("compile {}.json", disk_path), || asset::compile_by_extension(extension, &json, endian_type))?
+                .ok_or(format!("{} is missing and {}.json can't be compiled", disk_path, disk_path))?
+        };
+        let type_ = EndianDependentString::new(entry["type"].as_str().unwrap_or("").to_string());
+        let compression = match entry["compression"].as_str() {
+            Some(name) => CompressionKind::from_name(name).ok_or(format!("unknown compression \"{}\" for {}", name, path))?,
+            // manifests written before the compression kind was recorded only say whether it is compressed
+            None if entry["compress"].as_bool().unwrap_or(true) => CompressionKind::Zlib,
+            None => CompressionKind::None
+        };
+        let compression_level = entry["compression_level"].as_u64().unwrap_or(6) as u32;
+        files.push(VirtualFile::new(type_, compression, compression_level, path.to_string(), data));
+    }
+    let magic = EndianDependentString::new(manifest["magic"].as_str().unwrap_or(" KAP").to_string());
+    // to_dict_stripped writes the version as a float
+    let version = manifest["version"].as_f64().unwrap_or(2.0) as u32;
+    let mut packfile = Packfile::new(magic, version, files);
+    // manifests written before the layout was recorded get the retail one
+    if let Some(header_size) = manifest["header_size"].as_u64() {
+        if header_size < 20 || header_size > u32::MAX as u64 {
+            return Err(format!("invalid header_size {} in manifest {}", header_size, manifest_path));
+        }
+        packfile.set_header_size(header_size as u32);
+    }
+    if let Some(alignment) = manifest["alignment"].as_u64() {
+        if alignment == 0 || alignment > u32::MAX as u64 {
+            return Err(format!("invalid alignment {} in manifest {}", alignment, manifest_path));
+        }
+        packfile.set_alignment(alignment as u32);
+    }
+    packfile.shared_string_pool = manifest["shared_string_pool"].as_bool().unwrap_or(false);
+    packfile.name_encoding = match manifest["name_encoding"].as_str() {
+        None | Some("utf-8") => StringEncoding::Utf8,
+        Some("windows-1252") => StringEncoding::Windows1252,
+        Some(name_encoding) => return Err(format!("unknown name_encoding \"{}\" in manifest {}", name_encoding, manifest_path))
+    };
+    let data = catch_panic("pack the packfile", || packfile.to_binary(endian_type))?;
+    return write_file(&arguments.output, &data);
+}
+
+fn binary_to_json<A: Asset>(arguments: &Arguments, detect_endian: fn(&[u8]) -> Option<EndianType>) -> Result<(), String> {
+    let data = read_file(&arguments.input)?;
+    let endian_type = match arguments.endian_type.or_else(|| detect_endian(&data)) {
+        Some(endian_type) => endian_type,
+        None => return Err(format!("could not detect the byte order of {}, pass --endian big|little", arguments.input))
+    };
+    let json = catch_panic(&format!("read {}", arguments.input), || A::from_binary(&data, endian_type).to_json())?;
+    // record the byte order so json2* writes the file back the same way
+    let mut dict: serde_json::Value = serde_json::from_str(&json).unwrap();
+    dict["endian"] = serde_json::Value::from(endian_name(endian_type));
+    return write_file(&arguments.output, serde_json::to_string_pretty(&dict).unwrap().as_bytes());
+}
+
+fn json_to_binary<A: Asset>(arguments: &Arguments) -> Result<(), String> {
+    let json = read_text_file(&arguments.input)?;
+    let dict = serde_json::from_str::<serde_json::Value>(&json).map_err(|error| format!("invalid JSON in {}: {}", arguments.input, error))?;
+    let endian_type = endian_or_recorded(arguments, &dict)?;
+    let data = catch_panic(&format!("convert {}", arguments.input), || A::from_json(&json).to_binary(endian_type))?;
+    return write_file(&arguments.output, &data);
+}
+
+fn run(arguments: &Arguments) -> Result<(), String> {
+    return match arguments.command.as_str() {
+        "extract" => extract(arguments),
+        "pack" => pack(arguments),
+        "dct2json" => binary_to_json::<DCT>(arguments, |data| Some(DCT::detect_endian(data))),
+        "json2dct" => json_to_binary::<DCT>(arguments),
+        "scene2json" => binary_to_json::<SceneFile>(arguments, SceneFile::detect_endian),
+        "json2scene" => json_to_binary::<SceneFile>(arguments),
+        "clb2json" => binary_to_json::<CollectibleDatabase>(arguments, |data| Some(CollectibleDatabase::detect_endian(data))),
+        "json2clb" => json_to_binary::<CollectibleDatabase>(arguments),
+        command => Err(format!("unknown command {}", command))
+    };
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let arguments = match parse_arguments(&args) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    // print where a library panic happened, without a backtrace; catch_panic then reports it as an error
+    std::panic::set_hook(Box::new(|info| eprintln!("{}", info)));
+    if let Err(error) = run(&arguments) {
+        eprintln!("error: {}", error);
+        return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
+}
@@ -4,22 +4,104 @@
 use std::{fs::File, io::{Read, Write}};
 use serde_json;
 
-use flate2::write::ZlibEncoder;
-use flate2::read::ZlibDecoder;
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
 use flate2::Compression;
 
+use crate::asset;
 use crate::file_manipulator;
 use crate::scene_file;
-use crate::dct;
-use crate::collectible_database;
+
+/// An error produced while working with a Packfile.
+#[derive(Debug)]
+pub enum PackfileError {
+    /// No VirtualFile has this path.
+    FileNotFound(String),
+    /// Writing the extracted file failed.
+    Io(std::io::Error),
+    /// The file table describes data past the end of the archive.
+    TruncatedArchive {
+        /// The length the file table needs the archive to have.
+        expected: usize,
+        /// The length of the archive.
+        actual: usize
+    },
+    /// A dct, bin or clb file could not be read to convert it to JSON, for this reason.
+    Decompile(String),
+    /// The archive doesn't start with `PACKFILE_MAGIC` in either byte order.
+    InvalidMagic(Vec<u8>),
+    /// The archive has a version other than 2.
+    UnsupportedVersion(u32),
+    /// The header or file table has a value that can't be right, for this reason.
+    InvalidHeader(String),
+    /// The data of the file at this path could not be decompressed.
+    Decompress(String, std::io::Error),
+}
+
+impl std::fmt::Display for PackfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PackfileError::FileNotFound(path) => write!(f, "VirtualFile not found: {}", path),
+            PackfileError::Io(error) => write!(f, "I/O error: {}", error),
+            PackfileError::TruncatedArchive { expected, actual } => write!(f, "Truncated archive: the file table needs {} bytes, but there are {}", expected, actual),
+            PackfileError::Decompile(reason) => write!(f, "Could not decompile: {}", reason),
+            PackfileError::InvalidMagic(magic) => write!(f, "Invalid magic: {:?} (expected {:?})", magic, PACKFILE_MAGIC),
+            PackfileError::UnsupportedVersion(version) => write!(f, "Unsupported version: {} (expected 2)", version),
+            PackfileError::InvalidHeader(reason) => write!(f, "Invalid header: {}", reason),
+            PackfileError::Decompress(path, error) => write!(f, "Could not decompress {}: {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for PackfileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackfileError::Io(error) => Some(error),
+            PackfileError::Decompress(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PackfileError {
+    fn from(error: std::io::Error) -> Self {
+        PackfileError::Io(error)
+    }
+}
+
+/// A file that could not be decompiled or written while extracting a Packfile.
+/// 
+/// # Fields
+/// 
+/// * `path` - The path of the VirtualFile
+/// * `error` - Why it could not be extracted
+#[derive(Debug)]
+pub struct ExtractError {
+    pub path: String,
+    pub error: PackfileError
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Could not extract {}: {}", self.path, self.error)
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
 
 /// A string that is dependent on the endian type. If the endian type is little, the string is reversed. It is expected to be 4 characters long.
 /// 
 /// # Example
 /// 
 /// ```rust
+/// # use epic_mickey_lib_rs::packfile::EndianDependentString;
 /// let string = EndianDependentString::new(" KAP".to_string());
 /// ```
+#[derive(Clone, PartialEq)]
 pub struct EndianDependentString {
     /// The string to be stored.
     pub text: String
@@ -50,15 +132,16 @@ impl EndianDependentString {
     /// # Example
     /// 
     /// ```
+    /// # use epic_mickey_lib_rs::packfile::EndianDependentString;
+    /// # use epic_mickey_lib_rs::file_manipulator::{FileManipulator, EndianType, WriteMode};
+    /// # let mut fm = FileManipulator::new(b" KAP".to_vec(), EndianType::BIG, WriteMode::OVERWRITE);
     /// let mut string = EndianDependentString::new("".to_string());
     /// string.unpack(&mut fm);
     /// assert_eq!(string.text, " KAP");
     /// ```
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
         // read a 4 byte string
-        let mut buffer = [0; 4];
-        fm.read(&mut buffer);
-        self.text = String::from_utf8(buffer.to_vec()).unwrap();
+        self.text = fm.r_str(4);
 
         // if the endian is little, reverse the string
         if *fm.get_endian() == file_manipulator::EndianType::LITTLE {
@@ -78,37 +161,234 @@ impl EndianDependentString {
     /// 
     /// * `Vec<u8>` - The packed data
     pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
-        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type.clone(), file_manipulator::WriteMode::OVERWRITE);
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
         let mut string_to_write = self.text.clone();
         // pad the string with null bytes
         while string_to_write.len() < 4 {
             string_to_write.push(char::from(0));
         }
         // if the endian is little, reverse the string
-        if endian_type.clone() == file_manipulator::EndianType::LITTLE {
+        if endian_type == file_manipulator::EndianType::LITTLE {
             string_to_write = string_to_write.chars().rev().collect();
         }
         fm.write(string_to_write.as_bytes());
-        return fm.get_data().to_vec();
+        return fm.into_data();
+    }
+}
+
+/// How the data of a VirtualFile is compressed.
+/// 
+/// # Variants
+/// 
+/// * `None` - The data is stored as is
+/// * `Zlib` - DEFLATE with a zlib header and checksum (used by the game)
+/// * `RawDeflate` - DEFLATE without a header
+/// * `Gzip` - DEFLATE with a gzip header
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CompressionKind {
+    None,
+    #[default]
+    Zlib,
+    RawDeflate,
+    Gzip,
+}
+
+impl CompressionKind {
+    /// Get the name of the CompressionKind as it is written in JSON.
+    /// 
+    /// # Returns
+    /// 
+    /// * `&str` - "none", "zlib", "raw_deflate" or "gzip"
+    pub fn name(&self) -> &'static str {
+        return match self {
+            CompressionKind::None => "none",
+            CompressionKind::Zlib => "zlib",
+            CompressionKind::RawDeflate => "raw_deflate",
+            CompressionKind::Gzip => "gzip",
+        };
+    }
+
+    /// Get a CompressionKind from its name.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `name` - The name, as returned by `name`
+    /// 
+    /// # Returns
+    /// 
+    /// * `Option<CompressionKind>` - The CompressionKind, or `None` if the name is unknown
+    pub fn from_name(name: &str) -> Option<CompressionKind> {
+        return match name {
+            "none" => Some(CompressionKind::None),
+            "zlib" => Some(CompressionKind::Zlib),
+            "raw_deflate" => Some(CompressionKind::RawDeflate),
+            "gzip" => Some(CompressionKind::Gzip),
+            _ => None,
+        };
+    }
+
+    /// Guess how compressed data was compressed from its first bytes. Anything without a gzip or
+    /// zlib header is assumed to be raw DEFLATE.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The compressed data
+    /// 
+    /// # Returns
+    /// 
+    /// * `CompressionKind` - The detected CompressionKind (never `None`)
+    pub fn detect(data: &[u8]) -> CompressionKind {
+        if data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b {
+            return CompressionKind::Gzip;
+        }
+        // a zlib header uses method 8 (deflate) and its first 2 bytes are a multiple of 31
+        if data.len() >= 2 && data[0] & 0x0f == 8 && (((data[0] as u16) << 8) | data[1] as u16).is_multiple_of(31) {
+            return CompressionKind::Zlib;
+        }
+        return CompressionKind::RawDeflate;
+    }
+
+    /// Compress data.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The data to compress
+    /// * `compression_level` - The compression level (0-9)
+    /// 
+    /// # Returns
+    /// 
+    /// * `Vec<u8>` - The compressed data
+    pub fn compress(&self, data: &[u8], compression_level: u32) -> Vec<u8> {
+        let level = Compression::new(compression_level);
+        return match self {
+            CompressionKind::None => data.to_vec(),
+            CompressionKind::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            },
+            CompressionKind::RawDeflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            },
+            CompressionKind::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            },
+        };
     }
 
-    pub fn clone(&self) -> Self {
-        return EndianDependentString::new(self.text.clone());
+    /// Decompress data.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The compressed data
+    /// 
+    /// # Returns
+    /// 
+    /// * `Vec<u8>` - The decompressed data
+    pub fn decompress(&self, data: &[u8]) -> Vec<u8> {
+        return self.try_decompress(data).unwrap();
     }
+
+    /// Like `decompress`, but returns an error instead of panicking when the data is corrupt.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The compressed data
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Vec<u8>, std::io::Error>` - The decompressed data, or the decoder's error
+    pub fn try_decompress(&self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        let mut decompressed_data = Vec::new();
+        match self {
+            CompressionKind::None => decompressed_data.extend_from_slice(data),
+            CompressionKind::Zlib => {
+                ZlibDecoder::new(data).read_to_end(&mut decompressed_data)?;
+            },
+            CompressionKind::RawDeflate => {
+                DeflateDecoder::new(data).read_to_end(&mut decompressed_data)?;
+            },
+            CompressionKind::Gzip => {
+                GzDecoder::new(data).read_to_end(&mut decompressed_data)?;
+            },
+        }
+        return Ok(decompressed_data);
+    }}
+
+// compression level used for files added without one
+const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// The type and compression `VirtualFile::from_file` gives files with an extension.
+/// 
+/// # Fields
+/// 
+/// * `extension` - The file extension, lowercase and without the dot
+/// * `type_` - The file type, as stored in `EndianDependentString::text`
+/// * `compression` - How files with the extension are compressed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FileType {
+    pub extension: &'static str,
+    pub type_: &'static str,
+    pub compression: CompressionKind
 }
 
+/// The file types of the game's archives. The tag and compression the game uses for each
+/// extension have not been checked against retail archives yet, so none are listed and every
+/// file gets `DEFAULT_FILE_TYPE`; pass your own table to `VirtualFile::from_file_with_types`
+/// until they are.
+pub const FILE_TYPES: &[FileType] = &[];
+
+/// The type and compression given to files whose extension isn't in the table: an empty tag
+/// (packed as four null bytes) and zlib, the compression the game uses.
+pub const DEFAULT_FILE_TYPE: FileType = FileType { extension: "", type_: "", compression: CompressionKind::Zlib };
+
 /// A virtual file that can be stored in a Packfile.
 pub struct VirtualFile {
     /// The file type of the virtual file (different from the file extension).
     pub type_: EndianDependentString,
-    /// Whether the data is compressed.
-    pub compress: bool,
+    /// How the data is compressed.
+    pub compression: CompressionKind,
     /// The compression level of the data (0-9).
     pub compression_level: u32,
     /// The path of the file.
     pub path: String,
-    /// The data of the file.
-    pub data: Vec<u8>
+    // the uncompressed data, for a file read from an archive it is decompressed on first access
+    data: std::sync::OnceLock<Vec<u8>>,
+    // the data as stored in the archive, until it is decompressed
+    stored_data: Option<StoredData>,
+    // the data compressed with the compression and level it was compressed with, cleared when the data changes
+    compressed: std::sync::Mutex<Option<CompressedData>>
+}
+
+// where the bytes of a file read from an archive are
+enum StoredData {
+    Owned(Vec<u8>),
+    // a range of an archive opened with `Packfile::from_mmap`
+    #[cfg(feature = "mmap")]
+    Mapped(std::sync::Arc<memmap2::Mmap>, std::ops::Range<usize>)
+}
+
+impl StoredData {
+    fn bytes(&self) -> &[u8] {
+        return match self {
+            StoredData::Owned(data) => data,
+            #[cfg(feature = "mmap")]
+            StoredData::Mapped(map, range) => &map[range.clone()]
+        };
+    }
+}
+
+// compressed data and the compression and level it was compressed with
+type CompressedData = (CompressionKind, u32, std::sync::Arc<Vec<u8>>);
+
+// how many times a VirtualFile compressed its data, so tests can tell the cache is used
+#[cfg(test)]
+thread_local! {
+    static COMPRESSIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
 }
 
 impl VirtualFile {
@@ -117,7 +397,7 @@ impl VirtualFile {
     /// # Arguments
     /// 
     /// * `type_` - The file type of the virtual file
-    /// * `compress` - Whether the data is compressed
+    /// * `compression` - How the data is compressed
     /// * `compression_level` - The compression level of the data (0-9)
     /// * `path` - The path of the file
     /// * `data` - The data of the file
@@ -125,14 +405,144 @@ impl VirtualFile {
     /// # Returns
     /// 
     /// * `VirtualFile` - The created VirtualFile
-    pub fn new(type_: EndianDependentString, compress: bool, compression_level: u32, path: String, data: Vec<u8>) -> Self {
+    pub fn new(type_: EndianDependentString, compression: CompressionKind, compression_level: u32, path: String, data: Vec<u8>) -> Self {
         Self {
             type_,
-            compress,
+            compression,
             compression_level,
             path,
-            data
+            data: std::sync::OnceLock::from(data),
+            stored_data: None,
+            compressed: std::sync::Mutex::new(None)
+        }
+    }
+
+    // a file read from an archive, its data is only decompressed when it is first used
+    fn from_stored(type_: EndianDependentString, compression: CompressionKind, path: String, stored_data: StoredData) -> Self {
+        Self {
+            type_,
+            compression,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            path,
+            data: std::sync::OnceLock::new(),
+            stored_data: Some(stored_data),
+            compressed: std::sync::Mutex::new(None)
+        }
+    }
+
+    /// Get the uncompressed data of the VirtualFile. A file read from an archive is decompressed
+    /// the first time its data is asked for.
+    /// 
+    /// # Returns
+    /// 
+    /// * `&[u8]` - The data
+    /// 
+    /// # Panics
+    /// 
+    /// * If the stored data is corrupt, see `try_data`
+    pub fn data(&self) -> &[u8] {
+        return match self.try_data() {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `data`, but returns an error instead of panicking when the stored data can't be
+    /// decompressed. The error is returned again on every call.
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<&[u8], PackfileError>` - The data, or `Decompress`
+    pub fn try_data(&self) -> Result<&[u8], PackfileError> {
+        if let Some(data) = self.data.get() {
+            return Ok(data);
         }
+        let stored_data = self.stored_data.as_ref().map(StoredData::bytes).unwrap_or_default();
+        let data = self.compression.try_decompress(stored_data).map_err(|error| PackfileError::Decompress(self.path.clone(), error))?;
+        return Ok(self.data.get_or_init(|| data));
+    }
+
+    /// Get the uncompressed data of the VirtualFile to change it.
+    /// 
+    /// # Returns
+    /// 
+    /// * `&mut Vec<u8>` - The data
+    pub fn data_mut(&mut self) -> &mut Vec<u8> {
+        self.data();
+        // the stored and compressed bytes no longer match once the data can change
+        self.stored_data = None;
+        *self.compressed.get_mut().unwrap() = None;
+        return self.data.get_mut().unwrap();
+    }
+
+    /// Replace the data of the VirtualFile.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The new uncompressed data
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.data = std::sync::OnceLock::from(data);
+        self.stored_data = None;
+        *self.compressed.get_mut().unwrap() = None;
+    }
+
+    /// Whether the data has been decompressed yet. Only a file read from an archive whose data
+    /// hasn't been used is not.
+    /// 
+    /// # Returns
+    /// 
+    /// * `bool` - Whether the data is decompressed
+    pub fn is_decompressed(&self) -> bool {
+        return self.data.get().is_some();
+    }
+
+    /// Create a new VirtualFile, taking the type and compression from the entry in `FILE_TYPES`
+    /// for the extension of the path and using compression level 6. Files with an extension not
+    /// in the table get `DEFAULT_FILE_TYPE`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path_in_archive` - The path of the file
+    /// * `data` - The data of the file
+    /// 
+    /// # Returns
+    /// 
+    /// * `VirtualFile` - The created VirtualFile
+    pub fn from_file(path_in_archive: &str, data: Vec<u8>) -> Self {
+        return VirtualFile::from_file_with_types(path_in_archive, data, FILE_TYPES);
+    }
+
+    /// Like `from_file`, but looks the extension up in `file_types` instead of `FILE_TYPES`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path_in_archive` - The path of the file
+    /// * `data` - The data of the file
+    /// * `file_types` - The known file types
+    /// 
+    /// # Returns
+    /// 
+    /// * `VirtualFile` - The created VirtualFile
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::packfile::{CompressionKind, FileType, VirtualFile};
+    /// // a made-up tag, not one taken from the game
+    /// const TYPES: &[FileType] = &[FileType { extension: "txt", type_: " TXT", compression: CompressionKind::None }];
+    /// let virtual_file = VirtualFile::from_file_with_types("Notes/Read Me.TXT", b"hi".to_vec(), TYPES);
+    /// assert_eq!(virtual_file.type_.text, " TXT");
+    /// let virtual_file = VirtualFile::from_file_with_types("Notes/Read Me.md", b"hi".to_vec(), TYPES);
+    /// assert_eq!(virtual_file.type_.text, "");
+    /// ```
+    pub fn from_file_with_types(path_in_archive: &str, data: Vec<u8>, file_types: &[FileType]) -> Self {
+        let file_name = path_in_archive.rsplit(['/', '\\']).next().unwrap_or("");
+        let extension = match file_name.rsplit_once('.') {
+            Some((_, extension)) => extension.to_lowercase(),
+            None => String::new()
+        };
+        let file_type = file_types.iter().find(|file_type| file_type.extension == extension).unwrap_or(&DEFAULT_FILE_TYPE);
+        return VirtualFile::new(EndianDependentString::new(file_type.type_.to_string()), file_type.compression, DEFAULT_COMPRESSION_LEVEL, path_in_archive.to_string(), data);
     }
 
     /// Get the compressed data of the VirtualFile.
@@ -141,12 +551,21 @@ impl VirtualFile {
     /// 
     /// * `Vec<u8>` - The compressed data
     pub fn get_compressed_data(&self) -> Vec<u8> {
-        let mut data = self.data.clone();
-        if self.compress {
-            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(self.compression_level));
-            encoder.write_all(&self.data).unwrap();
-            data = encoder.finish().unwrap();
+        return self.compressed_data().to_vec();
+    }
+
+    // the compressed data, compressed again only when the data, compression or level changed
+    fn compressed_data(&self) -> std::sync::Arc<Vec<u8>> {
+        let mut compressed = self.compressed.lock().unwrap();
+        if let Some((compression, compression_level, data)) = compressed.as_ref() {
+            if *compression == self.compression && *compression_level == self.compression_level {
+                return data.clone();
+            }
         }
+        #[cfg(test)]
+        COMPRESSIONS.with(|compressions| compressions.set(compressions.get() + 1));
+        let data = std::sync::Arc::new(self.compression.compress(self.data(), self.compression_level));
+        *compressed = Some((self.compression, self.compression_level, data.clone()));
         return data;
     }
 
@@ -156,12 +575,25 @@ impl VirtualFile {
     /// 
     /// * `Vec<u8>` - The assembled data
     pub fn get_assembled_data(&self) -> Vec<u8> {
+        return self.get_assembled_data_with_alignment(DEFAULT_ALIGNMENT);
+    }
+
+    /// Get the assembled data of the VirtualFile, padded to a multiple of `alignment` bytes.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `alignment` - The alignment of the data blocks in the Packfile
+    /// 
+    /// # Returns
+    /// 
+    /// * `Vec<u8>` - The assembled data
+    pub fn get_assembled_data_with_alignment(&self, alignment: u32) -> Vec<u8> {
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), file_manipulator::EndianType::LITTLE, file_manipulator::WriteMode::OVERWRITE);
-        fm.write(&self.get_compressed_data());
-        while fm.size() % 32 != 0 {
-            fm.write(&vec![0]);
+        fm.write(&self.compressed_data());
+        while !fm.size().is_multiple_of(alignment as usize) {
+            fm.write(&[0]);
         }
-        return fm.get_data().to_vec();
+        return fm.into_data();
     }
 
     /// Get the real data size of the VirtualFile.
@@ -170,7 +602,7 @@ impl VirtualFile {
     /// 
     /// * `u32` - The real data size
     pub fn get_real_data_size(&self) -> u32 {
-        return self.data.len() as u32;
+        return self.data().len() as u32;
     }
 
     /// Get the compressed data size of the VirtualFile.
@@ -179,7 +611,7 @@ impl VirtualFile {
     /// 
     /// * `u32` - The compressed data size
     pub fn get_compressed_data_size(&self) -> u32 {
-        return self.get_compressed_data().len() as u32;
+        return self.compressed_data().len() as u32;
     }
 
     /// Get the aligned data size of the VirtualFile.
@@ -188,7 +620,20 @@ impl VirtualFile {
     /// 
     /// * `u32` - The aligned data size
     pub fn get_aligned_data_size(&self) -> u32 {
-        return self.get_assembled_data().len() as u32;
+        return self.get_aligned_data_size_with_alignment(DEFAULT_ALIGNMENT);
+    }
+
+    /// Get the aligned data size of the VirtualFile for a given alignment.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `alignment` - The alignment of the data blocks in the Packfile
+    /// 
+    /// # Returns
+    /// 
+    /// * `u32` - The aligned data size
+    pub fn get_aligned_data_size_with_alignment(&self, alignment: u32) -> u32 {
+        return self.get_compressed_data_size().next_multiple_of(alignment);
     }
 
     /// Get the split path of the VirtualFile (the directory and the file name).
@@ -200,7 +645,8 @@ impl VirtualFile {
     /// # Example
     /// 
     /// ```
-    /// let virtual_file = VirtualFile::new(EndianDependentString::new("".to_string()), false, 0, "Environments/_Test/Scene.bin".to_string(), vec![]);
+    /// # use epic_mickey_lib_rs::packfile::{CompressionKind, EndianDependentString, VirtualFile};
+    /// let virtual_file = VirtualFile::new(EndianDependentString::new("".to_string()), CompressionKind::None, 0, "Environments/_Test/Scene.bin".to_string(), vec![]);
     /// 
     /// assert_eq!(virtual_file.get_split_path(), vec!["Environments/_Test".to_string(), "Scene.bin".to_string()]);
     /// ```
@@ -220,21 +666,281 @@ impl VirtualFile {
     pub fn to_dict_stripped(&self) -> serde_json::Value {
         let mut dict = serde_json::Map::new();
         dict.insert("type".to_string(), serde_json::Value::String(self.type_.text.clone()));
-        dict.insert("compress".to_string(), serde_json::Value::Bool(self.compress));
+        dict.insert("compress".to_string(), serde_json::Value::Bool(self.compression != CompressionKind::None));
+        dict.insert("compression".to_string(), serde_json::Value::String(self.compression.name().to_string()));
         dict.insert("compression_level".to_string(), serde_json::Value::Number(serde_json::Number::from(self.compression_level)));
         dict.insert("path".to_string(), serde_json::Value::String(self.path.clone()));
         return serde_json::Value::Object(dict);
     }
+
+    /// Get the VirtualFile as a dictionary without the data, but with its sizes and a CRC32 of the
+    /// uncompressed data.
+    /// 
+    /// # Returns
+    /// 
+    /// * `serde_json::Value` - The VirtualFile as a dictionary
+    pub fn to_dict_manifest(&self) -> serde_json::Value {
+        return self.to_dict_manifest_with_alignment(DEFAULT_ALIGNMENT);
+    }
+
+    fn to_dict_manifest_with_alignment(&self, alignment: u32) -> serde_json::Value {
+        let mut dict = self.to_dict_stripped();
+        let sizes = self.get_file_compression(alignment);
+        let mut crc = flate2::Crc::new();
+        crc.update(self.data());
+        dict["real_size"] = serde_json::Value::from(sizes.real_size);
+        dict["compressed_size"] = serde_json::Value::from(sizes.compressed_size);
+        dict["aligned_size"] = serde_json::Value::from(sizes.aligned_size);
+        dict["crc32"] = serde_json::Value::from(crc.sum());
+        return dict;
+    }
+
+    fn get_file_compression(&self, alignment: u32) -> FileCompression {
+        let compressed_size = self.get_compressed_data_size();
+        return FileCompression {
+            path: self.path.clone(),
+            real_size: self.get_real_data_size(),
+            compressed_size,
+            aligned_size: compressed_size.next_multiple_of(alignment)
+        };
+    }
+}
+
+/// Two VirtualFiles are equal when they have the same path and uncompressed data. The type and
+/// compression settings are not compared, see `Packfile::diff` for those.
+impl PartialEq for VirtualFile {
+    fn eq(&self, other: &Self) -> bool {
+        return self.path == other.path && self.data() == other.data();
+    }
+}
+
+/// The sizes of one VirtualFile in a CompressionReport.
+/// 
+/// # Fields
+/// 
+/// * `path` - The path of the VirtualFile
+/// * `real_size` - The size of the uncompressed data
+/// * `compressed_size` - The size of the compressed data
+/// * `aligned_size` - The size the compressed data takes in the archive, with its padding
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileCompression {
+    pub path: String,
+    pub real_size: u32,
+    pub compressed_size: u32,
+    pub aligned_size: u32
+}
+
+impl FileCompression {
+    /// Get the compressed size as a fraction of the real size, 1 for an empty file.
+    pub fn ratio(&self) -> f64 {
+        return size_ratio(self.compressed_size as u64, self.real_size as u64);
+    }
+}
+
+/// The sizes of every file in a Packfile, made by `Packfile::compression_report`.
+/// 
+/// # Fields
+/// 
+/// * `files` - The sizes of each file, in table order
+/// * `real_size` - The total size of the uncompressed data
+/// * `compressed_size` - The total size of the compressed data
+/// * `aligned_size` - The total size the data takes in the archive, with its padding
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressionReport {
+    pub files: Vec<FileCompression>,
+    pub real_size: u64,
+    pub compressed_size: u64,
+    pub aligned_size: u64
+}
+
+impl CompressionReport {
+    /// Get the total compressed size as a fraction of the total real size, 1 for an empty archive.
+    pub fn ratio(&self) -> f64 {
+        return size_ratio(self.compressed_size, self.real_size);
+    }
+
+    /// Get the bytes saved by storing the data compressed and aligned. Negative when the archive
+    /// holds more than the uncompressed data would.
+    pub fn savings(&self) -> i64 {
+        return self.real_size as i64 - self.aligned_size as i64;
+    }
+}
+
+impl std::fmt::Display for CompressionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files, {} bytes real, {} compressed ({:.1}%), {} aligned, {} saved",
+            self.files.len(),
+            self.real_size,
+            self.compressed_size,
+            self.ratio() * 100.0,
+            self.aligned_size,
+            self.savings()
+        )
+    }
+}
+
+fn size_ratio(size: u64, real_size: u64) -> f64 {
+    if real_size == 0 {
+        return 1.0;
+    }
+    return size as f64 / real_size as f64;
+}
+
+/// Two Packfiles are equal when they have the same magic and version and equal files in the same
+/// order. The header size and alignment are not compared.
+impl PartialEq for Packfile {
+    fn eq(&self, other: &Self) -> bool {
+        return self.magic == other.magic && self.version == other.version && self.files == other.files;
+    }
+}
+
+/// The differences between two Packfiles, made by `Packfile::diff`. Paths are matched ignoring
+/// case and the direction of slashes, and listed as they are written in the Packfile they come
+/// from (the second one for files in both).
+/// 
+/// # Fields
+/// 
+/// * `only_in_self` - Files only in the first Packfile
+/// * `only_in_other` - Files only in the second Packfile
+/// * `data_changed` - Files in both whose uncompressed data differs
+/// * `metadata_changed` - Files in both whose type, compression or compression level differs
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct PackfileDiff {
+    pub only_in_self: Vec<String>,
+    pub only_in_other: Vec<String>,
+    pub data_changed: Vec<String>,
+    pub metadata_changed: Vec<String>
+}
+
+impl PackfileDiff {
+    /// Whether the Packfiles hold the same files with the same settings.
+    pub fn is_empty(&self) -> bool {
+        return self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.data_changed.is_empty() && self.metadata_changed.is_empty();
+    }
+}
+
+/// A file `Packfile::plan_extract` would write.
+/// 
+/// # Fields
+/// 
+/// * `virtual_path` - The path of the VirtualFile
+/// * `out_path` - The path of the file that would be written
+/// * `size` - The number of bytes that would be written
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedFile {
+    pub virtual_path: String,
+    pub out_path: String,
+    pub size: u64
+}
+
+/// VirtualFiles that would be extracted to the same file on a case-insensitive file system, so
+/// all but the last one written would be lost.
+/// 
+/// # Fields
+/// 
+/// * `out_path` - The path of the file, as the first of the VirtualFiles would write it
+/// * `virtual_paths` - The paths of the VirtualFiles, in table order
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractCollision {
+    pub out_path: String,
+    pub virtual_paths: Vec<String>
+}
+
+/// A VirtualFile whose out path would also be a directory, because other VirtualFiles would be
+/// extracted inside it (such as `a` and `a/b`). Only one of the two can be created.
+/// 
+/// # Fields
+/// 
+/// * `out_path` - The path of the file, as it would write it
+/// * `virtual_path` - The path of the VirtualFile
+/// * `nested_virtual_paths` - The paths of the VirtualFiles that would be extracted inside it,
+///   in table order
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectoryClash {
+    pub out_path: String,
+    pub virtual_path: String,
+    pub nested_virtual_paths: Vec<String>
+}
+
+/// A VirtualFile that could not be decompiled, so extracting would skip it.
+/// 
+/// # Fields
+/// 
+/// * `virtual_path` - The path of the VirtualFile
+/// * `reason` - Why it could not be decompiled
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedFailure {
+    pub virtual_path: String,
+    pub reason: String
+}
+
+/// What extracting a Packfile would write, made by `Packfile::plan_extract`.
+/// 
+/// # Fields
+/// 
+/// * `files` - The files that would be written, in table order
+/// * `collisions` - The groups of files that would overwrite each other
+/// * `directory_clashes` - The files that other files would be extracted inside of
+/// * `failures` - The files that could not be decompiled, which are not in `files`
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ExtractPlan {
+    pub files: Vec<PlannedFile>,
+    pub collisions: Vec<ExtractCollision>,
+    pub directory_clashes: Vec<DirectoryClash>,
+    pub failures: Vec<PlannedFailure>
+}
+
+impl ExtractPlan {
+    /// Get the number of bytes that would be written in total.
+    pub fn total_size(&self) -> u64 {
+        return self.files.iter().map(|file| file.size).sum();
+    }
+}
+
+/// The header size used by the retail packfiles.
+pub const DEFAULT_HEADER_SIZE: u32 = 32;
+
+/// The alignment of the file table end and the data blocks in the retail packfiles.
+pub const DEFAULT_ALIGNMENT: u32 = 32;
+
+// magic, version, zero, header size and data pointer
+const MINIMUM_HEADER_SIZE: u32 = 20;
+
+/// The magic of a packfile as it reads in big endian. Little endian packfiles store it reversed.
+pub const PACKFILE_MAGIC: &str = " KAP";
+
+// the canonical magic for any spelling of it: either byte order, any case, with or without the space
+fn canonical_magic(text: &str) -> Option<&'static str> {
+    let text = text.trim().to_uppercase();
+    if text == "KAP" || text == "PAK" {
+        return Some(PACKFILE_MAGIC);
+    }
+    return None;
 }
 
 /// A packfile that can store multiple VirtualFiles.
 pub struct Packfile {
-    /// The magic of the packfile (should be " KAP").
+    /// The magic of the packfile (" KAP"). Packing always writes `PACKFILE_MAGIC` in the target
+    /// byte order, this only has to be a spelling of it.
     pub magic: EndianDependentString,
     /// The version of the packfile (should be 2).
     pub version: u32,
     /// The VirtualFiles stored in the packfile.
-    pub files: Vec<VirtualFile>
+    pub files: Vec<VirtualFile>,
+    /// The size of the header before the file table (32 in the retail packfiles).
+    pub header_size: u32,
+    /// The alignment of the data blocks in bytes (32 in the retail packfiles).
+    pub alignment: u32,
+    /// Whether a name used as both a folder and a file name is stored once in the string table.
+    pub shared_string_pool: bool,
+    /// How folder and file names are written. Reading sets it to `Windows1252` when a name isn't
+    /// valid UTF-8, so the names pack back to the same bytes, and to `Utf8` otherwise.
+    pub name_encoding: file_manipulator::StringEncoding,
+    /// How many bytes followed the last data block when the packfile was read (0 for a new one).
+    /// Extra bytes can mean a concatenated or padded archive; they are not packed back.
+    pub trailing_bytes: usize
 }
 
 impl Packfile {
@@ -242,7 +948,8 @@ impl Packfile {
     /// 
     /// # Arguments
     /// 
-    /// * `magic` - The magic of the packfile
+    /// * `magic` - The magic of the packfile. " KAP" in either byte order and any case is stored
+    ///   as " KAP"
     /// * `version` - The version of the packfile
     /// * `files` - The VirtualFiles stored in the packfile
     /// 
@@ -250,105 +957,250 @@ impl Packfile {
     /// 
     /// * `Packfile` - The created Packfile
     pub fn new(magic: EndianDependentString, version: u32, files: Vec<VirtualFile>) -> Self {
+        let magic = match canonical_magic(&magic.text) {
+            Some(canonical) => EndianDependentString::new(canonical.to_string()),
+            None => magic
+        };
         Self {
             magic,
             version,
-            files
+            files,
+            header_size: DEFAULT_HEADER_SIZE,
+            alignment: DEFAULT_ALIGNMENT,
+            shared_string_pool: false,
+            name_encoding: file_manipulator::StringEncoding::Utf8,
+            trailing_bytes: 0
         }
     }
 
-    /// Unpack the Packfile from a FileManipulator.
+    /// Get the magic of the Packfile.
     /// 
-    /// # Arguments
+    /// # Returns
     /// 
-    /// * `fm` - The FileManipulator to read from
-    pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
-        self.magic = EndianDependentString::new("".to_string());
-        self.magic.unpack(fm);
-        // check if the magic is " KAP"
-        if self.magic.text != " KAP" {
-            panic!("Invalid magic: {}", self.magic.text);
-        }
-        self.version = fm.r_u32();
+    /// * `&str` - The magic as it reads in big endian (" KAP")
+    pub fn magic(&self) -> &str {
+        return &self.magic.text;
+    }
+
+    /// Get the version of the Packfile.
+    /// 
+    /// # Returns
+    /// 
+    /// * `u32` - The version of the Packfile
+    pub fn version(&self) -> u32 {
+        return self.version;
+    }
+
+    /// Set the version of the Packfile.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `version` - The version to write when packing
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    /// Get the size of the header before the file table.
+    /// 
+    /// # Returns
+    /// 
+    /// * `u32` - The header size read from the packfile, or `DEFAULT_HEADER_SIZE` for a new one
+    pub fn header_size(&self) -> u32 {
+        return self.header_size;
+    }
+
+    /// Set the size of the header before the file table. The bytes between the header fields and
+    /// the file table are written as zeros.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `header_size` - The header size to write when packing (at least 20 bytes)
+    pub fn set_header_size(&mut self, header_size: u32) {
+        if header_size < MINIMUM_HEADER_SIZE {
+            panic!("Invalid header size: {} (must be at least {})", header_size, MINIMUM_HEADER_SIZE);
+        }
+        self.header_size = header_size;
+    }
+
+    /// Get the alignment of the data blocks.
+    /// 
+    /// # Returns
+    /// 
+    /// * `u32` - The alignment in bytes
+    pub fn alignment(&self) -> u32 {
+        return self.alignment;
+    }
+
+    /// Set the alignment of the data blocks. It is used when packing, and unpacking checks that
+    /// every aligned data size is a multiple of it.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `alignment` - The alignment in bytes (not 0)
+    pub fn set_alignment(&mut self, alignment: u32) {
+        if alignment == 0 {
+            panic!("Invalid alignment: 0");
+        }
+        self.alignment = alignment;
+    }
+
+    /// Set whether folder and file names share one string table when packing. Each name is always
+    /// written once per kind; sharing also lets a folder and a file with the same name use the
+    /// same bytes, which makes the table smaller. Unpacking turns it on for a packfile that does
+    /// this, so it packs back the same way.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `shared_string_pool` - Whether to share the string table
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::packfile::{CompressionKind, EndianDependentString, Packfile, VirtualFile};
+    /// let file = |path: &str| VirtualFile::new(EndianDependentString::new(" NIB".to_string()), CompressionKind::None, 0, path.to_string(), vec![1]);
+    /// let mut packfile = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, vec![file("Scene"), file("Scene/Scene")]);
+    /// packfile.set_shared_string_pool(true);
+    /// let data = packfile.to_binary(EndianType::BIG);
+    /// assert_eq!(data.windows(6).filter(|window| window == b"Scene\0").count(), 1);
+    /// 
+    /// let unpacked = Packfile::from_binary(data.clone());
+    /// assert_eq!(unpacked.paths(), ["Scene", "Scene/Scene"]);
+    /// assert!(unpacked.shared_string_pool);
+    /// assert_eq!(unpacked.to_binary(EndianType::BIG), data);
+    /// ```
+    pub fn set_shared_string_pool(&mut self, shared_string_pool: bool) {
+        self.shared_string_pool = shared_string_pool;
+    }
+
+    /// Unpack the Packfile from a FileManipulator.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `fm` - The FileManipulator to read from
+    pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
+        if let Err(error) = self.try_unpack(fm) {
+            panic!("{}", error);
+        }
+    }
+
+    fn try_unpack(&mut self, fm: &mut file_manipulator::FileManipulator) -> Result<(), PackfileError> {
+        let entries = self.unpack_table(fm)?;
+        self.trailing_bytes = check_data_section(&entries, fm.size())?;
+        for entry in entries {
+            let virtual_file = entry.load(fm.get_data());
+            self.files.push(virtual_file);
+        }
+        return Ok(());
+    }
+
+    // read the header and the file table, leaving the data where it is
+    fn unpack_table(&mut self, fm: &mut file_manipulator::FileManipulator) -> Result<Vec<TableEntry>, PackfileError> {
+        let size = fm.size();
+        // the fixed part of the header, up to the data pointer
+        if size < MINIMUM_HEADER_SIZE as usize {
+            return Err(PackfileError::TruncatedArchive { expected: MINIMUM_HEADER_SIZE as usize, actual: size });
+        }
+        self.magic = EndianDependentString::new("".to_string());
+        self.magic.unpack(fm);
+        // check if the magic is " KAP"
+        if self.magic.text != PACKFILE_MAGIC {
+            return Err(PackfileError::InvalidMagic(self.magic.text.as_bytes().to_vec()));
+        }
+        self.version = fm.r_u32();
         // check if the version is 2
         if self.version != 2 {
-            panic!("Invalid version number: {}", self.version);
+            return Err(PackfileError::UnsupportedVersion(self.version));
         }
         let zero = fm.r_u32();
         if zero != 0 {
-            panic!("Invalid zero value: {}", zero);
+            return Err(PackfileError::InvalidHeader(format!("the word after the version is {}, not 0", zero)));
         }
         let header_size = fm.r_u32();
-        let mut data_pointer = fm.r_u32();
-        data_pointer += header_size;
-        let mut current_data_position = data_pointer;
-        fm.seek(header_size as usize);
+        if header_size < MINIMUM_HEADER_SIZE {
+            return Err(PackfileError::InvalidHeader(format!("the header size is {} (must be at least {})", header_size, MINIMUM_HEADER_SIZE)));
+        }
+        self.header_size = header_size;
+        let data_pointer = fm.r_u32();
+        let data_pointer = data_pointer.checked_add(header_size)
+            .ok_or(PackfileError::InvalidHeader(format!("the data pointer {} is past 4 GiB", data_pointer)))?;
+        let mut current_data_position = data_pointer as usize;
+        let current_header_position = header_size as usize + 4;
+        if current_header_position > size {
+            return Err(PackfileError::TruncatedArchive { expected: current_header_position, actual: size });
+        }
+        fm.seek_read(header_size as usize);
         let num_files = fm.r_u32();
-        let string_pointer = (num_files * 24) + header_size + 4;
-        let current_header_position = header_size + 4;
+        let string_pointer = (num_files as usize).checked_mul(24).and_then(|table_size| table_size.checked_add(current_header_position))
+            .ok_or(PackfileError::InvalidHeader(format!("the file table of {} files is too large", num_files)))?;
+        if string_pointer > size {
+            return Err(PackfileError::TruncatedArchive { expected: string_pointer, actual: size });
+        }
 
-        fm.seek(current_header_position as usize);
+        fm.seek_read(current_header_position);
 
+        let mut entries = Vec::new();
+        let mut folder_pointers = std::collections::HashSet::new();
+        let mut file_pointers = std::collections::HashSet::new();
+        self.name_encoding = file_manipulator::StringEncoding::Utf8;
         for _ in 0..num_files {
             let real_data_size = fm.r_u32();
             let compressed_data_size = fm.r_u32();
             let aligned_data_size = fm.r_u32();
             // check if the aligned data size is correct
-            if aligned_data_size % 32 != 0 {
-                panic!("Invalid aligned data size: {}", aligned_data_size);
+            if !aligned_data_size.is_multiple_of(self.alignment) {
+                return Err(PackfileError::InvalidHeader(format!("the aligned data size {} is not a multiple of {}", aligned_data_size, self.alignment)));
             }
 
-            let mut folder_pointer = fm.r_u32();
+            let folder_pointer = fm.r_u32();
 
             let mut file_type = EndianDependentString::new("".to_string());
             file_type.unpack(fm);
 
-            let mut file_pointer = fm.r_u32();
+            let file_pointer = fm.r_u32();
+
+            folder_pointers.insert(folder_pointer);
+            file_pointers.insert(file_pointer);
 
-            folder_pointer += string_pointer;
-            file_pointer += string_pointer;
+            let folder_pointer = name_offset(fm.get_data(), string_pointer, folder_pointer)?;
+            let file_pointer = name_offset(fm.get_data(), string_pointer, file_pointer)?;
 
-            let current_header_position = fm.tell() as u32;
+            let current_header_position = fm.tell();
 
-            fm.seek(folder_pointer as usize);
+            fm.seek_read(folder_pointer);
             let folder = fm.r_str_null();
 
-            fm.seek(file_pointer as usize);
+            fm.seek_read(file_pointer);
             let file_name = fm.r_str_null();
 
-            let path: String;
-            if folder == "" {
-                path = file_name.to_owned();
-            } else {
-                path = folder.to_owned() + "/" + &file_name;
+            if !is_utf8_name(fm.get_data(), folder_pointer) || !is_utf8_name(fm.get_data(), file_pointer) {
+                self.name_encoding = file_manipulator::StringEncoding::Windows1252;
             }
 
-            fm.seek(current_data_position as usize);
-
-
-            let mut data = vec![0; compressed_data_size as usize];
-
-            fm.read(&mut data);
-
-            let mut compress = false;
-            if compressed_data_size != real_data_size {
-                compress = true;
-                // decompress the data
-                let mut decoder = ZlibDecoder::new(data.as_slice());
-                let mut decompressed_data = Vec::new();
-                decoder.read_to_end(&mut decompressed_data).unwrap();
-                data = decompressed_data;
-            }
+            let path = if folder.is_empty() {
+                file_name.to_owned()
+            } else {
+                folder.to_owned() + "/" + &file_name
+            };
 
-            let virtual_file = VirtualFile::new(file_type, compress, 6, path.to_owned(), data);
-            self.files.push(virtual_file);
+            entries.push(TableEntry {
+                type_: file_type,
+                path,
+                data_offset: current_data_position,
+                real_data_size,
+                compressed_data_size,
+                aligned_data_size
+            });
 
-            current_data_position += aligned_data_size;
-            fm.seek(current_header_position as usize);
+            current_data_position += aligned_data_size as usize;
+            fm.seek_read(current_header_position);
         }
+        self.shared_string_pool = !folder_pointers.is_disjoint(&file_pointers);
+        return Ok(entries);
     }
 
-    /// Pack the Packfile into a Vec<u8>.
+    /// Pack the Packfile into a Vec<u8>. The magic is written as `PACKFILE_MAGIC` in the byte order
+    /// of `endian_type`; a magic that isn't a spelling of it panics.
     /// 
     /// # Arguments
     /// 
@@ -357,16 +1209,35 @@ impl Packfile {
     /// # Returns
     /// 
     /// * `Vec<u8>` - The packed data
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::packfile::{EndianDependentString, Packfile};
+    /// let mut packfile = Packfile::new(EndianDependentString::new("pak ".to_string()), 2, vec![]);
+    /// assert_eq!(packfile.magic(), " KAP");
+    /// packfile.magic.text = "KAP".to_string();
+    /// assert_eq!(&packfile.pack(EndianType::BIG)[..4], b" KAP");
+    /// assert_eq!(&packfile.pack(EndianType::LITTLE)[..4], b"PAK ");
+    /// ```
     pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
-        let mut endian_type_clone = endian_type.clone();
-        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type.clone(), file_manipulator::WriteMode::OVERWRITE);
-        fm.write(&self.magic.pack(endian_type));
+        if canonical_magic(&self.magic.text).is_none() {
+            panic!("Invalid magic: {:?} (expected {:?})", self.magic.text, PACKFILE_MAGIC);
+        }
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        // the bytes only depend on the byte order, whichever spelling the magic has
+        fm.write(&EndianDependentString::new(PACKFILE_MAGIC.to_string()).pack(endian_type));
         fm.w_u32(self.version);
         fm.w_u32(0);
-        let header_size = 32;
+        let header_size = self.header_size;
+        if header_size < MINIMUM_HEADER_SIZE {
+            panic!("Invalid header size: {} (must be at least {})", header_size, MINIMUM_HEADER_SIZE);
+        }
         fm.w_u32(header_size);
 
         let mut path_partition_fm = file_manipulator::FileManipulator::new(Vec::new(), file_manipulator::EndianType::LITTLE, file_manipulator::WriteMode::OVERWRITE);
+        path_partition_fm.set_string_encoding(self.name_encoding);
         // foldername_pointers dictionary
         let mut folder_pointers = std::collections::HashMap::new();
         // filename_pointers dictionary
@@ -377,18 +1248,32 @@ impl Packfile {
             let foldername = split_path[0].clone();
             let filename = split_path[1].clone();
             if !folder_pointers.contains_key(&foldername) {
-                // folder_pointers[foldername] = len(path_partition)
-                folder_pointers.insert(foldername.clone(), path_partition_fm.size() as u32);
-                path_partition_fm.w_str_null(&foldername.clone());
+                let pointer = match filename_pointers.get(&foldername) {
+                    Some(pointer) if self.shared_string_pool => *pointer,
+                    _ => {
+                        // folder_pointers[foldername] = len(path_partition)
+                        let pointer = path_partition_fm.size() as u32;
+                        path_partition_fm.w_str_null(&foldername.clone());
+                        pointer
+                    }
+                };
+                folder_pointers.insert(foldername.clone(), pointer);
             }
             if !filename_pointers.contains_key(&filename) {
-                // filename_pointers[filename] = len(path_partition)
-                filename_pointers.insert(filename.clone(), path_partition_fm.size() as u32);
-                path_partition_fm.w_str_null(&filename.clone());
+                let pointer = match folder_pointers.get(&filename) {
+                    Some(pointer) if self.shared_string_pool => *pointer,
+                    _ => {
+                        // filename_pointers[filename] = len(path_partition)
+                        let pointer = path_partition_fm.size() as u32;
+                        path_partition_fm.w_str_null(&filename.clone());
+                        pointer
+                    }
+                };
+                filename_pointers.insert(filename.clone(), pointer);
             }
         }
-        let mut data_pointer = (header_size + path_partition_fm.size() as u32 + (self.files.len() as u32 * 24) + 4) as u32;
-        while data_pointer % 32 != 0 {
+        let mut data_pointer = header_size + path_partition_fm.size() as u32 + (self.files.len() as u32 * 24) + 4;
+        while !data_pointer.is_multiple_of(self.alignment) {
             data_pointer += 1;
         }
         fm.w_u32(data_pointer - header_size);
@@ -397,16 +1282,18 @@ impl Packfile {
 
         fm.w_u32(self.files.len() as u32);
 
+        // compress each file once, for both the table and the data
+        let compressed_files: Vec<std::sync::Arc<Vec<u8>>> = self.files.iter().map(|virtual_file| virtual_file.compressed_data()).collect();
+
         // loop through the files
-        for virtual_file in &self.files {
+        for (virtual_file, compressed_data) in self.files.iter().zip(&compressed_files) {
             let split_path = virtual_file.get_split_path();
             let foldername = split_path[0].clone();
             let filename = split_path[1].clone();
             let real_data_size = virtual_file.get_real_data_size();
-            let compressed_data_size = virtual_file.get_compressed_data_size();
-            let aligned_data_size = virtual_file.get_aligned_data_size();
+            let compressed_data_size = compressed_data.len() as u32;
+            let aligned_data_size = compressed_data_size.next_multiple_of(self.alignment);
             let file_type = virtual_file.type_.clone();
-            let path = virtual_file.path.clone();
 
             let folder_pointer = folder_pointers.get(&foldername).unwrap();
             let file_pointer = filename_pointers.get(&filename).unwrap();
@@ -415,39 +1302,57 @@ impl Packfile {
             fm.w_u32(compressed_data_size);
             fm.w_u32(aligned_data_size);
             fm.w_u32(*folder_pointer);
-            fm.write(&file_type.pack(endian_type_clone.clone()));
+            fm.write(&file_type.pack(endian_type));
             fm.w_u32(*file_pointer);
         }
         // write the path partition
-        fm.write(&path_partition_fm.get_data());
+        fm.write(path_partition_fm.get_data());
         fm.seek(data_pointer as usize);
-        // pad to 32 bytes
-        while fm.size() % 32 != 0 {
-            fm.write(&vec![0]);
+        // pad to the alignment
+        while !fm.size().is_multiple_of(self.alignment as usize) {
+            fm.write(&[0]);
         }
-        for virtual_file in &self.files {
-            fm.write(&virtual_file.get_assembled_data());
+        for compressed_data in &compressed_files {
+            fm.write(compressed_data);
+            let padding = compressed_data.len().next_multiple_of(self.alignment as usize) - compressed_data.len();
+            fm.write(&vec![0; padding]);
         }
-        return fm.get_data().to_vec();
+        return fm.into_data();
         
     }
 
-    /// Extract the Packfile to a directory.
+    /// Extract the Packfile to a directory. Files at the root of the archive are written straight
+    /// into it.
     /// 
     /// # Arguments
     /// 
     /// * `path` - The path to extract the Packfile to
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::packfile::{EndianDependentString, Packfile, VirtualFile};
+    /// let files = vec![VirtualFile::from_file("root.txt", b"root".to_vec()), VirtualFile::from_file("folder/nested.txt", b"nested".to_vec())];
+    /// let packfile = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, files);
+    /// let out = std::env::temp_dir().join(format!("em_extract_root_{}", std::process::id()));
+    /// packfile.extract(out.to_str().unwrap().to_string());
+    /// assert_eq!(std::fs::read(out.join("root.txt")).unwrap(), b"root");
+    /// assert_eq!(std::fs::read(out.join("folder").join("nested.txt")).unwrap(), b"nested");
+    /// assert_eq!(std::fs::read_dir(&out).unwrap().count(), 2);
+    /// # std::fs::remove_dir_all(&out).unwrap();
+    /// ```
     pub fn extract(&self, path: String) {
         for virtual_file in &self.files {
+            let file_path = extracted_file_path(&path, virtual_file);
             // if the directory does not exist, create it
-            let split_path = virtual_file.get_split_path();
-            let directory = path.clone() + "/" + &split_path[0];
-            if !std::path::Path::new(&directory).exists() {
-                std::fs::create_dir_all(&directory).unwrap();
+            if let Some(directory) = std::path::Path::new(&file_path).parent() {
+                if !directory.exists() {
+                    std::fs::create_dir_all(directory).unwrap();
+                }
             }
-            let mut file = File::create(path.clone() + "/" + &virtual_file.path.clone()).unwrap();
+            let mut file = File::create(&file_path).unwrap();
 
-            file.write_all(virtual_file.data.clone().as_slice()).unwrap();
+            file.write_all(virtual_file.data()).unwrap();
         }
     }
 
@@ -458,46 +1363,197 @@ impl Packfile {
     /// * `path` - The path to extract the decompiled files to
     /// * `endian_type` - The endian type to use
     /// * `overwrite` - Whether to overwrite existing files
-    pub fn extract_decompiled(&self, path: String, endian_type: file_manipulator::EndianType, overwrite: bool) {
-        for virtual_file in &self.files {
-            let split_path = virtual_file.get_split_path();
-            let directory = path.clone() + "/" + &split_path[0];
-            let mut file_path = path.clone() + "/" + &virtual_file.path.clone();
-            
-            // dct, bin, or clb should be decompiled and saved as json
-            let extension = virtual_file.path.split(".").collect::<Vec<&str>>().pop().unwrap().to_lowercase();
-            let mut data = Vec::new();
-            match extension.as_str() {
-                "dct" => {
-                    let dct = dct::DCT::from_binary(virtual_file.data.clone());
-                    data = dct.to_json().as_bytes().to_vec();
-                    file_path += ".json";
-                },
-                "bin" => {
-                    let scene_file = scene_file::SceneFile::from_binary(&virtual_file.data, endian_type.clone());
-                    data = scene_file.to_json().as_bytes().to_vec();
-                    file_path += ".json";
-                },
-                "clb" => {
-                    let collectible_database = collectible_database::CollectibleDatabase::from_binary(virtual_file.data.clone(), endian_type.clone());
-                    data = collectible_database.to_json().as_bytes().to_vec();
-                    file_path += ".json";
-                },
-                _ => {
-                    data = virtual_file.data.clone();
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Vec<ExtractError>, PackfileError>` - The files that could not be decompiled or
+    ///   written (the others are still extracted), or the error creating the directory at `path`
+    pub fn extract_decompiled(&self, path: String, endian_type: file_manipulator::EndianType, overwrite: bool) -> Result<Vec<ExtractError>, PackfileError> {
+        return self.extract_filtered(path, endian_type, overwrite, &|_| true);
+    }
+
+    /// Extract the files a predicate accepts to a directory, decompiling them like
+    /// `extract_decompiled` does.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The path to extract the files to
+    /// * `endian_type` - The endian type to use
+    /// * `overwrite` - Whether to overwrite existing files
+    /// * `predicate` - Called with each VirtualFile (path, type and data), returns whether to extract it
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Vec<ExtractError>, PackfileError>` - The files that could not be decompiled or
+    ///   written (the others are still extracted), or the error creating the directory at `path`
+    pub fn extract_filtered(&self, path: String, endian_type: file_manipulator::EndianType, overwrite: bool, predicate: &dyn Fn(&VirtualFile) -> bool) -> Result<Vec<ExtractError>, PackfileError> {
+        std::fs::create_dir_all(&path)?;
+        let mut errors = Vec::new();
+        for virtual_file in self.files.iter().filter(|virtual_file| predicate(virtual_file)) {
+            let (file_path, data) = match extracted_contents(&path, virtual_file, Some(endian_type)) {
+                Ok(contents) => contents,
+                Err(error) => {
+                    errors.push(ExtractError { path: virtual_file.path.clone(), error });
+                    continue;
                 }
-            }
+            };
             // if overwrite is false and the file already exists, skip it
             if !overwrite && std::path::Path::new(&file_path).exists() {
                 continue;
             }
-            // if the directory does not exist, create it
-            if !std::path::Path::new(&directory).exists() {
-                std::fs::create_dir_all(&directory).unwrap();
+            // keep going, one unwritable file shouldn't stop the rest
+            if let Err(error) = write_extracted_file(&file_path, &data) {
+                errors.push(ExtractError { path: virtual_file.path.clone(), error });
+            }
+        }
+        return Ok(errors);
+    }
+
+    /// Work out what extracting the Packfile would write without touching the disk: the path and
+    /// size of every file, the files that would overwrite each other because their paths only
+    /// differ in case, the files that other files would go inside of (`a` and `a/b`), and the
+    /// files that could not be decompiled.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `out_dir` - The path the files would be extracted to
+    /// * `decompile` - The endian type to decompile dct, bin and clb files with, like
+    ///   `extract_decompiled`, or `None` to plan writing the data as is, like `extract`
+    /// 
+    /// # Returns
+    /// 
+    /// * `ExtractPlan` - The files and the problems found. Decompiled files are converted to find
+    ///   their size, so this takes about as long as the conversion does
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::packfile::{EndianDependentString, Packfile, VirtualFile};
+    /// let files = vec![VirtualFile::from_file("a/Icon.txt", vec![0; 3]), VirtualFile::from_file("A/icon.txt", vec![0; 4])];
+    /// let packfile = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, files);
+    /// let plan = packfile.plan_extract("out", None);
+    /// assert_eq!(plan.files[0].out_path, "out/a/Icon.txt");
+    /// assert_eq!(plan.total_size(), 7);
+    /// assert_eq!(plan.collisions[0].virtual_paths, ["a/Icon.txt", "A/icon.txt"]);
+    /// 
+    /// let files = vec![VirtualFile::from_file("a/b.txt", vec![0; 3]), VirtualFile::from_file("A/B.txt/c.txt", vec![0; 4])];
+    /// let packfile = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, files);
+    /// let plan = packfile.plan_extract("out", None);
+    /// assert_eq!(plan.directory_clashes[0].virtual_path, "a/b.txt");
+    /// assert_eq!(plan.directory_clashes[0].nested_virtual_paths, ["A/B.txt/c.txt"]);
+    /// ```
+    pub fn plan_extract(&self, out_dir: &str, decompile: Option<file_manipulator::EndianType>) -> ExtractPlan {
+        let mut plan = ExtractPlan::default();
+        // for each case folded path, the first file with it and its collision once a second file has it
+        let mut seen: std::collections::HashMap<String, (usize, Option<usize>)> = std::collections::HashMap::new();
+        for virtual_file in &self.files {
+            let (out_path, data) = match extracted_contents(out_dir, virtual_file, decompile) {
+                Ok(contents) => contents,
+                Err(error) => {
+                    let reason = match error {
+                        PackfileError::Decompile(reason) => reason,
+                        error => error.to_string()
+                    };
+                    plan.failures.push(PlannedFailure { virtual_path: virtual_file.path.clone(), reason });
+                    continue;
+                }
+            };
+            match seen.get_mut(&normalized_path(&out_path)) {
+                Some((_, Some(collision))) => plan.collisions[*collision].virtual_paths.push(virtual_file.path.clone()),
+                Some((first, collision)) => {
+                    let first = &plan.files[*first];
+                    plan.collisions.push(ExtractCollision { out_path: first.out_path.clone(), virtual_paths: vec![first.virtual_path.clone(), virtual_file.path.clone()] });
+                    *collision = Some(plan.collisions.len() - 1);
+                },
+                None => {
+                    seen.insert(normalized_path(&out_path), (plan.files.len(), None));
+                }
             }
-            let mut file = File::create(file_path).unwrap();
-            file.write_all(&data).unwrap();
+            plan.files.push(PlannedFile { virtual_path: virtual_file.path.clone(), out_path, size: data.len() as u64 });
         }
+        // a file whose path is a directory above another file, checked against every directory below out_dir
+        let root_length = normalized_path(out_dir.trim_end_matches('/')).len();
+        for planned_file in &plan.files {
+            let out_path = normalized_path(&planned_file.out_path);
+            let directories = out_path.match_indices('/').map(|(index, _)| &out_path[..index]).filter(|directory| directory.len() > root_length);
+            for directory in directories {
+                let Some((first, _)) = seen.get(directory) else { continue };
+                let file = &plan.files[*first];
+                match plan.directory_clashes.iter_mut().find(|clash| clash.virtual_path == file.virtual_path) {
+                    Some(clash) => clash.nested_virtual_paths.push(planned_file.virtual_path.clone()),
+                    None => plan.directory_clashes.push(DirectoryClash {
+                        out_path: file.out_path.clone(),
+                        virtual_path: file.virtual_path.clone(),
+                        nested_virtual_paths: vec![planned_file.virtual_path.clone()]
+                    })
+                }
+            }
+        }
+        return plan;
+    }
+
+    /// Extract the files with one of the given extensions to a directory, decompiling them like
+    /// `extract_decompiled` does. Existing files are overwritten.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The path to extract the files to
+    /// * `endian_type` - The endian type to use
+    /// * `extensions` - The extensions to extract, case insensitive and with or without the dot
+    ///   (e.g. `&["dct"]`)
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Vec<ExtractError>, PackfileError>` - See `extract_filtered`
+    pub fn extract_by_extension(&self, path: String, endian_type: file_manipulator::EndianType, extensions: &[&str]) -> Result<Vec<ExtractError>, PackfileError> {
+        let extensions: Vec<String> = extensions.iter().map(|extension| extension.trim_start_matches('.').to_lowercase()).collect();
+        return self.extract_filtered(path, endian_type, true, &|virtual_file| {
+            let file_name = virtual_file.path.rsplit('/').next().unwrap();
+            return match file_name.rsplit_once('.') {
+                Some((_, extension)) => extensions.contains(&extension.to_lowercase()),
+                None => false
+            };
+        });
+    }
+
+    /// Extract a single VirtualFile to a file. Only that file's data is decompressed.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `virtual_path` - The path of the VirtualFile (case insensitive, `\` or `/` separators)
+    /// * `out_path` - The path of the file to write, its directory is created if needed
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<(), PackfileError>` - `FileNotFound` if no VirtualFile has the path, `Decompress`
+    ///   if its data is corrupt
+    pub fn extract_one(&self, virtual_path: &str, out_path: &str) -> Result<(), PackfileError> {
+        let virtual_file = self.get_file(virtual_path).ok_or(PackfileError::FileNotFound(virtual_path.to_string()))?;
+        return write_extracted_file(out_path, virtual_file.try_data()?);
+    }
+
+    /// Extract a single VirtualFile to a file, converting dct, bin and clb files to JSON like
+    /// `extract_decompiled` does. Other files are written as is.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `virtual_path` - The path of the VirtualFile (case insensitive, `\` or `/` separators)
+    /// * `out_path` - The path of the file to write, its directory is created if needed
+    /// * `endian_type` - The endian type of the file's data
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<(), PackfileError>` - `FileNotFound` if no VirtualFile has the path, `Decompress`
+    ///   if its data is corrupt, `Decompile` if it can't be read as the format its extension names
+    pub fn extract_one_decompiled(&self, virtual_path: &str, out_path: &str, endian_type: file_manipulator::EndianType) -> Result<(), PackfileError> {
+        let virtual_file = self.get_file(virtual_path).ok_or(PackfileError::FileNotFound(virtual_path.to_string()))?;
+        let extension = virtual_file.path.rsplit('.').next().unwrap().to_lowercase();
+        let data = virtual_file.try_data()?;
+        return match asset::try_decompile_by_extension(&extension, data, endian_type) {
+            Some(Ok(json)) => write_extracted_file(out_path, json.as_bytes()),
+            Some(Err(error)) => Err(PackfileError::Decompile(error.to_string())),
+            None => write_extracted_file(out_path, data)
+        };
     }
 
     /// Pack the Packfile into a binary file.
@@ -520,7 +1576,8 @@ impl Packfile {
         file.write_all(&self.to_binary(endian_type)).unwrap();
     }
 
-    /// Get the Packfile as a dictionary without the data.
+    /// Get the Packfile as a dictionary without the data. The header size, alignment and string
+    /// table sharing are included so the layout can be packed back the same way.
     /// 
     /// # Returns
     /// 
@@ -529,6 +1586,12 @@ impl Packfile {
         let mut dict = serde_json::Map::new();
         dict.insert("magic".to_string(), serde_json::Value::String(self.magic.text.clone()));
         dict.insert("version".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.version as f64).unwrap()));
+        dict.insert("header_size".to_string(), serde_json::Value::from(self.header_size));
+        dict.insert("alignment".to_string(), serde_json::Value::from(self.alignment));
+        dict.insert("shared_string_pool".to_string(), serde_json::Value::Bool(self.shared_string_pool));
+        if self.name_encoding == file_manipulator::StringEncoding::Windows1252 {
+            dict.insert("name_encoding".to_string(), serde_json::Value::String("windows-1252".to_string()));
+        }
         let mut files = Vec::new();
         for virtual_file in &self.files {
             files.push(virtual_file.to_dict_stripped());
@@ -537,6 +1600,27 @@ impl Packfile {
         return serde_json::Value::Object(dict);
     }
 
+    /// Get the Packfile as a dictionary without the data, but with the sizes and a CRC32 of every
+    /// file, so two archives can be compared without extracting them.
+    /// 
+    /// # Returns
+    /// 
+    /// * `serde_json::Value` - The Packfile as a dictionary
+    pub fn to_dict_manifest(&self) -> serde_json::Value {
+        let mut dict = self.to_dict_stripped();
+        dict["files"] = serde_json::Value::Array(self.files.iter().map(|virtual_file| virtual_file.to_dict_manifest_with_alignment(self.alignment)).collect());
+        return dict;
+    }
+
+    /// Get the Packfile as a JSON string without the data, but with file sizes and CRC32s.
+    /// 
+    /// # Returns
+    /// 
+    /// * `String` - The Packfile as a JSON string
+    pub fn to_json_manifest(&self) -> String {
+        return serde_json::to_string_pretty(&self.to_dict_manifest()).unwrap();
+    }
+
     /// Get the Packfile as a JSON string without the data.
     /// 
     /// # Returns
@@ -566,27 +1650,122 @@ impl Packfile {
     /// 
     /// * `Packfile` - The created Packfile
     pub fn from_binary(data: Vec<u8>) -> Self {
-        // if the first 4 bytes are "PAK ", then the endian is little, otherwise it is big
-        // get the first 4 bytes
-        let first_4_bytes = data[0..4].to_vec();
-        let mut endian_type = file_manipulator::EndianType::BIG;
-        if first_4_bytes == "PAK ".as_bytes() {
-            endian_type = file_manipulator::EndianType::LITTLE;
-        } else if first_4_bytes == " KAP".as_bytes() {
-            endian_type = file_manipulator::EndianType::BIG;
-        } else {
-            panic!("Invalid magic: {:?}", first_4_bytes);
-        }
+        return Packfile::from_binary_with_alignment(data, DEFAULT_ALIGNMENT);
+    }
+
+    /// Get a Packfile whose data blocks use a different alignment from binary data.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The binary data
+    /// * `alignment` - The alignment of the data blocks in bytes
+    /// 
+    /// # Returns
+    /// 
+    /// * `Packfile` - The created Packfile
+    pub fn from_binary_with_alignment(data: Vec<u8>, alignment: u32) -> Self {
+        return match Packfile::try_from_binary_with_alignment(data, alignment) {
+            Ok(packfile) => packfile,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `from_binary`, but returns an error instead of panicking when the header is invalid or
+    /// the file table describes data past the end of the archive. Extra bytes after the last data
+    /// block are not an error, they are counted in `trailing_bytes`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The binary data
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Packfile, PackfileError>` - The created Packfile, or `InvalidMagic`,
+    ///   `UnsupportedVersion`, `InvalidHeader` or `TruncatedArchive`
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::packfile::{CompressionKind, EndianDependentString, Packfile, PackfileError, VirtualFile};
+    /// let file = VirtualFile::new(EndianDependentString::new(" NIB".to_string()), CompressionKind::None, 0, "a.bin".to_string(), vec![7; 40]);
+    /// let mut data = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, vec![file]).to_binary(EndianType::BIG);
+    /// 
+    /// data.extend_from_slice(&[0; 5]);
+    /// assert_eq!(Packfile::try_from_binary(data.clone()).unwrap().trailing_bytes, 5);
+    /// 
+    /// data.truncate(data.len() - 40);
+    /// assert!(matches!(Packfile::try_from_binary(data.clone()), Err(PackfileError::TruncatedArchive { .. })));
+    /// 
+    /// assert!(matches!(Packfile::try_from_binary(data[..3].to_vec()), Err(PackfileError::TruncatedArchive { .. })));
+    /// data[7] = 3;
+    /// assert!(matches!(Packfile::try_from_binary(data.clone()), Err(PackfileError::UnsupportedVersion(3))));
+    /// data[0] = b'X';
+    /// assert!(matches!(Packfile::try_from_binary(data), Err(PackfileError::InvalidMagic(_))));
+    /// ```
+    pub fn try_from_binary(data: Vec<u8>) -> Result<Self, PackfileError> {
+        return Packfile::try_from_binary_with_alignment(data, DEFAULT_ALIGNMENT);
+    }
+
+    /// Like `from_binary_with_alignment`, but returns an error, see `try_from_binary`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data` - The binary data
+    /// * `alignment` - The alignment of the data blocks in bytes
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Packfile, PackfileError>` - The created Packfile, or the error, see
+    ///   `try_from_binary`
+    pub fn try_from_binary_with_alignment(data: Vec<u8>, alignment: u32) -> Result<Self, PackfileError> {
+        let endian_type = endian_from_magic(&data)?;
 
         let mut fm = file_manipulator::FileManipulator::new(
             data,
             endian_type,
             file_manipulator::WriteMode::OVERWRITE
         );
+        // some older archives have Windows-1252 file names, don't fail the whole parse on them
+        fm.set_string_encoding(file_manipulator::StringEncoding::Utf8OrWindows1252);
+
+        let mut packfile = Packfile::new(EndianDependentString::new("".to_string()), 0, vec![]);
+        packfile.set_alignment(alignment);
+        packfile.try_unpack(&mut fm)?;
+        return Ok(packfile);
+    }
 
+    /// Open a binary file (*.pak) without reading it into memory. Only the header and the file
+    /// table are read; the data of a file is copied out of the mapping when it is first used.
+    /// 
+    /// The file is memory-mapped, so it must stay on disk and unchanged for as long as the
+    /// returned Packfile, or any VirtualFile taken out of it, is alive. Needs the `mmap` feature.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The path to the binary file
+    /// 
+    /// # Returns
+    /// 
+    /// * `Result<Packfile, PackfileError>` - The created Packfile, or the error opening, mapping
+    ///   or reading the file, see `try_from_binary`
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap(path: &str) -> Result<Self, PackfileError> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only, and callers are told to leave the file alone while it is mapped
+        let map = std::sync::Arc::new(unsafe { memmap2::Mmap::map(&file)? });
+        let endian_type = endian_from_magic(&map)?;
+        // only the header and file table are copied, they end where the data starts
+        let header_size = file_manipulator::read_u32_at(&map, 12, endian_type).unwrap_or(0);
+        let data_pointer = file_manipulator::read_u32_at(&map, 16, endian_type).unwrap_or(0);
+        let table_end = (data_pointer as usize + header_size as usize).min(map.len());
+        let mut fm = file_manipulator::FileManipulator::new(map[..table_end].to_vec(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        fm.set_string_encoding(file_manipulator::StringEncoding::Utf8OrWindows1252);
         let mut packfile = Packfile::new(EndianDependentString::new("".to_string()), 0, vec![]);
-        packfile.unpack(&mut fm);
-        return packfile;
+        let entries = packfile.unpack_table(&mut fm)?;
+        packfile.trailing_bytes = check_data_section(&entries, map.len())?;
+        packfile.files = entries.iter().map(|entry| entry.load_mapped(&map)).collect();
+        return Ok(packfile);
     }
 
     /// Get a Packfile from a binary file (*.pak).
@@ -622,7 +1801,7 @@ impl Packfile {
         let fixed_path = path.replace("\\", "/").to_lowercase();
         for virtual_file in &self.files {
             if virtual_file.path.to_lowercase() == fixed_path {
-                return virtual_file.data.clone();
+                return virtual_file.data().to_vec();
             }
         }
         // if the file does not exist, panic
@@ -639,11 +1818,504 @@ impl Packfile {
         let fixed_path = path.replace("\\", "/").to_lowercase();
         for virtual_file in &mut self.files {
             if virtual_file.path.to_lowercase() == fixed_path {
-                virtual_file.data = data.clone();
+                virtual_file.set_data(data.clone());
                 return;
             }
         }
         // if the file does not exist, panic
         panic!("VirtualFile not found: {}", path);
     }
-}
\ No newline at end of file
+
+    /// Get a VirtualFile from a path, matched the same way as in `get_data_from_path`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The path of the VirtualFile
+    /// 
+    /// # Returns
+    /// 
+    /// * `Option<&VirtualFile>` - The VirtualFile, or `None` if there is no file at the path
+    pub fn get_file(&self, path: &str) -> Option<&VirtualFile> {
+        let fixed_path = path.replace("\\", "/").to_lowercase();
+        return self.files.iter().find(|virtual_file| virtual_file.path.to_lowercase() == fixed_path);
+    }
+
+    /// Parse a scene file in the archive straight from its stored data, without copying it first.
+    /// Panics if the data is not a valid scene file.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The path of the VirtualFile, matched the same way as in `get_data_from_path`
+    /// * `endian_type` - The endian type of the scene file
+    /// 
+    /// # Returns
+    /// 
+    /// * `Option<SceneFile>` - The scene file, or None if there is no file at the path
+    pub fn get_scene_file(&self, path: &str, endian_type: file_manipulator::EndianType) -> Option<scene_file::SceneFile> {
+        return self.get_file(path).map(|virtual_file| scene_file::SceneFile::from_binary(virtual_file.data(), endian_type));
+    }
+
+    /// Get a mutable VirtualFile from a path, matched the same way as in `get_data_from_path`.
+    /// Use it to change the type, compression or compression level of a file in place.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `path` - The path of the VirtualFile
+    /// 
+    /// # Returns
+    /// 
+    /// * `Option<&mut VirtualFile>` - The VirtualFile, or `None` if there is no file at the path
+    pub fn get_file_mut(&mut self, path: &str) -> Option<&mut VirtualFile> {
+        let fixed_path = path.replace("\\", "/").to_lowercase();
+        return self.files.iter_mut().find(|virtual_file| virtual_file.path.to_lowercase() == fixed_path);
+    }
+
+    /// Get the paths of the files in the archive, in table order.
+    /// 
+    /// # Returns
+    /// 
+    /// * `Vec<&str>` - The paths
+    pub fn paths(&self) -> Vec<&str> {
+        return self.files.iter().map(|virtual_file| virtual_file.path.as_str()).collect();
+    }
+
+    /// Group the file names by directory, split the same way as in `VirtualFile::get_split_path`.
+    /// Files at the root of the archive are under `""`.
+    /// 
+    /// # Returns
+    /// 
+    /// * `BTreeMap<String, Vec<String>>` - The file names in each directory, in table order
+    pub fn tree(&self) -> std::collections::BTreeMap<String, Vec<String>> {
+        let mut tree: std::collections::BTreeMap<String, Vec<String>> = std::collections::BTreeMap::new();
+        for virtual_file in &self.files {
+            let mut split_path = virtual_file.get_split_path();
+            let file_name = split_path.pop().unwrap();
+            let directory = split_path.pop().unwrap();
+            tree.entry(directory).or_default().push(file_name);
+        }
+        return tree;
+    }
+
+    /// Get the paths that contain a substring, ignoring case.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `substring` - The text to look for
+    /// 
+    /// # Returns
+    /// 
+    /// * `Vec<&str>` - The matching paths, in table order
+    pub fn find_paths(&self, substring: &str) -> Vec<&str> {
+        let substring = substring.to_lowercase();
+        return self.paths().into_iter().filter(|path| path.to_lowercase().contains(&substring)).collect();
+    }
+
+    /// Compare the files of two Packfiles by path. A file whose data is the same but whose
+    /// compression settings changed is only in `metadata_changed`. Files are reported in table
+    /// order; with duplicate paths only the first of each is compared.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `other` - The Packfile to compare with
+    /// 
+    /// # Returns
+    /// 
+    /// * `PackfileDiff` - The files that differ
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::packfile::{CompressionKind, EndianDependentString, Packfile, VirtualFile};
+    /// let file = |path: &str, compression: CompressionKind, data: Vec<u8>| VirtualFile::new(EndianDependentString::new(" NIB".to_string()), compression, 6, path.to_string(), data);
+    /// let before = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, vec![
+    ///     file("Scenes/A.bin", CompressionKind::Zlib, vec![1]),
+    ///     file("Scenes/B.bin", CompressionKind::Zlib, vec![2]),
+    /// ]);
+    /// let after = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, vec![
+    ///     file("scenes\\a.bin", CompressionKind::None, vec![1]),
+    ///     file("Scenes/C.bin", CompressionKind::Zlib, vec![3]),
+    /// ]);
+    /// let diff = before.diff(&after);
+    /// assert_eq!(diff.only_in_self, ["Scenes/B.bin"]);
+    /// assert_eq!(diff.only_in_other, ["Scenes/C.bin"]);
+    /// assert!(diff.data_changed.is_empty());
+    /// assert_eq!(diff.metadata_changed, ["scenes\\a.bin"]);
+    /// assert!(before.diff(&before).is_empty());
+    /// ```
+    pub fn diff(&self, other: &Packfile) -> PackfileDiff {
+        let mut diff = PackfileDiff::default();
+        let mut other_files: std::collections::HashMap<String, &VirtualFile> = std::collections::HashMap::new();
+        for virtual_file in &other.files {
+            other_files.entry(normalized_path(&virtual_file.path)).or_insert(virtual_file);
+        }
+        let mut own_paths = std::collections::HashSet::new();
+        for virtual_file in &self.files {
+            let path = normalized_path(&virtual_file.path);
+            if !own_paths.insert(path.clone()) {
+                continue;
+            }
+            match other_files.get(&path) {
+                Some(other_file) => {
+                    if virtual_file.data() != other_file.data() {
+                        diff.data_changed.push(other_file.path.clone());
+                    }
+                    if virtual_file.type_ != other_file.type_ || virtual_file.compression != other_file.compression || virtual_file.compression_level != other_file.compression_level {
+                        diff.metadata_changed.push(other_file.path.clone());
+                    }
+                },
+                None => diff.only_in_self.push(virtual_file.path.clone())
+            }
+        }
+        for virtual_file in &other.files {
+            // inserting marks the path as seen, so a duplicate is only listed once
+            if own_paths.insert(normalized_path(&virtual_file.path)) {
+                diff.only_in_other.push(virtual_file.path.clone());
+            }
+        }
+        return diff;
+    }
+
+    /// Compress every file to see how well the archive compresses. Each file is compressed once.
+    /// 
+    /// # Returns
+    /// 
+    /// * `CompressionReport` - The sizes of each file and their totals
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::packfile::{CompressionKind, EndianDependentString, Packfile, VirtualFile};
+    /// let files = vec![
+    ///     VirtualFile::new(EndianDependentString::new(" NIB".to_string()), CompressionKind::Zlib, 9, "a.bin".to_string(), vec![0; 1000]),
+    ///     VirtualFile::new(EndianDependentString::new(" NIB".to_string()), CompressionKind::None, 0, "b.bin".to_string(), vec![1; 40]),
+    /// ];
+    /// let packfile = Packfile::new(EndianDependentString::new(" KAP".to_string()), 2, files);
+    /// let report = packfile.compression_report();
+    /// assert_eq!(report.real_size, 1040);
+    /// assert_eq!(report.files[1].aligned_size, 64);
+    /// assert!(report.files[0].ratio() < 0.1);
+    /// assert_eq!(report.savings(), 1040 - report.aligned_size as i64);
+    /// ```
+    pub fn compression_report(&self) -> CompressionReport {
+        let files: Vec<FileCompression> = self.files.iter().map(|virtual_file| virtual_file.get_file_compression(self.alignment)).collect();
+        return CompressionReport {
+            real_size: files.iter().map(|file| file.real_size as u64).sum(),
+            compressed_size: files.iter().map(|file| file.compressed_size as u64).sum(),
+            aligned_size: files.iter().map(|file| file.aligned_size as u64).sum(),
+            files
+        };
+    }
+}
+
+// a file table entry whose data has not been read yet
+struct TableEntry {
+    type_: EndianDependentString,
+    path: String,
+    data_offset: usize,
+    real_data_size: u32,
+    compressed_data_size: u32,
+    aligned_data_size: u32
+}
+
+impl TableEntry {
+    // copy the data out of the whole archive, it is decompressed when it is first used
+    fn load(&self, archive: &[u8]) -> VirtualFile {
+        let data = archive[self.data_range()].to_vec();
+        let compression = self.compression(&data);
+        return VirtualFile::from_stored(EndianDependentString::new(self.type_.text.clone()), compression, self.path.clone(), StoredData::Owned(data));
+    }
+
+    // keep a range of the mapping, it is only read when the data is first used
+    #[cfg(feature = "mmap")]
+    fn load_mapped(&self, map: &std::sync::Arc<memmap2::Mmap>) -> VirtualFile {
+        let compression = self.compression(&map[self.data_range()]);
+        return VirtualFile::from_stored(EndianDependentString::new(self.type_.text.clone()), compression, self.path.clone(), StoredData::Mapped(map.clone(), self.data_range()));
+    }
+
+    fn data_range(&self) -> std::ops::Range<usize> {
+        return self.data_offset..self.data_offset + self.compressed_data_size as usize;
+    }
+
+    fn compression(&self, data: &[u8]) -> CompressionKind {
+        if self.compressed_data_size != self.real_data_size {
+            return CompressionKind::detect(data);
+        }
+        return CompressionKind::None;
+    }
+}
+
+// make sure the data the table describes is in the archive, and count the bytes after the last block
+fn check_data_section(entries: &[TableEntry], archive_size: usize) -> Result<usize, PackfileError> {
+    let data_end = entries.iter().map(|entry| entry.data_offset + entry.compressed_data_size as usize).max().unwrap_or(0);
+    if data_end > archive_size {
+        return Err(PackfileError::TruncatedArchive { expected: data_end, actual: archive_size });
+    }
+    let aligned_end = entries.iter().map(|entry| entry.data_offset + entry.aligned_data_size as usize).max().unwrap_or(archive_size);
+    return Ok(archive_size.saturating_sub(aligned_end));
+}
+
+// if the first 4 bytes are "PAK ", then the endian is little, if they are " KAP" it is big
+fn endian_from_magic(data: &[u8]) -> Result<file_manipulator::EndianType, PackfileError> {
+    let first_4_bytes = data.get(0..4).ok_or(PackfileError::TruncatedArchive { expected: 4, actual: data.len() })?;
+    if first_4_bytes.iter().rev().eq(PACKFILE_MAGIC.as_bytes()) {
+        return Ok(file_manipulator::EndianType::LITTLE);
+    } else if first_4_bytes == PACKFILE_MAGIC.as_bytes() {
+        return Ok(file_manipulator::EndianType::BIG);
+    }
+    return Err(PackfileError::InvalidMagic(first_4_bytes.to_vec()));
+}
+
+// the offset of a name in the string table, which must be in the archive and end with a null byte
+fn name_offset(data: &[u8], string_pointer: usize, pointer: u32) -> Result<usize, PackfileError> {
+    let offset = string_pointer + pointer as usize;
+    if !data.get(offset..).is_some_and(|name| name.contains(&0)) {
+        return Err(PackfileError::InvalidHeader(format!("the name at {} is not in the string table", offset)));
+    }
+    return Ok(offset);
+}
+
+/// Parses a packfile and packs it again with `endian_type`, returning the offset of the first
+/// byte where the output differs from `data`. The compression level of every entry is taken to be
+/// 6, so archives compressed at another level differ from the first compressed entry on.
+pub fn assert_roundtrip(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<(), usize> {
+    let repacked = Packfile::from_binary(data.to_vec()).to_binary(endian_type);
+    return match asset::first_difference(data, &repacked) {
+        Some(offset) => Err(offset),
+        None => Ok(())
+    };
+}
+
+// paths in a packfile are matched ignoring case and slash direction
+fn normalized_path(path: &str) -> String {
+    return path.replace("\\", "/").to_lowercase();
+}
+
+// where a file is extracted to under root, without an empty directory part for root-level files
+fn extracted_file_path(root: &str, virtual_file: &VirtualFile) -> String {
+    let split_path = virtual_file.get_split_path();
+    let root = root.trim_end_matches('/');
+    if split_path[0].is_empty() {
+        return format!("{}/{}", root, split_path[1]);
+    }
+    return format!("{}/{}/{}", root, split_path[0], split_path[1]);
+}
+
+// the path and data a file is extracted as, dct, bin and clb files are decompiled to json if an endian type is given
+fn extracted_contents<'a>(root: &str, virtual_file: &'a VirtualFile, decompile: Option<file_manipulator::EndianType>) -> Result<(String, std::borrow::Cow<'a, [u8]>), PackfileError> {
+    let file_path = extracted_file_path(root, virtual_file);
+    let data = virtual_file.try_data()?;
+    if let Some(endian_type) = decompile {
+        let extension = virtual_file.path.split(".").collect::<Vec<&str>>().pop().unwrap().to_lowercase();
+        match asset::try_decompile_by_extension(&extension, data, endian_type) {
+            Some(Ok(json)) => return Ok((file_path + ".json", std::borrow::Cow::Owned(json.into_bytes()))),
+            Some(Err(error)) => return Err(PackfileError::Decompile(error.to_string())),
+            None => {}
+        }
+    }
+    return Ok((file_path, std::borrow::Cow::Borrowed(data)));
+}
+
+// whether the null terminated name at offset is valid UTF-8
+fn is_utf8_name(data: &[u8], offset: usize) -> bool {
+    let name = data.get(offset..).unwrap_or(&[]);
+    let end = name.iter().position(|byte| *byte == 0).unwrap_or(name.len());
+    return std::str::from_utf8(&name[..end]).is_ok();
+}
+
+fn write_extracted_file(path: &str, data: &[u8]) -> Result<(), PackfileError> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::write(path, data)?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_manipulator::EndianType;
+
+    // made-up tags, the game's aren't known
+    const TEST_FILE_TYPES: &[FileType] = &[
+        FileType { extension: "txt", type_: " TXT", compression: CompressionKind::Zlib },
+        FileType { extension: "bin", type_: " NIB", compression: CompressionKind::Zlib },
+        FileType { extension: "clb", type_: " BLC", compression: CompressionKind::Zlib },
+    ];
+
+    fn sample_packfile() -> Packfile {
+        let files = vec![
+            VirtualFile::from_file_with_types("root.txt", b"root".to_vec(), TEST_FILE_TYPES),
+            VirtualFile::from_file_with_types("Environments/_Test/Scene.bin", vec![7; 100], TEST_FILE_TYPES),
+            VirtualFile::new(EndianDependentString::new(" TXT".to_string()), CompressionKind::None, 0, "Environments/_Test/raw.txt".to_string(), b"raw".to_vec()),
+        ];
+        return Packfile::new(EndianDependentString::new(PACKFILE_MAGIC.to_string()), 2, files);
+    }
+
+    #[test]
+    fn packs_both_endians() {
+        let packfile = sample_packfile();
+        let big = packfile.to_binary(EndianType::BIG);
+        let little = packfile.to_binary(EndianType::LITTLE);
+        assert_eq!(&big[0..4], b" KAP");
+        assert_eq!(&little[0..4], b"PAK ");
+        assert_eq!(big.len(), little.len());
+
+        let from_big = Packfile::from_binary(big.clone());
+        let from_little = Packfile::from_binary(little.clone());
+        assert!(from_big == packfile);
+        assert!(from_little == packfile);
+        for (big_file, little_file) in from_big.files.iter().zip(&from_little.files) {
+            assert_eq!(big_file.type_.text, little_file.type_.text);
+            assert_eq!(big_file.compression, little_file.compression);
+        }
+        assert_eq!(from_big.to_binary(EndianType::BIG), big);
+        assert_eq!(from_little.to_binary(EndianType::LITTLE), little);
+        assert_eq!(from_big.to_binary(EndianType::LITTLE), little);
+    }
+
+    #[test]
+    fn decompresses_files_on_first_use() {
+        let data = sample_packfile().to_binary(EndianType::BIG);
+        let mut packfile = Packfile::from_binary(data);
+        assert!(packfile.files.iter().all(|virtual_file| !virtual_file.is_decompressed()));
+
+        let path = packfile.files[0].path.clone();
+        let expected = sample_packfile().files[0].data().to_vec();
+        assert_eq!(packfile.get_data_from_path(path), expected);
+        assert!(packfile.files[0].is_decompressed());
+        assert!(packfile.files[1..].iter().all(|virtual_file| !virtual_file.is_decompressed()));
+
+        packfile.files[1].data_mut().push(1);
+        assert_eq!(packfile.files[1].get_real_data_size(), sample_packfile().files[1].get_real_data_size() + 1);
+    }
+
+    #[test]
+    fn corrupt_files_report_decompression_errors() {
+        let mut data = sample_packfile().to_binary(EndianType::BIG);
+        let compressed = sample_packfile().files[1].get_compressed_data();
+        let start = data.windows(compressed.len()).position(|window| window == compressed).unwrap();
+        // break the checksum at the end of the zlib stream
+        data[start + compressed.len() - 1] ^= 0xFF;
+        let packfile = Packfile::from_binary(data);
+        let path = packfile.files[1].path.clone();
+        assert!(matches!(packfile.files[1].try_data(), Err(PackfileError::Decompress(error_path, _)) if error_path == path));
+        assert!(!packfile.files[1].is_decompressed());
+        assert!(matches!(packfile.extract_one(&path, "unused"), Err(PackfileError::Decompress(..))));
+        assert_eq!(packfile.files[0].try_data().unwrap(), b"root");
+    }
+
+    #[test]
+    fn extraction_reports_files_that_fail_to_decompile() {
+        // the collectible count says 9, but no collectibles follow
+        let files = vec![
+            VirtualFile::from_file_with_types("Data/broken.clb", vec![0, 0, 0, 2, 0, 0, 0, 9], TEST_FILE_TYPES),
+            VirtualFile::from_file_with_types("Data/notes.txt", b"notes".to_vec(), TEST_FILE_TYPES),
+        ];
+        let packfile = Packfile::new(EndianDependentString::new(PACKFILE_MAGIC.to_string()), 2, files);
+        let out_dir = std::env::temp_dir().join(format!("epic_mickey_extract_{}", std::process::id()));
+        let out_path = out_dir.to_str().unwrap().to_string();
+
+        let errors = packfile.extract_decompiled(out_path.clone(), EndianType::BIG, true).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "Data/broken.clb");
+        assert!(matches!(errors[0].error, PackfileError::Decompile(_)));
+        assert!(out_dir.join("Data/notes.txt").exists());
+        assert!(!out_dir.join("Data/broken.clb.json").exists());
+
+        let one = packfile.extract_one_decompiled("Data/broken.clb", &format!("{}/one.json", out_path), EndianType::BIG);
+        assert!(matches!(one, Err(PackfileError::Decompile(_))));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn names_pack_back_in_the_encoding_they_were_read_in() {
+        let files = vec![VirtualFile::from_file_with_types("Café/Menu.txt", b"menu".to_vec(), TEST_FILE_TYPES)];
+        let mut packfile = Packfile::new(EndianDependentString::new(PACKFILE_MAGIC.to_string()), 2, files);
+        let utf8 = packfile.to_binary(EndianType::BIG);
+        assert!(Packfile::from_binary(utf8.clone()).name_encoding == file_manipulator::StringEncoding::Utf8);
+
+        packfile.name_encoding = file_manipulator::StringEncoding::Windows1252;
+        let windows_1252 = packfile.to_binary(EndianType::BIG);
+        assert_eq!(windows_1252.windows(5).filter(|window| window == b"Caf\xE9\0").count(), 1);
+        let unpacked = Packfile::from_binary(windows_1252.clone());
+        assert!(unpacked.name_encoding == file_manipulator::StringEncoding::Windows1252);
+        assert_eq!(unpacked.paths(), ["Café/Menu.txt"]);
+        assert_eq!(unpacked.to_binary(EndianType::BIG), windows_1252);
+    }
+
+    #[test]
+    fn plans_report_failures_and_directory_clashes() {
+        let files = vec![
+            VirtualFile::from_file_with_types("Data/broken.clb", vec![0, 0, 0, 2, 0, 0, 0, 9], TEST_FILE_TYPES),
+            VirtualFile::from_file_with_types("Data/notes.txt", b"notes".to_vec(), TEST_FILE_TYPES),
+            VirtualFile::from_file_with_types("data/notes.txt/old.txt", b"old".to_vec(), TEST_FILE_TYPES),
+            VirtualFile::from_file_with_types("Data/Notes.txt/older.txt", b"older".to_vec(), TEST_FILE_TYPES),
+        ];
+        let packfile = Packfile::new(EndianDependentString::new(PACKFILE_MAGIC.to_string()), 2, files);
+
+        let plan = packfile.plan_extract("out/", Some(EndianType::BIG));
+        assert_eq!(plan.failures.len(), 1);
+        assert_eq!(plan.failures[0].virtual_path, "Data/broken.clb");
+        assert_eq!(plan.files.len(), 3);
+        assert_eq!(plan.total_size(), 13);
+        assert_eq!(plan.directory_clashes, [DirectoryClash {
+            out_path: "out/Data/notes.txt".to_string(),
+            virtual_path: "Data/notes.txt".to_string(),
+            nested_virtual_paths: vec!["data/notes.txt/old.txt".to_string(), "Data/Notes.txt/older.txt".to_string()]
+        }]);
+
+        // without decompiling, the broken file is planned as it is
+        let plan = packfile.plan_extract("out", None);
+        assert!(plan.failures.is_empty());
+        assert_eq!(plan.files.len(), 4);
+    }
+
+    #[test]
+    fn compresses_once_until_the_data_changes() {
+        let virtual_file = VirtualFile::from_file_with_types("Data/notes.txt", vec![7; 1000], TEST_FILE_TYPES);
+        let compressions = || COMPRESSIONS.with(|compressions| compressions.get());
+        let before = compressions();
+        let size = virtual_file.get_compressed_data_size();
+        assert_eq!(compressions(), before + 1);
+        assert_eq!(virtual_file.get_compressed_data_size(), size);
+        assert_eq!(virtual_file.get_aligned_data_size(), size.next_multiple_of(DEFAULT_ALIGNMENT));
+        let packfile = Packfile::new(EndianDependentString::new(PACKFILE_MAGIC.to_string()), 2, vec![virtual_file]);
+        packfile.compression_report();
+        packfile.to_dict_manifest();
+        packfile.to_binary(EndianType::BIG);
+        assert_eq!(compressions(), before + 1);
+
+        let mut virtual_file = packfile.files.into_iter().next().unwrap();
+        virtual_file.compression_level = 9;
+        virtual_file.get_compressed_data_size();
+        assert_eq!(compressions(), before + 2);
+        virtual_file.data_mut().push(1);
+        virtual_file.get_compressed_data_size();
+        virtual_file.set_data(vec![1; 10]);
+        virtual_file.get_compressed_data_size();
+        assert_eq!(compressions(), before + 4);
+    }
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mapped_packfiles_pack_back_byte_for_byte() {
+        let mut packfile = sample_packfile();
+        // a folder with the name of a file, so the shared string pool has something to share
+        packfile.files.push(VirtualFile::from_file("Café", b"menu".to_vec()));
+        packfile.files.push(VirtualFile::from_file("Café/Café", b"menu".to_vec()));
+        packfile.name_encoding = file_manipulator::StringEncoding::Windows1252;
+        packfile.set_shared_string_pool(true);
+        let path = std::env::temp_dir().join(format!("epic_mickey_mmap_{}.pak", std::process::id()));
+        for endian_type in [EndianType::BIG, EndianType::LITTLE] {
+            let data = packfile.to_binary(endian_type);
+            std::fs::write(&path, &data).unwrap();
+            let mapped = Packfile::from_mmap(path.to_str().unwrap()).unwrap();
+            assert!(mapped.shared_string_pool);
+            assert!(mapped.name_encoding == file_manipulator::StringEncoding::Windows1252);
+            assert!(!mapped.files[0].is_decompressed());
+            assert_eq!(mapped.to_binary(endian_type), data);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,30 +1,154 @@
 // scene_file.rs
 // © 2024 Epic Mickey Library
 
-use std::{io::{Read, Write}, str::FromStr};
+use std::str::FromStr;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
 use serde_json;
+use crate::asset;
 use crate::file_manipulator;
 
+// JSON has no NaN or infinity, so non-finite floats are written as the strings "NaN", "inf" and "-inf"
+fn float_to_value(value: f32) -> serde_json::Value {
+    if value.is_nan() {
+        return serde_json::Value::String("NaN".to_string());
+    }
+    if value.is_infinite() {
+        let string = if value > 0.0 { "inf" } else { "-inf" };
+        return serde_json::Value::String(string.to_string());
+    }
+    return serde_json::Value::from(value);
+}
+
+// accepts numbers, the strings written by float_to_value and null (which serde_json writes for NaN)
+fn float_from_value(value: &serde_json::Value) -> Option<f32> {
+    match value {
+        serde_json::Value::Number(number) => return Some(number.as_f64()? as f32),
+        serde_json::Value::Null => return Some(f32::NAN),
+        serde_json::Value::String(string) => match string.to_lowercase().as_str() {
+            "nan" => return Some(f32::NAN),
+            "inf" | "+inf" | "infinity" => return Some(f32::INFINITY),
+            "-inf" | "-infinity" => return Some(f32::NEG_INFINITY),
+            _ => return None
+        },
+        _ => return None
+    }
+}
+
+// serde adapter for f32 fields, used with #[serde(with = "json_float")]
+mod json_float {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        return super::float_to_value(*value).serialize(serializer);
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        return super::float_from_value(&value).ok_or_else(|| D::Error::custom(format!("invalid float: {}", value)));
+    }
+}
+
+// serde adapter for matrix fields, used with #[serde(with = "json_float_matrix")]
+mod json_float_matrix {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, const R: usize, const C: usize>(m: &[[f32; C]; R], serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<Vec<serde_json::Value>> = m.iter().map(|row| row.iter().map(|value| super::float_to_value(*value)).collect()).collect();
+        return rows.serialize(serializer);
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const R: usize, const C: usize>(deserializer: D) -> Result<[[f32; C]; R], D::Error> {
+        let rows = Vec::<Vec<serde_json::Value>>::deserialize(deserializer)?;
+        if rows.len() != R || rows.iter().any(|row| row.len() != C) {
+            return Err(D::Error::custom(format!("expected a {}x{} matrix", R, C)));
+        }
+        let mut m = [[0.0; C]; R];
+        for (i, row) in rows.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                m[i][j] = super::float_from_value(value).ok_or_else(|| D::Error::custom(format!("invalid float: {}", value)))?;
+            }
+        }
+        return Ok(m);
+    }
+}
+
 /// The version of the scene file
 /// 
+/// The numbers 1, 2 and 3 used by `as_u32`, `from_u32` and the `version` field in JSON are this
+/// library's codes for the versions. They are not stored in the file: version 2 files are told
+/// apart by the magic words 0x02000001 (prototype) and 0x02000002 after the string section.
+/// 
 /// # Fields
 /// 
-/// * `Version1` - Version 1, used in Epic Mickey (Demo), Epic Mickey, and Epic Mickey (JP)
-/// * `Version2Prototype` - Version 2 Prototype, used in some Epic Mickey 2: The Power of Two prototypes
-/// * `Version2` - Version 2, used in later Epic Mickey 2: The Power of Two prototypes and Epic Mickey 2: The Power of Two
-#[derive(Clone)]
+/// * `Version1` - Version 1 (code 1), used in Epic Mickey (Demo), Epic Mickey, and Epic Mickey (JP)
+/// * `Version2Prototype` - Version 2 Prototype (code 2), used in some Epic Mickey 2: The Power of Two prototypes
+/// * `Version2` - Version 2 (code 3), used in later Epic Mickey 2: The Power of Two prototypes and Epic Mickey 2: The Power of Two
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SceneFileVersion {
     Version1 = 1,
     Version2Prototype,
     Version2
 }
 impl SceneFileVersion {
-    pub fn from_u32(version: u32) -> SceneFileVersion {
+    /// Returns the library's code for the version (1, 2 or 3).
+    pub fn as_u32(&self) -> u32 {
+        return *self as u32;
+    }
+
+    pub fn try_from_u32(version: u32) -> Result<SceneFileVersion, SceneFileError> {
         match version {
-            1 => SceneFileVersion::Version1,
-            2 => SceneFileVersion::Version2Prototype,
-            3 => SceneFileVersion::Version2,
-            _ => panic!("Unknown scene file version: {}", version)
+            1 => Ok(SceneFileVersion::Version1),
+            2 => Ok(SceneFileVersion::Version2Prototype),
+            3 => Ok(SceneFileVersion::Version2),
+            _ => Err(SceneFileError::InvalidVersionCode(version))
+        }
+    }
+
+    pub fn from_u32(version: u32) -> SceneFileVersion {
+        match SceneFileVersion::try_from_u32(version) {
+            Ok(version) => return version,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    /// The header offsets of this version, used by both reading and writing
+    pub fn header_adjustments(&self) -> HeaderAdjustments {
+        return match self {
+            SceneFileVersion::Version1 => HeaderAdjustments { marker_size: 0, data_offset: 0, string_offset: 0, version_word: None },
+            SceneFileVersion::Version2Prototype => HeaderAdjustments { marker_size: 4, data_offset: 8, string_offset: 4, version_word: Some(0x02000001) },
+            SceneFileVersion::Version2 => HeaderAdjustments { marker_size: 4, data_offset: 8, string_offset: 4, version_word: Some(0x02000002) }
+        };
+    }
+}
+
+/// Version-dependent offsets in the scene file header
+/// 
+/// The data offset word is the length of the string section plus 4, so the end of the string
+/// section is at the data offset plus `marker_size`.
+/// 
+/// # Fields
+/// 
+/// * `marker_size` - The size of the marker before the data offset word (0x01000001 in version 2).
+///   Version 2 keeps its version word right after the string section, at the data offset plus this
+/// * `data_offset` - What is added to the data offset word to get where the data starts
+/// * `string_offset` - What is added to a string offset (of a name, class name or String value)
+///   to get where the string is
+/// * `version_word` - The word after the string section, `None` for versions without one
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HeaderAdjustments {
+    pub marker_size: usize,
+    pub data_offset: usize,
+    pub string_offset: usize,
+    pub version_word: Option<u32>,
+}
+
+impl std::fmt::Display for SceneFileVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneFileVersion::Version1 => write!(f, "Version 1"),
+            SceneFileVersion::Version2Prototype => write!(f, "Version 2 Prototype"),
+            SceneFileVersion::Version2 => write!(f, "Version 2"),
         }
     }
 }
@@ -35,8 +159,11 @@ impl SceneFileVersion {
 /// 
 /// * `x` - The x coordinate
 /// * `y` - The y coordinate
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Point2 {
+    #[serde(with = "json_float")]
     pub x: f32,
+    #[serde(with = "json_float")]
     pub y: f32
 }
 
@@ -57,20 +184,55 @@ impl Point2 {
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
         fm.w_float(self.x);
         fm.w_float(self.y);
-        return fm.get_data().to_vec();
+        return fm.into_data();
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut dict = serde_json::Map::new();
-        dict.insert("x".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.x as f64).unwrap()));
-        dict.insert("y".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.y as f64).unwrap()));
-        return serde_json::Value::Object(dict)
+        return serde_json::to_value(self).unwrap()
     }
 
     pub fn from_dict(dict: &serde_json::Value) -> Point2 {
-        let x = dict["x"].as_f64().unwrap() as f32;
-        let y = dict["y"].as_f64().unwrap() as f32;
-        return Point2::new(x, y)
+        return serde_json::from_value(dict.clone()).unwrap()
+    }
+
+    pub fn add(&self, other: &Point2) -> Point2 {
+        return Point2::new(self.x + other.x, self.y + other.y)
+    }
+
+    pub fn sub(&self, other: &Point2) -> Point2 {
+        return Point2::new(self.x - other.x, self.y - other.y)
+    }
+
+    pub fn scale(&self, factor: f32) -> Point2 {
+        return Point2::new(self.x * factor, self.y * factor)
+    }
+
+    pub fn length(&self) -> f32 {
+        return (self.x * self.x + self.y * self.y).sqrt()
+    }
+}
+
+impl std::ops::Add for Point2 {
+    type Output = Point2;
+
+    fn add(self, other: Point2) -> Point2 {
+        return Point2::add(&self, &other)
+    }
+}
+
+impl std::ops::Sub for Point2 {
+    type Output = Point2;
+
+    fn sub(self, other: Point2) -> Point2 {
+        return Point2::sub(&self, &other)
+    }
+}
+
+impl std::ops::Mul<f32> for Point2 {
+    type Output = Point2;
+
+    fn mul(self, factor: f32) -> Point2 {
+        return self.scale(factor)
     }
 }
 
@@ -81,9 +243,13 @@ impl Point2 {
 /// * `x` - The x coordinate
 /// * `y` - The y coordinate
 /// * `z` - The z coordinate
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Point3 {
+    #[serde(with = "json_float")]
     pub x: f32,
+    #[serde(with = "json_float")]
     pub y: f32,
+    #[serde(with = "json_float")]
     pub z: f32
 }
 
@@ -107,22 +273,76 @@ impl Point3 {
         fm.w_float(self.x);
         fm.w_float(self.y);
         fm.w_float(self.z);
-        return fm.get_data().to_vec();
+        return fm.into_data();
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut dict = serde_json::Map::new();
-        dict.insert("x".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.x as f64).unwrap()));
-        dict.insert("y".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.y as f64).unwrap()));
-        dict.insert("z".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.z as f64).unwrap()));
-        return serde_json::Value::Object(dict)
+        return serde_json::to_value(self).unwrap()
     }
 
     pub fn from_dict(dict: &serde_json::Value) -> Point3 {
-        let x = dict["x"].as_f64().unwrap() as f32;
-        let y = dict["y"].as_f64().unwrap() as f32;
-        let z = dict["z"].as_f64().unwrap() as f32;
-        return Point3::new(x, y, z)
+        return serde_json::from_value(dict.clone()).unwrap()
+    }
+
+    pub fn add(&self, other: &Point3) -> Point3 {
+        return Point3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+
+    pub fn sub(&self, other: &Point3) -> Point3 {
+        return Point3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+
+    pub fn scale(&self, factor: f32) -> Point3 {
+        return Point3::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+
+    pub fn dot(&self, other: &Point3) -> f32 {
+        return self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &Point3) -> Point3 {
+        return Point3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x
+        )
+    }
+
+    pub fn length(&self) -> f32 {
+        return self.dot(self).sqrt()
+    }
+
+    /// Returns the point scaled to length 1. A zero-length point is returned unchanged.
+    pub fn normalize(&self) -> Point3 {
+        let length = self.length();
+        if length == 0.0 {
+            return *self
+        }
+        return self.scale(1.0 / length)
+    }
+}
+
+impl std::ops::Add for Point3 {
+    type Output = Point3;
+
+    fn add(self, other: Point3) -> Point3 {
+        return Point3::add(&self, &other)
+    }
+}
+
+impl std::ops::Sub for Point3 {
+    type Output = Point3;
+
+    fn sub(self, other: Point3) -> Point3 {
+        return Point3::sub(&self, &other)
+    }
+}
+
+impl std::ops::Mul<f32> for Point3 {
+    type Output = Point3;
+
+    fn mul(self, factor: f32) -> Point3 {
+        return self.scale(factor)
     }
 }
 
@@ -131,7 +351,9 @@ impl Point3 {
 /// # Fields
 /// 
 /// * `m` - The matrix, 3 arrays of 3 floats
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Matrix3 {
+    #[serde(with = "json_float_matrix")]
     pub m: [[f32; 3]; 3]
 }
 
@@ -157,41 +379,286 @@ impl Matrix3 {
                 fm.w_float(self.m[i][j]);
             }
         }
-        return fm.get_data().to_vec();
+        return fm.into_data();
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut dict = serde_json::Map::new();
-        let mut m = Vec::new();
-        for i in 0..3 {
-            let mut row = Vec::new();
-            for j in 0..3 {
-                row.push(serde_json::Value::Number(serde_json::Number::from_f64(self.m[i][j] as f64).unwrap()));
+        return serde_json::to_value(self).unwrap()
+    }
+
+    pub fn from_dict(dict: &serde_json::Value) -> Matrix3 {
+        return serde_json::from_value(dict.clone()).unwrap()
+    }
+
+    pub fn identity() -> Matrix3 {
+        let m = [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0]
+        ];
+        return Matrix3::new(m)
+    }
+
+    /// Transforms a point as a row vector, `p * m`.
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        return Point3::new(
+            p.x * m[0][0] + p.y * m[1][0] + p.z * m[2][0],
+            p.x * m[0][1] + p.y * m[1][1] + p.z * m[2][1],
+            p.x * m[0][2] + p.y * m[1][2] + p.z * m[2][2]
+        )
+    }
+
+    /// Returns `self * other`, so transforming by the result applies `self` first and then `other`.
+    pub fn multiply(&self, other: &Matrix3) -> Matrix3 {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[i][0] * other.m[0][j] + self.m[i][1] * other.m[1][j] + self.m[i][2] * other.m[2][j];
             }
-            m.push(serde_json::Value::Array(row));
         }
-        dict.insert("m".to_string(), serde_json::Value::Array(m));
-        return serde_json::Value::Object(dict)
+        return Matrix3::new(m)
     }
 
-    pub fn from_dict(dict: &serde_json::Value) -> Matrix3 {
+    pub fn transpose(&self) -> Matrix3 {
         let mut m = [[0.0; 3]; 3];
-        for i in 0..3 {
-            for j in 0..3 {
-                m[i][j] = dict["m"][i][j].as_f64().unwrap() as f32;
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
             }
         }
         return Matrix3::new(m)
     }
 
-    pub fn identity() -> Matrix3 {
+    /// Builds a rotation from Euler angles in radians. The point is rotated about the x axis
+    /// first, then y, then z, so the result is `rx.multiply(&ry).multiply(&rz)`. Positive angles
+    /// turn counterclockwise looking down the axis towards the origin.
+    pub fn from_euler_xyz(x: f32, y: f32, z: f32) -> Matrix3 {
+        let (sx, cx) = x.sin_cos();
+        let (sy, cy) = y.sin_cos();
+        let (sz, cz) = z.sin_cos();
+        let rx = Matrix3::new([[1.0, 0.0, 0.0], [0.0, cx, sx], [0.0, -sx, cx]]);
+        let ry = Matrix3::new([[cy, 0.0, -sy], [0.0, 1.0, 0.0], [sy, 0.0, cy]]);
+        let rz = Matrix3::new([[cz, sz, 0.0], [-sz, cz, 0.0], [0.0, 0.0, 1.0]]);
+        return rx.multiply(&ry).multiply(&rz)
+    }
+
+    /// The Euler angles in radians of a rotation matrix, in the order used by `from_euler_xyz`.
+    /// y is in [-pi/2, pi/2]; when it is at either end x and z turn about the same axis, and all
+    /// of the rotation is put in x.
+    /// 
+    /// ```
+    /// use epic_mickey_lib_rs::scene_file::Matrix3;
+    /// 
+    /// let angles: [(f32, f32, f32); 4] = [(0.0, 0.0, 0.0), (0.5, -0.25, 1.0), (-1.2, 0.8, 3.0), (0.3, 1.5, -2.0)];
+    /// for (x, y, z) in angles {
+    ///     let matrix = Matrix3::from_euler_xyz(x, y, z);
+    ///     let (x2, y2, z2) = matrix.to_euler_xyz();
+    ///     assert!((x - x2).abs() < 1e-4 && (y - y2).abs() < 1e-4 && (z - z2).abs() < 1e-4);
+    ///     let again = Matrix3::from_euler_xyz(x2, y2, z2);
+    ///     for i in 0..3 {
+    ///         for j in 0..3 {
+    ///             assert!((matrix.m[i][j] - again.m[i][j]).abs() < 1e-5);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn to_euler_xyz(&self) -> (f32, f32, f32) {
+        let m = &self.m;
+        let y = (-m[0][2]).clamp(-1.0, 1.0).asin();
+        if m[0][2].abs() > 0.99999 {
+            return ((-m[2][1]).atan2(m[1][1]), y, 0.0)
+        }
+        return (m[1][2].atan2(m[2][2]), y, m[0][1].atan2(m[0][0]))
+    }
+}
+
+/// Represents a 4x4 matrix, used for transforms
+/// 
+/// # Fields
+/// 
+/// * `m` - The matrix, 4 arrays of 4 floats
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Matrix4 {
+    #[serde(with = "json_float_matrix")]
+    pub m: [[f32; 4]; 4]
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f32; 4]; 4]) -> Matrix4 {
+        Matrix4 {
+            m
+        }
+    }
+
+    pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
+        for i in 0..4 {
+            for j in 0..4 {
+                self.m[i][j] = fm.r_float();
+            }
+        }
+    }
+
+    pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        for i in 0..4 {
+            for j in 0..4 {
+                fm.w_float(self.m[i][j]);
+            }
+        }
+        return fm.into_data();
+    }
+
+    pub fn to_dict(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap()
+    }
+
+    pub fn from_dict(dict: &serde_json::Value) -> Matrix4 {
+        return serde_json::from_value(dict.clone()).unwrap()
+    }
+
+    pub fn identity() -> Matrix4 {
         let m = [
-            [1.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0],
-            [0.0, 0.0, 1.0]
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ];
+        return Matrix4::new(m)
+    }
+
+    /// Transforms a point as a row vector `[x, y, z, 1] * m`, so the bottom row is the translation.
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        return Point3::new(
+            p.x * m[0][0] + p.y * m[1][0] + p.z * m[2][0] + m[3][0],
+            p.x * m[0][1] + p.y * m[1][1] + p.z * m[2][1] + m[3][1],
+            p.x * m[0][2] + p.y * m[1][2] + p.z * m[2][2] + m[3][2]
+        )
+    }
+}
+
+/// Represents a rotation as a unit quaternion
+/// 
+/// # Fields
+/// 
+/// * `w` - The scalar part
+/// * `x` - The x part of the vector
+/// * `y` - The y part of the vector
+/// * `z` - The z part of the vector
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Quaternion {
+    #[serde(with = "json_float")]
+    pub w: f32,
+    #[serde(with = "json_float")]
+    pub x: f32,
+    #[serde(with = "json_float")]
+    pub y: f32,
+    #[serde(with = "json_float")]
+    pub z: f32
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Quaternion {
+        Quaternion {
+            w,
+            x,
+            y,
+            z
+        }
+    }
+
+    pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
+        self.w = fm.r_float();
+        self.x = fm.r_float();
+        self.y = fm.r_float();
+        self.z = fm.r_float();
+    }
+
+    pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        fm.w_float(self.w);
+        fm.w_float(self.x);
+        fm.w_float(self.y);
+        fm.w_float(self.z);
+        return fm.into_data();
+    }
+
+    pub fn to_dict(&self) -> serde_json::Value {
+        return serde_json::to_value(self).unwrap()
+    }
+
+    pub fn from_dict(dict: &serde_json::Value) -> Quaternion {
+        return serde_json::from_value(dict.clone()).unwrap()
+    }
+
+    pub fn identity() -> Quaternion {
+        return Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Converts to a rotation matrix. Matrices are row-vector style: a point is rotated as `p * m`.
+    pub fn to_matrix3(&self) -> Matrix3 {
+        let (w, x, y, z) = (self.w, self.x, self.y, self.z);
+        let m = [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z), 2.0 * (x * z - w * y)],
+            [2.0 * (x * y - w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + w * x)],
+            [2.0 * (x * z + w * y), 2.0 * (y * z - w * x), 1.0 - 2.0 * (x * x + y * y)]
         ];
         return Matrix3::new(m)
     }
+
+    /// Converts from a rotation matrix laid out as in `to_matrix3`.
+    pub fn from_matrix3(matrix: &Matrix3) -> Quaternion {
+        let m = &matrix.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            return Quaternion::new(0.25 * s, (m[1][2] - m[2][1]) / s, (m[2][0] - m[0][2]) / s, (m[0][1] - m[1][0]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            return Quaternion::new((m[1][2] - m[2][1]) / s, 0.25 * s, (m[1][0] + m[0][1]) / s, (m[2][0] + m[0][2]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            return Quaternion::new((m[2][0] - m[0][2]) / s, (m[1][0] + m[0][1]) / s, 0.25 * s, (m[2][1] + m[1][2]) / s)
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            return Quaternion::new((m[0][1] - m[1][0]) / s, (m[2][0] + m[0][2]) / s, (m[2][1] + m[1][2]) / s, 0.25 * s)
+        }
+    }
+}
+
+/// An error produced while parsing a hex color string
+/// 
+/// # Variants
+/// 
+/// * `InvalidLength` - The string (without a leading `#`) has the wrong number of digits
+/// * `InvalidDigit` - The string contains a character that is not a hex digit
+#[derive(Debug, PartialEq)]
+pub enum ColorParseError {
+    InvalidLength { expected: usize, found: usize },
+    InvalidDigit(String),
+}
+
+impl std::fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ColorParseError::InvalidLength { expected, found } => write!(f, "Expected {} hex digits, found {}", expected, found),
+            ColorParseError::InvalidDigit(hex) => write!(f, "Invalid hex color: {}", hex),
+        }
+    }
+}
+
+impl std::error::Error for ColorParseError {}
+
+fn parse_hex_color(hex: &str, digits: usize) -> Result<u32, ColorParseError> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.chars().count() != digits {
+        return Err(ColorParseError::InvalidLength { expected: digits, found: hex.chars().count() });
+    }
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ColorParseError::InvalidDigit(hex.to_string()));
+    }
+    return Ok(u32::from_str_radix(hex, 16).unwrap())
 }
 
 /// Represents a color with red, green and blue channels. Channels are in the range 0.0 to 1.0.
@@ -201,9 +668,13 @@ impl Matrix3 {
 /// * `r` - The red channel
 /// * `g` - The green channel
 /// * `b` - The blue channel
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ColorRGB {
+    #[serde(with = "json_float")]
     pub r: f32,
+    #[serde(with = "json_float")]
     pub g: f32,
+    #[serde(with = "json_float")]
     pub b: f32
 }
 
@@ -227,22 +698,15 @@ impl ColorRGB {
         fm.w_float(self.r);
         fm.w_float(self.g);
         fm.w_float(self.b);
-        return fm.get_data().to_vec();
+        return fm.into_data();
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut dict = serde_json::Map::new();
-        dict.insert("r".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.r as f64).unwrap()));
-        dict.insert("g".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.g as f64).unwrap()));
-        dict.insert("b".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.b as f64).unwrap()));
-        return serde_json::Value::Object(dict)
+        return serde_json::to_value(self).unwrap()
     }
 
     pub fn from_dict(dict: &serde_json::Value) -> ColorRGB {
-        let r = dict["r"].as_f64().unwrap() as f32;
-        let g = dict["g"].as_f64().unwrap() as f32;
-        let b = dict["b"].as_f64().unwrap() as f32;
-        return ColorRGB::new(r, g, b)
+        return serde_json::from_value(dict.clone()).unwrap()
     }
 
     pub fn to_u32(&self) -> u32 {
@@ -264,9 +728,61 @@ impl ColorRGB {
         return format!("{:06X}", hex)
     }
 
-    pub fn from_hex_string(hex: &str) -> ColorRGB {
-        let hex = u32::from_str_radix(hex, 16).unwrap();
-        return ColorRGB::from_u32(hex)
+    /// Parses a 6 digit hex color such as `"FF8800"`, optionally prefixed with `#`.
+    pub fn from_hex_string(hex: &str) -> Result<ColorRGB, ColorParseError> {
+        return Ok(ColorRGB::from_u32(parse_hex_color(hex, 6)?))
+    }
+
+    /// Returns the color as (hue, saturation, value), with hue in degrees (0.0 to 360.0).
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let delta = max - min;
+        let mut h = if delta == 0.0 {
+            0.0
+        } else if max == self.r {
+            60.0 * ((self.g - self.b) / delta)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / delta + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / delta + 4.0)
+        };
+        if h < 0.0 {
+            h += 360.0;
+        }
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+        return (h, s, max)
+    }
+
+    /// Builds a color from hue in degrees (wrapped into 0.0 to 360.0), saturation and value.
+    pub fn from_hsv(h: f32, s: f32, v: f32) -> ColorRGB {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x)
+        };
+        let m = v - c;
+        return ColorRGB::new(r + m, g + m, b + m)
+    }
+
+    /// Linearly interpolates towards `other`; `t` of 0.0 gives `self` and 1.0 gives `other`.
+    pub fn lerp(&self, other: &ColorRGB, t: f32) -> ColorRGB {
+        return ColorRGB::new(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t
+        )
+    }
+
+    /// Bounds every channel to 0.0 to 1.0. `to_u32` wraps channels outside that range, so clamp first.
+    pub fn clamp(&self) -> ColorRGB {
+        return ColorRGB::new(self.r.clamp(0.0, 1.0), self.g.clamp(0.0, 1.0), self.b.clamp(0.0, 1.0))
     }
 }
 
@@ -278,10 +794,15 @@ impl ColorRGB {
 /// * `g` - The green channel
 /// * `b` - The blue channel
 /// * `a` - The alpha channel
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ColorRGBA {
+    #[serde(with = "json_float")]
     pub r: f32,
+    #[serde(with = "json_float")]
     pub g: f32,
+    #[serde(with = "json_float")]
     pub b: f32,
+    #[serde(with = "json_float")]
     pub a: f32
 }
 
@@ -308,26 +829,18 @@ impl ColorRGBA {
         fm.w_float(self.g);
         fm.w_float(self.b);
         fm.w_float(self.a);
-        return fm.get_data().to_vec();
+        return fm.into_data();
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        let mut dict = serde_json::Map::new();
-        dict.insert("r".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.r as f64).unwrap()));
-        dict.insert("g".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.g as f64).unwrap()));
-        dict.insert("b".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.b as f64).unwrap()));
-        dict.insert("a".to_string(), serde_json::Value::Number(serde_json::Number::from_f64(self.a as f64).unwrap()));
-        return serde_json::Value::Object(dict)
+        return serde_json::to_value(self).unwrap()
     }
 
     pub fn from_dict(dict: &serde_json::Value) -> ColorRGBA {
-        let r = dict["r"].as_f64().unwrap() as f32;
-        let g = dict["g"].as_f64().unwrap() as f32;
-        let b = dict["b"].as_f64().unwrap() as f32;
-        let a = dict["a"].as_f64().unwrap() as f32;
-        return ColorRGBA::new(r, g, b, a)
+        return serde_json::from_value(dict.clone()).unwrap()
     }
 
+    /// Packs the color as RGBA: red in the high byte, alpha in the low byte (`0xRRGGBBAA`).
     pub fn to_u32(&self) -> u32 {
         let r = (self.r * 255.0) as u32;
         let g = (self.g * 255.0) as u32;
@@ -336,6 +849,7 @@ impl ColorRGBA {
         return (r << 24) | (g << 16) | (b << 8) | a
     }
 
+    /// Unpacks an RGBA color (`0xRRGGBBAA`), the inverse of `to_u32`.
     pub fn from_u32(hex: u32) -> ColorRGBA {
         let r = ((hex >> 24) & 0xFF) as f32 / 255.0;
         let g = ((hex >> 16) & 0xFF) as f32 / 255.0;
@@ -344,14 +858,35 @@ impl ColorRGBA {
         return ColorRGBA::new(r, g, b, a)
     }
 
+    /// Packs the color as ARGB: alpha in the high byte, blue in the low byte (`0xAARRGGBB`).
+    pub fn to_u32_argb(&self) -> u32 {
+        return self.to_u32().rotate_right(8)
+    }
+
+    /// Unpacks an ARGB color (`0xAARRGGBB`), the inverse of `to_u32_argb`.
+    pub fn from_u32_argb(hex: u32) -> ColorRGBA {
+        return ColorRGBA::from_u32(hex.rotate_left(8))
+    }
+
+    /// Formats the color as 8 RGBA hex digits (`RRGGBBAA`).
     pub fn to_hex_string(&self) -> String {
         let hex = self.to_u32();
         return format!("{:08X}", hex)
     }
 
-    pub fn from_hex_string(hex: &str) -> ColorRGBA {
-        let hex = u32::from_str_radix(hex, 16).unwrap();
-        return ColorRGBA::from_u32(hex)
+    /// Parses an 8 digit RGBA hex color such as `"FF8800FF"` (`RRGGBBAA`), optionally prefixed with `#`.
+    pub fn from_hex_string(hex: &str) -> Result<ColorRGBA, ColorParseError> {
+        return Ok(ColorRGBA::from_u32(parse_hex_color(hex, 8)?))
+    }
+
+    /// Formats the color as 8 ARGB hex digits (`AARRGGBB`).
+    pub fn to_hex_string_argb(&self) -> String {
+        return format!("{:08X}", self.to_u32_argb())
+    }
+
+    /// Parses an 8 digit ARGB hex color such as `"FFFF8800"` (`AARRGGBB`), optionally prefixed with `#`.
+    pub fn from_hex_string_argb(hex: &str) -> Result<ColorRGBA, ColorParseError> {
+        return Ok(ColorRGBA::from_u32_argb(parse_hex_color(hex, 8)?))
     }
 
     pub fn to_rgb(&self) -> ColorRGB {
@@ -361,67 +896,147 @@ impl ColorRGBA {
     pub fn from_rgb(color: &ColorRGB, a: f32) -> ColorRGBA {
         return ColorRGBA::new(color.r, color.g, color.b, a)
     }
+
+    pub fn lerp(&self, other: &ColorRGBA, t: f32) -> ColorRGBA {
+        let rgb = self.to_rgb().lerp(&other.to_rgb(), t);
+        return ColorRGBA::from_rgb(&rgb, self.a + (other.a - self.a) * t)
+    }
+
+    pub fn clamp(&self) -> ColorRGBA {
+        return ColorRGBA::from_rgb(&self.to_rgb().clamp(), self.a.clamp(0.0, 1.0))
+    }
+
+    /// Multiplies the color channels by alpha, leaving alpha itself unchanged.
+    pub fn premultiply_alpha(&self) -> ColorRGBA {
+        return ColorRGBA::new(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+    }
+
+    pub fn with_alpha(&self, a: f32) -> ColorRGBA {
+        return ColorRGBA::new(self.r, self.g, self.b, a)
+    }
 }
 
-/// Represents an ID
+/// An error produced while converting an ID from or to another representation
 /// 
-/// # Fields
+/// # Variants
 /// 
-/// * `id` - The ID
-pub struct ID {
-    pub id: u128
+/// * `InvalidByte` - A comma-separated part of an ID string is not 1 or 2 hex digits
+/// * `TooManyBytes` - An ID string has more parts than fit in a u128
+/// * `OutOfRange` - The ID is too large for the requested integer width
+#[derive(Debug, PartialEq)]
+pub enum IdParseError {
+    InvalidByte(String),
+    TooManyBytes(usize),
+    OutOfRange(u128),
 }
 
-impl ID {
-    pub fn new(id: u128) -> ID {
+impl std::fmt::Display for IdParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IdParseError::InvalidByte(part) => write!(f, "Invalid ID byte \"{}\", expected 1 or 2 hex digits", part),
+            IdParseError::TooManyBytes(count) => write!(f, "ID has {} bytes, at most 16 are allowed", count),
+            IdParseError::OutOfRange(id) => write!(f, "ID {} is too large to fit into a 32 bit integer", id),
+        }
+    }
+}
+
+impl std::error::Error for IdParseError {}
+
+/// Represents an ID
+/// 
+/// # Fields
+/// 
+/// * `id` - The ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ID {
+    pub id: u128
+}
+
+impl ID {
+    pub fn new(id: u128) -> ID {
         ID {
             id
         }
     }
 
     pub fn to_string(&self, num_bytes: u8) -> String {
-        // convert to hex string with commas every 2 characters
+        return self.to_string_with(num_bytes, ",", false)
+    }
+
+    /// Writes the ID as `num_bytes` hex bytes joined with `sep`, in upper or lower case.
+    /// `from_string` reads it back for a separator of ",", "-" or " ".
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::ID;
+    /// let id = ID::from_u32(0x01A2FF);
+    /// assert_eq!(id.to_string(4), "00,01,a2,ff");
+    /// assert_eq!(id.to_string_with(4, "-", true), "00-01-A2-FF");
+    /// assert!(ID::from_string("00 01 A2 FF") == id);
+    /// assert!(ID::from_string("0-1, a2,ff") == id);
+    /// ```
+    pub fn to_string_with(&self, num_bytes: u8, sep: &str, uppercase: bool) -> String {
+        let parts = self.hex_bytes(num_bytes);
+        let result = parts.join(sep);
+        if uppercase {
+            return result
+        }
+        return result.to_lowercase()
+    }
+
+    // the first num_bytes pairs of hex digits, zero padded to at least num_bytes bytes
+    fn hex_bytes(&self, num_bytes: u8) -> Vec<String> {
         let mut hex = format!("{:X}", self.id);
         while hex.len() < num_bytes as usize * 2 {
             hex = format!("0{}", hex);
         }
-        let mut result = String::new();
+        let mut parts = Vec::new();
         for i in 0..num_bytes as usize {
-            if i > 0 {
-                result.push_str(",");
-            }
-            result.push_str(&hex[i*2..i*2+2]);
+            parts.push(hex[i*2..i*2+2].to_string());
         }
-        return result.to_lowercase()
+        return parts
     }
 
     pub fn to_string_no_leaders(&self, num_bytes: u8) -> String {
-        let string = self.to_string(num_bytes);
         // for part, if the first character is 0, remove it
         let mut parts = Vec::new();
-        for part in string.split(",") {
-            let part = part.to_string();
-            if part.chars().nth(0).unwrap() == '0' {
-                parts.push(part.chars().skip(1).collect());
-            } else {
-                parts.push(part);
+        for part in self.hex_bytes(num_bytes) {
+            match part.strip_prefix('0') {
+                Some(rest) => parts.push(rest.to_string()),
+                None => parts.push(part)
             }
         }
-        return parts.join(",")
+        return parts.join(",").to_lowercase()
     }
 
     pub fn from_string(string: &str) -> ID {
-        let mut hex = String::new();
-        for part in string.split(",") {
-            // until its 2 characters long, add 0 in front
-            let mut part = part.to_string();
-            while part.len() < 2 {
-                part = format!("0{}", part);
+        match ID::try_from_string(string) {
+            Ok(id) => return id,
+            Err(error) => panic!("Could not parse ID \"{}\": {}", string, error)
+        }
+    }
+
+    /// Parses a list of hex bytes such as `"1,a2,ff"`, separated by commas, hyphens or spaces.
+    /// Spaces around a comma or hyphen are ignored. An empty string is ID 0.
+    pub fn try_from_string(string: &str) -> Result<ID, IdParseError> {
+        if string.is_empty() {
+            return Ok(ID::new(0));
+        }
+        let parts: Vec<&str> = if string.contains([',', '-']) {
+            string.split([',', '-']).map(str::trim).collect()
+        } else {
+            string.split(' ').collect()
+        };
+        if parts.len() > 16 {
+            return Err(IdParseError::TooManyBytes(parts.len()));
+        }
+        let mut id: u128 = 0;
+        for part in parts {
+            if part.is_empty() || part.len() > 2 || !part.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(IdParseError::InvalidByte(part.to_string()));
             }
-            hex.push_str(&part);
+            id = (id << 8) | u8::from_str_radix(part, 16).unwrap() as u128;
         }
-        let id = u128::from_str_radix(&hex, 16).unwrap();
-        return ID::new(id)
+        return Ok(ID::new(id))
     }
 
     pub fn from_u32(id: u32) -> ID {
@@ -429,11 +1044,17 @@ impl ID {
     }
 
     pub fn to_u32(&self) -> u32 {
-        // check if it fits in u32
+        match self.try_to_u32() {
+            Ok(id) => return id,
+            Err(_) => panic!("The ID is too large to fit into a 32 bit integer, you have too many objects in your scene!")
+        }
+    }
+
+    pub fn try_to_u32(&self) -> Result<u32, IdParseError> {
         if self.id > u32::MAX as u128 {
-            panic!("The ID is too large to fit into a 32 bit integer, you have too many objects in your scene!");
+            return Err(IdParseError::OutOfRange(self.id));
         }
-        return self.id as u32
+        return Ok(self.id as u32)
     }
 
     pub fn from_u128(id: u128) -> ID {
@@ -447,6 +1068,10 @@ impl ID {
 
 /// Represents a pointer to an entity
 /// 
+/// A pointer holds the link ID of the entity it points to and is stored as a u32 in every scene
+/// file version, the same width as `Entity::link_id`. Template IDs are wider, but they are
+/// stored as strings and never pointed to.
+/// 
 /// # Fields
 /// 
 /// * `id` - The ID of the entity
@@ -467,47 +1092,176 @@ impl EntityPointer {
 
     pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
-        fm.w_u32(self.id.to_u32());
-        return fm.get_data().to_vec();
+        fm.w_u32(self.to_u32());
+        return fm.into_data();
+    }
+
+    fn to_u32(&self) -> u32 {
+        match self.id.try_to_u32() {
+            Ok(id) => return id,
+            Err(_) => panic!("Entity Pointer {} does not fit in 32 bits, entity pointers are u32 link IDs", self.id.to_u128())
+        }
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
-        return serde_json::Value::Number(serde_json::Number::from(self.id.to_u32() as i64));
+        return serde_json::Value::Number(serde_json::Number::from(self.to_u32()));
     }
 
     pub fn from_dict(dict: &serde_json::Value) -> EntityPointer {
-        let id = ID::from_u32(dict.as_u64().unwrap() as u32);
+        // keep the whole value so pack can report one that is too large instead of truncating it
+        let id = ID::from_u128(dict.as_u64().unwrap() as u128);
         return EntityPointer::new(id)
     }
 }
 
-// Property value types
-// "Boolean" - bool
-// "Integer" - s32
-// "Unsigned Integer" - u32
-// "Short" - s16
-// "Unsigned Short" - u16
-// "Float" - f32
-// "String" - String
-// "Point2" - Point2
-// "Point3" - Point3
-// "Matrix3" - Matrix3
-// "Color (RGB)" - ColorRGB
-// "Color (RGBA)" - ColorRGBA
-// "Entity Pointer" - EntityPointer
+/// The value type of a property, stored in the file as the property's class name
+/// 
+/// # Variants
+/// 
+/// * `Boolean` - "Boolean", a bool
+/// * `Integer` - "Integer", an s32
+/// * `UnsignedInteger` - "Unsigned Integer", a u32
+/// * `Short` - "Short", an s16
+/// * `UnsignedShort` - "Unsigned Short", a u16
+/// * `Float` - "Float", an f32
+/// * `String` - "String", a string
+/// * `Point2` - "Point2", a Point2
+/// * `Point3` - "Point3", a Point3
+/// * `Matrix3` - "Matrix3", a Matrix3
+/// * `Matrix4` - "Matrix4", a Matrix4
+/// * `Quaternion` - "Quaternion", a Quaternion
+/// * `ColorRGB` - "Color (RGB)", a ColorRGB
+/// * `ColorRGBA` - "Color (RGBA)", a ColorRGBA
+/// * `EntityPointer` - "Entity Pointer", an EntityPointer
+/// * `Unknown` - Any other class name, values are kept as raw bytes (see `ReadOptions::raw_value_sizes`)
+#[derive(Clone, PartialEq, Debug)]
+pub enum PropertyType {
+    Boolean,
+    Integer,
+    UnsignedInteger,
+    Short,
+    UnsignedShort,
+    Float,
+    String,
+    Point2,
+    Point3,
+    Matrix3,
+    Matrix4,
+    Quaternion,
+    ColorRGB,
+    ColorRGBA,
+    EntityPointer,
+    Unknown(String)
+}
+
+impl PropertyType {
+    pub fn from_class_name(class_name: &str) -> PropertyType {
+        match class_name {
+            "Boolean" => PropertyType::Boolean,
+            "Integer" => PropertyType::Integer,
+            "Unsigned Integer" => PropertyType::UnsignedInteger,
+            "Short" => PropertyType::Short,
+            "Unsigned Short" => PropertyType::UnsignedShort,
+            "Float" => PropertyType::Float,
+            "String" => PropertyType::String,
+            "Point2" => PropertyType::Point2,
+            "Point3" => PropertyType::Point3,
+            "Matrix3" => PropertyType::Matrix3,
+            "Matrix4" => PropertyType::Matrix4,
+            "Quaternion" => PropertyType::Quaternion,
+            "Color (RGB)" => PropertyType::ColorRGB,
+            "Color (RGBA)" => PropertyType::ColorRGBA,
+            "Entity Pointer" => PropertyType::EntityPointer,
+            _ => PropertyType::Unknown(class_name.to_string())
+        }
+    }
+
+    /// The class name as stored in the file
+    pub fn as_str(&self) -> &str {
+        match self {
+            PropertyType::Boolean => "Boolean",
+            PropertyType::Integer => "Integer",
+            PropertyType::UnsignedInteger => "Unsigned Integer",
+            PropertyType::Short => "Short",
+            PropertyType::UnsignedShort => "Unsigned Short",
+            PropertyType::Float => "Float",
+            PropertyType::String => "String",
+            PropertyType::Point2 => "Point2",
+            PropertyType::Point3 => "Point3",
+            PropertyType::Matrix3 => "Matrix3",
+            PropertyType::Matrix4 => "Matrix4",
+            PropertyType::Quaternion => "Quaternion",
+            PropertyType::ColorRGB => "Color (RGB)",
+            PropertyType::ColorRGBA => "Color (RGBA)",
+            PropertyType::EntityPointer => "Entity Pointer",
+            PropertyType::Unknown(class_name) => class_name
+        }
+    }
+
+    /// Whether `value` is a single JSON value that can be written as this type
+    pub fn accepts(&self, value: &serde_json::Value) -> bool {
+        return match self {
+            PropertyType::Boolean => value.is_boolean(),
+            PropertyType::Integer => value.as_i64().is_some_and(|value| i32::try_from(value).is_ok()),
+            PropertyType::UnsignedInteger => value.as_u64().is_some_and(|value| u32::try_from(value).is_ok()),
+            PropertyType::Short => value.as_i64().is_some_and(|value| i16::try_from(value).is_ok()),
+            PropertyType::UnsignedShort => value.as_u64().is_some_and(|value| u16::try_from(value).is_ok()),
+            PropertyType::Float => float_from_value(value).is_some(),
+            PropertyType::String => value.is_string(),
+            PropertyType::Point2 => serde_json::from_value::<Point2>(value.clone()).is_ok(),
+            PropertyType::Point3 => serde_json::from_value::<Point3>(value.clone()).is_ok(),
+            PropertyType::Matrix3 => serde_json::from_value::<Matrix3>(value.clone()).is_ok(),
+            PropertyType::Matrix4 => serde_json::from_value::<Matrix4>(value.clone()).is_ok(),
+            PropertyType::Quaternion => serde_json::from_value::<Quaternion>(value.clone()).is_ok(),
+            PropertyType::ColorRGB => serde_json::from_value::<ColorRGB>(value.clone()).is_ok(),
+            PropertyType::ColorRGBA => serde_json::from_value::<ColorRGBA>(value.clone()).is_ok(),
+            // pointers that don't fit in a u32 are reported when packing
+            PropertyType::EntityPointer => value.is_u64(),
+            PropertyType::Unknown(_) => value.as_str().and_then(base64_decode).is_some()
+        };
+    }
+}
+
+impl std::fmt::Display for PropertyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    return base64::engine::general_purpose::STANDARD.encode(data);
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    return base64::engine::general_purpose::STANDARD.decode(text).ok();
+}
+
+// maps an on-disk data storage type to (list, asset, palette, template)
+fn data_type_flags(data_type: u32) -> Option<(bool, bool, bool, bool)> {
+    return match data_type {
+        0 => Some((false, false, false, false)),
+        1 => Some((true, false, false, false)),
+        2 => Some((false, true, false, false)),
+        3 => Some((true, true, false, false)),
+        4 => Some((false, false, true, false)),
+        5 => Some((true, false, false, true)),
+        _ => None
+    };
+}
 
 /// Represents a property in a component
 /// 
 /// # Fields
 /// 
-/// * `class_name` - The class name of the property
+/// * `class_name` - The value type of the property (its class name in the file)
 /// * `name` - The name of the property
 /// * `asset` - Whether the property is an asset
 /// * `palette` - Whether the property is a palette
 /// * `template` - Whether the property is a template
 /// * `value` - The value of the property
+#[derive(Clone)]
 pub struct Property {
-    pub class_name: String,
+    pub class_name: PropertyType,
     pub name: String,
     pub asset: bool,
     pub palette: bool,
@@ -516,7 +1270,7 @@ pub struct Property {
 }
 
 impl Property {
-    pub fn new(class_name: String, name: String, asset: bool, palette: bool, template: bool, value: serde_json::Value) -> Property {
+    pub fn new(class_name: PropertyType, name: String, asset: bool, palette: bool, template: bool, value: serde_json::Value) -> Property {
         Property {
             class_name,
             name,
@@ -527,269 +1281,432 @@ impl Property {
         }
     }
 
-    fn read_value_for_type(fm: &mut file_manipulator::FileManipulator, value_type: &str, version: SceneFileVersion) -> serde_json::Value {
+    // the value if the property holds a single value of this type
+    fn single_value(&self, value_type: PropertyType) -> Option<&serde_json::Value> {
+        if self.class_name != value_type || self.value.is_array() {
+            return None;
+        }
+        return Some(&self.value);
+    }
+
+    fn typed_value<T: serde::de::DeserializeOwned>(&self, value_type: PropertyType) -> Option<T> {
+        return serde_json::from_value(self.single_value(value_type)?.clone()).ok();
+    }
+
+    // make the property hold a single value of this type
+    fn set_single_value(&mut self, value_type: PropertyType, value: serde_json::Value) {
+        self.class_name = value_type;
+        self.value = value;
+    }
+
+    /// Typed accessors for single values. Each returns `None` if the property has another
+    /// `class_name` or holds a list.
+    pub fn as_bool(&self) -> Option<bool> {
+        return self.single_value(PropertyType::Boolean)?.as_bool();
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        return self.single_value(PropertyType::Integer)?.as_i64().and_then(|value| i32::try_from(value).ok());
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        return self.single_value(PropertyType::UnsignedInteger)?.as_u64().and_then(|value| u32::try_from(value).ok());
+    }
+
+    pub fn as_i16(&self) -> Option<i16> {
+        return self.single_value(PropertyType::Short)?.as_i64().and_then(|value| i16::try_from(value).ok());
+    }
+
+    pub fn as_u16(&self) -> Option<u16> {
+        return self.single_value(PropertyType::UnsignedShort)?.as_u64().and_then(|value| u16::try_from(value).ok());
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        return float_from_value(self.single_value(PropertyType::Float)?);
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
+        return self.single_value(PropertyType::String)?.as_str();
+    }
+
+    pub fn as_point2(&self) -> Option<Point2> {
+        return self.typed_value(PropertyType::Point2);
+    }
+
+    pub fn as_point3(&self) -> Option<Point3> {
+        return self.typed_value(PropertyType::Point3);
+    }
+
+    pub fn as_matrix3(&self) -> Option<Matrix3> {
+        return self.typed_value(PropertyType::Matrix3);
+    }
+
+    pub fn as_matrix4(&self) -> Option<Matrix4> {
+        return self.typed_value(PropertyType::Matrix4);
+    }
+
+    pub fn as_quaternion(&self) -> Option<Quaternion> {
+        return self.typed_value(PropertyType::Quaternion);
+    }
+
+    pub fn as_color_rgb(&self) -> Option<ColorRGB> {
+        return self.typed_value(PropertyType::ColorRGB);
+    }
+
+    pub fn as_color_rgba(&self) -> Option<ColorRGBA> {
+        return self.typed_value(PropertyType::ColorRGBA);
+    }
+
+    /// The link ID an `Entity Pointer` property points to.
+    pub fn as_entity_pointer(&self) -> Option<ID> {
+        return self.single_value(PropertyType::EntityPointer)?.as_u64().and_then(|value| u32::try_from(value).ok()).map(ID::from_u32);
+    }
+
+    /// Typed setters. Each replaces the value with a single value and sets `class_name` to match,
+    /// so a list property becomes a single value.
+    pub fn set_bool(&mut self, value: bool) {
+        self.set_single_value(PropertyType::Boolean, serde_json::Value::Bool(value));
+    }
+
+    pub fn set_i32(&mut self, value: i32) {
+        self.set_single_value(PropertyType::Integer, serde_json::Value::from(value));
+    }
+
+    pub fn set_u32(&mut self, value: u32) {
+        self.set_single_value(PropertyType::UnsignedInteger, serde_json::Value::from(value));
+    }
+
+    pub fn set_i16(&mut self, value: i16) {
+        self.set_single_value(PropertyType::Short, serde_json::Value::from(value));
+    }
+
+    pub fn set_u16(&mut self, value: u16) {
+        self.set_single_value(PropertyType::UnsignedShort, serde_json::Value::from(value));
+    }
+
+    pub fn set_f32(&mut self, value: f32) {
+        self.set_single_value(PropertyType::Float, float_to_value(value));
+    }
+
+    pub fn set_string(&mut self, value: &str) {
+        self.set_single_value(PropertyType::String, serde_json::Value::String(value.to_string()));
+    }
+
+    pub fn set_point2(&mut self, value: Point2) {
+        self.set_single_value(PropertyType::Point2, value.to_dict());
+    }
+
+    pub fn set_point3(&mut self, value: Point3) {
+        self.set_single_value(PropertyType::Point3, value.to_dict());
+    }
+
+    pub fn set_matrix3(&mut self, value: &Matrix3) {
+        self.set_single_value(PropertyType::Matrix3, value.to_dict());
+    }
+
+    pub fn set_matrix4(&mut self, value: &Matrix4) {
+        self.set_single_value(PropertyType::Matrix4, value.to_dict());
+    }
+
+    pub fn set_quaternion(&mut self, value: &Quaternion) {
+        self.set_single_value(PropertyType::Quaternion, value.to_dict());
+    }
+
+    pub fn set_color_rgb(&mut self, value: ColorRGB) {
+        self.set_single_value(PropertyType::ColorRGB, value.to_dict());
+    }
+
+    pub fn set_color_rgba(&mut self, value: ColorRGBA) {
+        self.set_single_value(PropertyType::ColorRGBA, value.to_dict());
+    }
+
+    pub fn set_entity_pointer(&mut self, id: ID) {
+        self.set_single_value(PropertyType::EntityPointer, EntityPointer::new(id).to_dict());
+    }
+
+    fn read_value_for_type(fm: &mut file_manipulator::FileManipulator, value_type: &PropertyType, version: SceneFileVersion, options: &ReadOptions) -> serde_json::Value {
         match value_type {
-            "Boolean" => {
-                return serde_json::Value::Bool(fm.r_bool());
+            PropertyType::Boolean => {
+                // any nonzero value is true, remember when a file stores true as 1 instead of all FF
+                let value = fm.r_u32();
+                if value != 0 && value != u32::MAX {
+                    fm.set_bool_representation(file_manipulator::BoolRepresentation::LeastSignificantByte);
+                }
+                return serde_json::Value::Bool(value != 0);
             },
-            "Integer" => {
+            PropertyType::Integer => {
                 return serde_json::Value::Number(serde_json::Number::from(fm.r_s32()));
             },
-            "Unsigned Integer" => {
+            PropertyType::UnsignedInteger => {
                 return serde_json::Value::Number(serde_json::Number::from(fm.r_u32()));
             },
-            "Short" => {
+            PropertyType::Short => {
                 return serde_json::Value::Number(serde_json::Number::from(fm.r_s16_jps()));
             },
-            "Unsigned Short" => {
+            PropertyType::UnsignedShort => {
                 return serde_json::Value::Number(serde_json::Number::from(fm.r_u16_jps()));
             },
-            "Float" => {
-                return serde_json::Value::Number(serde_json::Number::from_f64(fm.r_float() as f64).unwrap());
+            PropertyType::Float => {
+                return float_to_value(fm.r_float());
             },
-            "String" => {
-                // read pointer
-                let mut pointer = fm.r_u32();
-                // if its version 2 proto or version 2, add 4 to the pointer
-                match version {
-                    SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                        pointer += 4;
-                    },
-                    _ => {}
-                }
-                let pos = fm.tell();
-                // seek to pointer
-                fm.seek(pointer as usize);
-                // read string
-                let string = fm.r_str_jps();
-                // seek back
-                fm.seek(pos);
-                return serde_json::Value::String(string);
+            PropertyType::String => {
+                let pointer = fm.r_u32() as usize + version.header_adjustments().string_offset;
+                let mut fm = fm.save_position();
+                fm.seek_read(pointer);
+                return serde_json::Value::String(fm.r_str_jps());
             },
-            "Point2" => {
+            PropertyType::Point2 => {
                 let mut point = Point2::new(0.0, 0.0);
                 point.unpack(fm);
                 return point.to_dict();
             },
-            "Point3" => {
+            PropertyType::Point3 => {
                 let mut point = Point3::new(0.0, 0.0, 0.0);
                 point.unpack(fm);
                 return point.to_dict();
             },
-            "Matrix3" => {
+            PropertyType::Matrix3 => {
                 let mut matrix = Matrix3::new([[0.0; 3]; 3]);
                 matrix.unpack(fm);
                 return matrix.to_dict();
             },
-            "Color (RGB)" => {
+            PropertyType::Matrix4 => {
+                let mut matrix = Matrix4::new([[0.0; 4]; 4]);
+                matrix.unpack(fm);
+                return matrix.to_dict();
+            },
+            PropertyType::Quaternion => {
+                let mut quaternion = Quaternion::new(0.0, 0.0, 0.0, 0.0);
+                quaternion.unpack(fm);
+                return quaternion.to_dict();
+            },
+            PropertyType::ColorRGB => {
                 let mut color = ColorRGB::new(0.0, 0.0, 0.0);
                 color.unpack(fm);
                 return color.to_dict();
             },
-            "Color (RGBA)" => {
+            PropertyType::ColorRGBA => {
                 let mut color = ColorRGBA::new(0.0, 0.0, 0.0, 0.0);
                 color.unpack(fm);
                 return color.to_dict();
             },
-            "Entity Pointer" => {
+            PropertyType::EntityPointer => {
                 let mut entity_pointer = EntityPointer::new(ID::new(0));
                 entity_pointer.unpack(fm);
                 return entity_pointer.to_dict();
             },
-            _ => {
-                panic!("Unknown property value type: {}", value_type);
+            PropertyType::Unknown(class_name) => {
+                // keep the raw bytes so the file still round-trips, unpack checked there is a size
+                let mut data = vec![0; options.raw_value_sizes[class_name]];
+                fm.read(&mut data);
+                return serde_json::Value::String(base64_encode(&data));
             }
         }
     }
 
-    fn write_value_for_type(fm: &mut file_manipulator::FileManipulator, strings_offsets_map: &mut std::collections::HashMap<String, u32>, value: &serde_json::Value, value_type: &str) {
+    fn write_value_for_type(fm: &mut file_manipulator::FileManipulator, strings_offsets_map: &mut std::collections::HashMap<String, u32>, value: &serde_json::Value, value_type: &PropertyType) {
         match value_type {
-            "Boolean" => {
+            PropertyType::Boolean => {
                 fm.w_bool(value.as_bool().unwrap());
             },
-            "Integer" => {
+            PropertyType::Integer => {
                 fm.w_s32(value.as_i64().unwrap() as i32);
             },
-            "Unsigned Integer" => {
+            PropertyType::UnsignedInteger => {
                 fm.w_u32(value.as_u64().unwrap() as u32);
             },
-            "Short" => {
+            PropertyType::Short => {
                 fm.w_s16_jps(value.as_i64().unwrap() as i16, 0);
             },
-            "Unsigned Short" => {
+            PropertyType::UnsignedShort => {
                 fm.w_u16_jps(value.as_u64().unwrap() as u16, 0);
             },
-            "Float" => {
-                fm.w_float(value.as_f64().unwrap() as f32);
+            PropertyType::Float => {
+                fm.w_float(float_from_value(value).unwrap());
             },
-            "String" => {
+            PropertyType::String => {
                 let string = value.as_str().unwrap();
                 let offset = strings_offsets_map.get(string).unwrap();
                 fm.w_u32(*offset);
             },
-            "Point2" => {
+            PropertyType::Point2 => {
                 let point = Point2::from_dict(value);
-                fm.write(&point.pack(fm.get_endian().clone()));
+                fm.write(&point.pack(*fm.get_endian()));
             },
-            "Point3" => {
+            PropertyType::Point3 => {
                 let point = Point3::from_dict(value);
-                fm.write(&point.pack(fm.get_endian().clone()));
+                fm.write(&point.pack(*fm.get_endian()));
             },
-            "Matrix3" => {
+            PropertyType::Matrix3 => {
                 let matrix = Matrix3::from_dict(value);
-                fm.write(&matrix.pack(fm.get_endian().clone()));
+                fm.write(&matrix.pack(*fm.get_endian()));
+            },
+            PropertyType::Matrix4 => {
+                let matrix = Matrix4::from_dict(value);
+                fm.write(&matrix.pack(*fm.get_endian()));
+            },
+            PropertyType::Quaternion => {
+                let quaternion = Quaternion::from_dict(value);
+                fm.write(&quaternion.pack(*fm.get_endian()));
             },
-            "Color (RGB)" => {
+            PropertyType::ColorRGB => {
                 let color = ColorRGB::from_dict(value);
-                fm.write(&color.pack(fm.get_endian().clone()));
+                fm.write(&color.pack(*fm.get_endian()));
             },
-            "Color (RGBA)" => {
+            PropertyType::ColorRGBA => {
                 let color = ColorRGBA::from_dict(value);
-                fm.write(&color.pack(fm.get_endian().clone()));
+                fm.write(&color.pack(*fm.get_endian()));
             },
-            "Entity Pointer" => {
+            PropertyType::EntityPointer => {
                 let entity_pointer = EntityPointer::from_dict(value);
-                fm.write(&entity_pointer.pack(fm.get_endian().clone()));
+                fm.write(&entity_pointer.pack(*fm.get_endian()));
             },
-            _ => {
-                panic!("Unknown property value type: {}", value_type);
+            PropertyType::Unknown(_) => {
+                let data = match base64_decode(value.as_str().unwrap()) {
+                    Some(data) => data,
+                    None => panic!("Invalid base64 raw value for property value type: {}", value_type)
+                };
+                fm.write(&data);
             }
         }
     }
 
+    /// Reads a property. Panics if its value type is not one this library knows, see
+    /// `SceneFile::try_from_binary_with_options`.
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator, version: SceneFileVersion) {
-        let mut name_offset = fm.r_u32();
-        let mut class_name_offset = fm.r_u32();
-        // if its version 2 proto or version 2
-        match version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                class_name_offset += 4;
-                name_offset += 4;
-            },
-            _ => {}
+        if let Err(error) = self.unpack_with_span(fm, version, &ReadOptions::default()) {
+            panic!("{}", error);
         }
-        let pos = fm.tell();
-
-        fm.seek(class_name_offset as usize);
-        self.class_name = fm.r_str_jps();
-
-        fm.seek(name_offset as usize);
-        self.name = fm.r_str_jps();
+    }
 
-        fm.seek(pos);
+    // unpack, returning where the value (all of it, for a list) was in the data
+    fn unpack_with_span(&mut self, fm: &mut file_manipulator::FileManipulator, version: SceneFileVersion, options: &ReadOptions) -> Result<std::ops::Range<usize>, SceneFileError> {
+        let string_offset = version.header_adjustments().string_offset;
+        let name_offset = fm.r_u32() as usize + string_offset;
+        let class_name_offset = fm.r_u32() as usize + string_offset;
+        {
+            let mut fm = fm.save_position();
+            fm.seek_read(class_name_offset);
+            self.class_name = PropertyType::from_class_name(&fm.r_str_jps());
+            fm.seek_read(name_offset);
+            self.name = fm.r_str_jps();
+        }
 
         let data_type = fm.r_u32();
 
-        let mut list_mode = false;
-
-        match data_type {
-            0 => {
-                self.asset = false;
-                self.palette = false;
-                self.template = false;
-            },
-            1 => {
-                list_mode = true;
-                self.asset = false;
-                self.palette = false;
-                self.template = false;
-            },
-            2 => {
-                self.asset = true;
-                self.palette = false;
-                self.template = false;
-            },
-            3 => {
-                list_mode = true;
-                self.asset = true;
-                self.palette = false;
-                self.template = false;
+        let list_mode = match data_type_flags(data_type) {
+            Some((list_mode, asset, palette, template)) => {
+                self.asset = asset;
+                self.palette = palette;
+                self.template = template;
+                list_mode
             },
-            4 => {
-                self.palette = true;
-                self.asset = false;
-                self.template = false;
-            },
-            5 => {
-                list_mode = true;
-                self.template = true;
-                self.asset = false;
-                self.palette = false;
-            }
-            _ => {
-                panic!("Unknown property data storage type: {}", data_type);
-            }
-        }
+            None => return Err(SceneFileError::UnknownStorageType { property: self.name.clone(), data_type })
+        };
 
         let amount = fm.r_u32();
 
+        // the size of an unknown value can't be guessed, a wrong guess would misread everything after it
+        if let PropertyType::Unknown(class_name) = &self.class_name {
+            if !options.raw_value_sizes.contains_key(class_name) {
+                return Err(SceneFileError::UnknownPropertyType { property: self.name.clone(), class_name: class_name.clone() });
+            }
+        }
+
+        let value_start = fm.tell();
         self.value = match list_mode {
             true => {
                 let mut list = Vec::new();
                 for _ in 0..amount {
-                    list.push(Property::read_value_for_type(fm, &self.class_name, version.clone()));
+                    list.push(Property::read_value_for_type(fm, &self.class_name, version, options));
                 }
                 serde_json::Value::Array(list)
             },
             false => {
-                Property::read_value_for_type(fm, &self.class_name, version.clone())
+                Property::read_value_for_type(fm, &self.class_name, version, options)
             }
         };
+        return Ok(value_start..fm.tell());
     }
 
     pub fn pack(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>) -> Vec<u8> {
+        return self.pack_with(endian_type, strings_offsets_map, file_manipulator::BoolRepresentation::AllBytes);
+    }
+
+    /// Like `pack`, but bools are written with `bool_representation`.
+    pub fn pack_with(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, bool_representation: file_manipulator::BoolRepresentation) -> Vec<u8> {
+        return match self.try_pack_with(endian_type, strings_offsets_map, bool_representation) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    fn try_pack_with(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, bool_representation: file_manipulator::BoolRepresentation) -> Result<Vec<u8>, SceneFileError> {
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        fm.set_bool_representation(bool_representation);
 
         // write class name offset
         fm.w_u32(strings_offsets_map[&self.name]);
-        fm.w_u32(strings_offsets_map[&self.class_name]);
+        fm.w_u32(strings_offsets_map[self.class_name.as_str()]);
         
 
         // write data type
-        let list_mode = self.value.is_array();
-
-        let data_type = match (list_mode, self.asset, self.palette, self.template) {
-            (false, false, false, false) => 0, // single
-            (true, false, false, false) => 1, // list
-            (false, true, false, false) => 2, // asset
-            (true, true, false, false) => 3, // asset list
-            (false, false, true, false) => 4, // palette
-            (true, false, false, true) => 5, // template
-            _ => panic!("Unknown property data storage type")
+        let data_type = match self.data_type() {
+            Some(data_type) => data_type,
+            None => panic!(
+                "Property \"{}\" can't be stored: list {}, asset {}, palette {}, template {}. At most one of asset, palette and template may be set, and palette values can't be lists",
+                self.name, self.value.is_array(), self.asset, self.palette, self.template
+            )
         };
+        // template values only exist in list form, a single template value is stored as a list of one
+        let list_mode = self.value.is_array() || data_type == 5;
 
         fm.w_u32(data_type);
 
-        // write amount
-        let amount = match list_mode {
-            true => self.value.as_array().unwrap().len(),
-            false => 1
-        };
-        fm.w_u32(amount as u32);
-
         let mut values = Vec::new();
 
-        match list_mode {
-            true => {
-                for value in self.value.as_array().unwrap() {
+        match self.value.as_array() {
+            Some(array) => {
+                for value in array {
                     values.push(value);
                 }
             },
-            false => {
+            None => {
                 values.push(&self.value);
             }
         }
 
+        // write amount
+        let amount = match list_mode {
+            true => values.len(),
+            false => 1
+        };
+        fm.w_u32(checked_count(amount, "property value count")?);
+
         for value in values {
             Property::write_value_for_type(&mut fm, strings_offsets_map, value, &self.class_name);
         }
 
-        return fm.get_data().to_vec();
+        return Ok(fm.into_data());
+    }
+
+    /// Returns the on-disk data storage type for this property's flags and value shape, or `None`
+    /// for a combination the format can't store.
+    pub fn data_type(&self) -> Option<u32> {
+        return match (self.value.is_array(), self.asset, self.palette, self.template) {
+            (false, false, false, false) => Some(0), // single
+            (true, false, false, false) => Some(1), // list
+            (false, true, false, false) => Some(2), // asset
+            (true, true, false, false) => Some(3), // asset list
+            (false, false, true, false) => Some(4), // palette
+            (_, false, false, true) => Some(5), // template, always a list
+            _ => None
+        };
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
         let mut dict = serde_json::Map::new();
-        dict.insert("class_name".to_string(), serde_json::Value::String(self.class_name.clone()));
+        dict.insert("class_name".to_string(), serde_json::Value::String(self.class_name.to_string()));
         dict.insert("name".to_string(), serde_json::Value::String(self.name.clone()));
         dict.insert("asset".to_string(), serde_json::Value::Bool(self.asset));
         dict.insert("palette".to_string(), serde_json::Value::Bool(self.palette));
@@ -799,7 +1716,7 @@ impl Property {
     }
 
     pub fn from_dict(dict: &serde_json::Value) -> Property {
-        let class_name = dict["class_name"].as_str().unwrap().to_string();
+        let class_name = PropertyType::from_class_name(dict["class_name"].as_str().unwrap());
         let name = dict["name"].as_str().unwrap().to_string();
         let asset = dict["asset"].as_bool().unwrap();
         let palette = dict["palette"].as_bool().unwrap();
@@ -808,18 +1725,170 @@ impl Property {
         return Property::new(class_name, name, asset, palette, template, value)
     }
 
+    // checks everything from_dict reads, including that the value fits the class name
+    fn validate_dict(dict: &serde_json::Value, path: &str) -> Result<(), SceneParseError> {
+        let fields = DictValidator::new(dict, path)?;
+        fields.string("class_name", false)?;
+        fields.string("name", false)?;
+        fields.bool("asset", false)?;
+        fields.bool("palette", false)?;
+        fields.bool("template", false)?;
+        let class_name = PropertyType::from_class_name(dict["class_name"].as_str().unwrap());
+        let value = fields.required("value", class_name.as_str())?;
+        let expected = format!("a {} value", class_name);
+        match value.as_array() {
+            Some(values) => {
+                for (i, element) in values.iter().enumerate() {
+                    if !class_name.accepts(element) {
+                        return Err(DictValidator::error(&format!("{}[{}]", fields.path_of("value"), i), &expected, Some(element)));
+                    }
+                }
+            },
+            None => fields.check("value", &expected, false, |value| class_name.accepts(value))?
+        }
+        return Ok(());
+    }
+
+    // every value (or every element of a list) must fit the class name, or packing panics later
+    fn check_value(name: &str, class_name: &PropertyType, value: &serde_json::Value) -> Result<(), SceneFileError> {
+        let values = match value.as_array() {
+            Some(values) => values.iter().collect(),
+            None => vec![value]
+        };
+        for value in values {
+            if !class_name.accepts(value) {
+                return Err(SceneFileError::ValueTypeMismatch { property: name.to_string(), class_name: class_name.to_string(), value: value.clone() });
+            }
+        }
+        return Ok(());
+    }
+
+    /// Merges a property dict into this property. Array values are appended unless the dict has
+    /// `"replace": true`; any of `class_name`, `asset`, `palette` and `template` that are present
+    /// overwrite the current ones. Panics if a merged value doesn't fit the class name, see
+    /// `merge_in_dict_with_options`.
+    /// 
+    /// ```should_panic
+    /// use epic_mickey_lib_rs::scene_file::{Property, PropertyType};
+    /// 
+    /// let mut property = Property::new(PropertyType::String, "tags".to_string(), false, false, false, serde_json::json!(["a"]));
+    /// property.merge_in_dict(&serde_json::json!({ "value": [1.5] }));
+    /// ```
     pub fn merge_in_dict(&mut self, dict: &serde_json::Value) {
-        // if the value is an array, merge the arrays (add the new values)
-        if dict["value"].is_array() {
-            for value in dict["value"].as_array().unwrap() {
-                self.value.as_array_mut().unwrap().push(value.clone());
+        if let Err(error) = self.merge_in_dict_with_options(dict, &MergeOptions::default()) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Like `merge_in_dict`, with `options.array_merge` deciding how array values are combined.
+    /// `"replace": true` in the dict still replaces the array. The merged values are checked
+    /// against the class name (the one in the dict, if it has one) first, and on a mismatch the
+    /// property is left unchanged and `SceneFileError::ValueTypeMismatch` is returned.
+    pub fn merge_in_dict_with_options(&mut self, dict: &serde_json::Value, options: &MergeOptions) -> Result<(), SceneFileError> {
+        let class_name = match dict.get("class_name") {
+            Some(class_name) => PropertyType::from_class_name(class_name.as_str().unwrap()),
+            None => self.class_name.clone()
+        };
+        // if the value is an array, merge the arrays as the options say
+        let value = match dict.get("value") {
+            Some(value) => {
+                let replace = dict["replace"].as_bool().unwrap_or(false) || options.array_merge == ArrayMerge::Replace;
+                match (value.as_array(), self.value.as_array()) {
+                    (Some(merged_values), Some(values)) if !replace => {
+                        let mut values = values.clone();
+                        for value in merged_values {
+                            if options.array_merge == ArrayMerge::Union && values.contains(value) {
+                                continue;
+                            }
+                            values.push(value.clone());
+                        }
+                        serde_json::Value::Array(values)
+                    },
+                    _ => value.clone()
+                }
+            },
+            None => self.value.clone()
+        };
+        // a new class name has to fit the values kept from before too
+        if dict.get("value").is_some() || class_name != self.class_name {
+            Property::check_value(&self.name, &class_name, &value)?;
+        }
+        self.class_name = class_name;
+        self.value = value;
+        if let Some(asset) = dict.get("asset") {
+            self.asset = asset.as_bool().unwrap();
+        }
+        if let Some(palette) = dict.get("palette") {
+            self.palette = palette.as_bool().unwrap();
+        }
+        if let Some(template) = dict.get("template") {
+            self.template = template.as_bool().unwrap();
+        }
+        return Ok(());
+    }
+
+    /// Returns a dict that turns this property into `other` when passed to `merge_in_dict`, or
+    /// `None` if they are the same.
+    pub fn diff(&self, other: &Property) -> Option<serde_json::Value> {
+        let mut dict = serde_json::Map::new();
+        if self.class_name != other.class_name {
+            dict.insert("class_name".to_string(), serde_json::Value::String(other.class_name.to_string()));
+        }
+        if self.asset != other.asset {
+            dict.insert("asset".to_string(), serde_json::Value::Bool(other.asset));
+        }
+        if self.palette != other.palette {
+            dict.insert("palette".to_string(), serde_json::Value::Bool(other.palette));
+        }
+        if self.template != other.template {
+            dict.insert("template".to_string(), serde_json::Value::Bool(other.template));
+        }
+        if self.value != other.value {
+            match (self.value.as_array(), other.value.as_array()) {
+                // an append only needs the new values
+                (Some(values), Some(other_values)) if other_values.starts_with(values) => {
+                    dict.insert("value".to_string(), serde_json::Value::Array(other_values[values.len()..].to_vec()));
+                },
+                (Some(_), Some(_)) => {
+                    dict.insert("value".to_string(), other.value.clone());
+                    dict.insert("replace".to_string(), serde_json::Value::Bool(true));
+                },
+                _ => {
+                    dict.insert("value".to_string(), other.value.clone());
+                }
             }
-        } else {
-            self.value = dict["value"].clone();
         }
+        if dict.is_empty() {
+            return None;
+        }
+        dict.insert("name".to_string(), serde_json::Value::String(other.name.clone()));
+        return Some(serde_json::Value::Object(dict));
     }
 }
 
+// built-in component class names and their friendly names
+const COMPONENT_CLASS_NAMES: &[(&str, &str)] = &[
+    ("NiTransformationComponent", "Transformation"),
+    ("JPSTransformationComponent", "Transformation"),
+    ("NiInheritedTransformationComponent", "Inherited Transformation"),
+    ("NiSceneGraphComponent", "Scene Graph"),
+    ("JPSSceneGraphComponent", "Scene Graph"),
+    ("NiActorComponent", "Actor"),
+    ("NiLightComponent", "Light"),
+    ("JPSLightComponent", "Light"),
+    ("NiCameraComponent", "Camera"),
+    ("JPSCameraComponent", "Camera"),
+    ("NiShadowGeneratorComponent", "Shadow Generator"),
+];
+
+/// Component classes and the `Entity Pointer` property that links an entity to its parent, as
+/// (class name, property name). The property `NiSceneGraphComponent` links through has not been
+/// confirmed in the game's files, so it isn't listed; pass it to the `_with` functions, such as
+/// `SceneFile::walk_with`, along with any other links.
+pub const PARENT_LINK_PROPERTIES: &[(&str, &str)] = &[
+    ("NiInheritedTransformationComponent", "Source Entity"),
+];
+
 /// Represents a component in an entity, a collection of properties
 /// 
 /// # Fields
@@ -830,6 +1899,7 @@ impl Property {
 /// * `link_id` - The link ID of the component
 /// * `master_link_id` - The master link ID of the component
 /// * `properties` - The properties of the component
+#[derive(Clone)]
 pub struct Component {
     pub class_name: String,
     pub name: String,
@@ -851,42 +1921,78 @@ impl Component {
         }
     }
 
-    fn get_name_for_class_name(class_name: &str) -> String {
-        return match class_name {
-            "NiTransformationComponent" => "Transformation".to_string(),
-            "JPSTransformationComponent" => "Transformation".to_string(),
-            "NiSceneGraphComponent" => "Scene Graph".to_string(),
-            "JPSSceneGraphComponent" => "Scene Graph".to_string(),
-            "NiLightComponent" => "Light".to_string(),
-            "JPSLightComponent" => "Light".to_string(),
-            "NiCameraComponent" => "Camera".to_string(),
-            "JPSCameraComponent" => "Camera".to_string(),
-            _ => "Unknown".to_string()
+    /// Maps a component class to the friendly name used in JSON. Classes not in the built-in
+    /// table get their name split into words, without the "Ni" or "JPS" prefix and the
+    /// "Component" suffix: "JPSBossFightComponent" is "Boss Fight". A name given in JSON is
+    /// kept as it is.
+    /// 
+    /// This changes the JSON of scenes with classes outside the table: they used to be named
+    /// "Unknown" and now get the derived name. Only a class name that is empty once the prefix
+    /// and suffix are removed is still "Unknown".
+    pub fn get_name_for_class_name(class_name: &str) -> String {
+        return Component::get_name_for_class_name_with(class_name, &[]);
+    }
+
+    /// Like `get_name_for_class_name`, looking the class up in `extra`, as (class name, friendly
+    /// name) pairs, before the built-in table.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::Component;
+    /// let extra = [("JPSPaintComponent", "Paint and Thinner"), ("NiLightComponent", "Lamp")];
+    /// assert_eq!(Component::get_name_for_class_name_with("JPSPaintComponent", &extra), "Paint and Thinner");
+    /// assert_eq!(Component::get_name_for_class_name_with("NiLightComponent", &extra), "Lamp");
+    /// assert_eq!(Component::get_name_for_class_name_with("NiCameraComponent", &extra), "Camera");
+    /// assert_eq!(Component::get_name_for_class_name_with("JPSBossFightComponent", &extra), "Boss Fight");
+    /// ```
+    pub fn get_name_for_class_name_with(class_name: &str, extra: &[(&str, &str)]) -> String {
+        for (known_class_name, name) in extra.iter().chain(COMPONENT_CLASS_NAMES) {
+            if *known_class_name == class_name {
+                return name.to_string();
+            }
+        }
+        let base = class_name.strip_suffix("Component").unwrap_or(class_name);
+        let base = ["Ni", "JPS"].iter()
+            .filter_map(|prefix| base.strip_prefix(prefix))
+            .find(|rest| rest.starts_with(|c: char| c.is_ascii_uppercase()))
+            .unwrap_or(base);
+        if base.is_empty() {
+            return "Unknown".to_string();
+        }
+        // a space before each word: an upper case letter after a lower case one, or the last
+        // upper case letter of a run that is followed by a lower case one ("UIButton" is "UI Button")
+        let chars: Vec<char> = base.chars().collect();
+        let mut name = String::new();
+        for (i, c) in chars.iter().enumerate() {
+            let next_is_lowercase = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            let starts_word = i > 0 && c.is_uppercase() && (chars[i - 1].is_lowercase() || (chars[i - 1].is_uppercase() && next_is_lowercase));
+            if starts_word {
+                name.push(' ');
+            }
+            name.push(*c);
         }
+        return name;
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator, version: SceneFileVersion) {
-        let mut class_name_offset = fm.r_u32();
-        let mut template_id_string_offset = fm.r_u32();
-
-        match version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                class_name_offset += 4;
-                template_id_string_offset += 4;
-            },
-            _ => {}
+        if let Err(error) = self.unpack_with_spans(fm, version, &ReadOptions::default(), &mut Vec::new()) {
+            panic!("{}", error);
         }
+    }
 
-        let pos = fm.tell();
-
-        fm.seek(class_name_offset as usize);
-        self.class_name = fm.r_str_jps();
-
-        fm.seek(template_id_string_offset as usize);
-        let template_id_string = fm.r_str_jps();
-        self.template_id = ID::from_string(&template_id_string);
-
-        fm.seek(pos);
+    // unpack, adding where each property value was in the data to value_spans
+    fn unpack_with_spans(&mut self, fm: &mut file_manipulator::FileManipulator, version: SceneFileVersion, options: &ReadOptions, value_spans: &mut Vec<std::ops::Range<usize>>) -> Result<(), SceneFileError> {
+        let string_offset = version.header_adjustments().string_offset;
+        let class_name_offset = fm.r_u32() as usize + string_offset;
+        let template_id_string_offset = fm.r_u32() as usize + string_offset;
+
+        {
+            let mut fm = fm.save_position();
+            fm.seek_read(class_name_offset);
+            self.class_name = fm.r_str_jps();
+            fm.seek_read(template_id_string_offset);
+            let template_id_string = fm.r_str_jps();
+            self.template_id = ID::from_string(&template_id_string);
+        }
 
         self.link_id = ID::from_u32(fm.r_u32());
         self.master_link_id = ID::from_u32(fm.r_u32());
@@ -896,15 +2002,28 @@ impl Component {
         self.properties = Vec::new();
 
         for _ in 0..amount {
-            let mut property = Property::new("".to_string(), "".to_string(), false, false, false, serde_json::Value::Null);
-            property.unpack(fm, version.clone()); // Clone the version variable
+            let mut property = Property::new(PropertyType::Unknown("".to_string()), "".to_string(), false, false, false, serde_json::Value::Null);
+            value_spans.push(property.unpack_with_span(fm, version, options)?);
             self.properties.push(property);
         }
 
         self.name = Component::get_name_for_class_name(&self.class_name);
+        return Ok(());
     }
 
     pub fn pack(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>) -> Vec<u8> {
+        return self.pack_with(endian_type, strings_offsets_map, file_manipulator::BoolRepresentation::AllBytes);
+    }
+
+    /// Like `pack`, but bools are written with `bool_representation`.
+    pub fn pack_with(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, bool_representation: file_manipulator::BoolRepresentation) -> Vec<u8> {
+        return match self.try_pack_with(endian_type, strings_offsets_map, bool_representation) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    fn try_pack_with(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, bool_representation: file_manipulator::BoolRepresentation) -> Result<Vec<u8>, SceneFileError> {
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
 
         // write class name offset
@@ -916,23 +2035,28 @@ impl Component {
         fm.w_u32(self.master_link_id.to_u32());
 
         // write amount
-        fm.w_u32(self.properties.len() as u32);
+        fm.w_u32(checked_count(self.properties.len(), "property count")?);
 
         for property in &self.properties {
-            fm.write(&property.pack(endian_type.clone(), strings_offsets_map));
+            fm.write(&property.try_pack_with(endian_type, strings_offsets_map, bool_representation)?);
         }
 
-        return fm.get_data().to_vec();
+        return Ok(fm.into_data());
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
+        return self.to_dict_with(false);
+    }
+
+    /// Like `to_dict`, but with `emit_defaults` set `master_link_id` is written even when it is 0.
+    pub fn to_dict_with(&self, emit_defaults: bool) -> serde_json::Value {
         let mut dict = serde_json::Map::new();
         dict.insert("class_name".to_string(), serde_json::Value::String(self.class_name.clone()));
         dict.insert("name".to_string(), serde_json::Value::String(self.name.clone()));
         dict.insert("template_id".to_string(), serde_json::Value::String(self.template_id.to_string_no_leaders(4)));
         dict.insert("link_id".to_string(), serde_json::Value::Number(serde_json::Number::from(self.link_id.to_u32() as i64)));
         // if master link id is not 0
-        if self.master_link_id.to_u32() != 0 {
+        if emit_defaults || self.master_link_id.to_u32() != 0 {
             dict.insert("master_link_id".to_string(), serde_json::Value::Number(serde_json::Number::from(self.master_link_id.to_u32() as i64)));
         }
         let mut properties = Vec::new();
@@ -950,7 +2074,7 @@ impl Component {
             Some(name) => name.as_str().unwrap().to_string(),
             None => Component::get_name_for_class_name(&class_name)
         };
-        let template_id = ID::from_string(&dict["template_id"].as_str().unwrap());
+        let template_id = ID::from_string(dict["template_id"].as_str().unwrap());
         let link_id = ID::from_u32(dict["link_id"].as_u64().unwrap() as u32);
         // check if master link id is present
         let master_link_id = match dict.get("master_link_id") {
@@ -971,32 +2095,167 @@ impl Component {
         return Component::new(class_name, name, template_id, link_id, master_link_id, properties)
     }
 
-    pub fn merge_in_dict(&mut self, dict: &serde_json::Value) {
-        // if the properties are present, merge them
-        if dict.get("properties").is_some() {
-            for property in dict["properties"].as_array().unwrap() {
-                let mut found = false;
-                for self_property in &mut self.properties {
-                    if self_property.name == property["name"].as_str().unwrap() {
-                        self_property.merge_in_dict(property);
-                        found = true;
-                        break;
-                    }
-                }
-                if !found {
-                    self.properties.push(Property::from_dict(property));
-                }
-            }
+    fn validate_dict(dict: &serde_json::Value, path: &str) -> Result<(), SceneParseError> {
+        let fields = DictValidator::new(dict, path)?;
+        fields.string("class_name", false)?;
+        fields.string("name", true)?;
+        fields.id("template_id", false)?;
+        fields.u32("link_id", false)?;
+        fields.u32("master_link_id", true)?;
+        for (property_path, property) in fields.array("properties")? {
+            Property::validate_dict(property, &property_path)?;
         }
+        return Ok(());
     }
 
-    pub fn get_property(&self, name: &str) -> &Property {
+    pub fn merge_in_dict(&mut self, dict: &serde_json::Value) {
+        if let Err(error) = self.merge_in_dict_with_options(dict, &MergeOptions::default()) {
+            panic!("{}", error);
+        }
+    }
+
+    fn check_values(&self) -> Result<(), SceneFileError> {
         for property in &self.properties {
-            if property.name == name {
-                return property;
+            Property::check_value(&property.name, &property.class_name, &property.value)?;
+        }
+        return Ok(());
+    }
+
+    /// Like `merge_in_dict`, passing `options` on to the properties. Properties are matched by
+    /// name, and unmatched ones are only added if `options.add_unmatched` is set. Added properties
+    /// go at the end, or with `options.keep_property_order` next to the property they follow in
+    /// `dict`. A `"property_order"` list of names then puts the properties in that order. If a
+    /// property value doesn't fit its class name, the error is returned and the component is left
+    /// unchanged.
+    pub fn merge_in_dict_with_options(&mut self, dict: &serde_json::Value, options: &MergeOptions) -> Result<(), SceneFileError> {
+        // merge into a copy, so a bad value leaves this one as it was
+        let mut merged = self.clone();
+        merged.merge_in_place(dict, options)?;
+        *self = merged;
+        return Ok(());
+    }
+
+    fn merge_in_place(&mut self, dict: &serde_json::Value, options: &MergeOptions) -> Result<(), SceneFileError> {
+        // keep an explicitly given name
+        if let Some(name) = dict.get("name") {
+            self.name = name.as_str().unwrap().to_string();
+        }
+        // if the properties are present, merge them
+        if dict.get("properties").is_some() {
+            let properties = dict["properties"].as_array().unwrap();
+            // where the last merged property is, so the next added one can go after it
+            let mut last_position = None;
+            for (i, property) in properties.iter().enumerate() {
+                let name = property["name"].as_str().unwrap();
+                match self.properties.iter().position(|self_property| self_property.name == name) {
+                    Some(position) => {
+                        self.properties[position].merge_in_dict_with_options(property, options)?;
+                        last_position = Some(position);
+                    },
+                    None if options.add_unmatched => {
+                        let property = Property::from_dict(property);
+                        Property::check_value(&property.name, &property.class_name, &property.value)?;
+                        let position = match last_position {
+                            _ if !options.keep_property_order => self.properties.len(),
+                            Some(position) => position + 1,
+                            // nothing before it in dict, so go before the next property we have
+                            None => properties[i + 1..].iter()
+                                .find_map(|next| self.properties.iter().position(|self_property| self_property.name == next["name"].as_str().unwrap()))
+                                .unwrap_or(self.properties.len())
+                        };
+                        self.properties.insert(position, property);
+                        last_position = Some(position);
+                    },
+                    None => {}
+                }
             }
         }
-        panic!("Property not found: {}", name);
+        if let Some(order) = dict.get("property_order") {
+            let order: Vec<&str> = order.as_array().unwrap().iter().map(|name| name.as_str().unwrap()).collect();
+            self.reorder_properties(&order);
+        }
+        return Ok(());
+    }
+
+    /// Moves the properties named in `order` to the front, in that order. The other properties
+    /// keep their order after them, and names with no property are ignored.
+    /// 
+    /// The game stores properties as a list, and it is not known for every component whether
+    /// they are looked up by name or read by position, so reading and packing keep the order
+    /// they were in. Use this to put merged or built properties back in the order the game wrote.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, Property, PropertyType};
+    /// let mut component = ComponentBuilder::new("JPSTransformationComponent")
+    ///     .property(Property::new(PropertyType::Boolean, "b".to_string(), false, false, false, serde_json::json!([true])))
+    ///     .property(Property::new(PropertyType::Boolean, "c".to_string(), false, false, false, serde_json::json!([true])))
+    ///     .property(Property::new(PropertyType::Boolean, "a".to_string(), false, false, false, serde_json::json!([true])))
+    ///     .build();
+    /// component.reorder_properties(&["a", "b"]);
+    /// let names: Vec<&str> = component.properties.iter().map(|property| property.name.as_str()).collect();
+    /// assert_eq!(names, ["a", "b", "c"]);
+    /// ```
+    pub fn reorder_properties(&mut self, order: &[&str]) {
+        // stable, so the unnamed properties keep their order
+        self.properties.sort_by_key(|property| order.iter().position(|name| *name == property.name).unwrap_or(order.len()));
+    }
+
+    /// Returns a dict that turns this component into `other` when passed to `merge_in_dict`, or
+    /// `None` if nothing changed. Properties missing from `other` are not removed.
+    pub fn diff(&self, other: &Component) -> Option<serde_json::Value> {
+        let mut properties = Vec::new();
+        for other_property in &other.properties {
+            match self.try_get_property(&other_property.name) {
+                Some(property) => {
+                    if let Some(property_diff) = property.diff(other_property) {
+                        properties.push(property_diff);
+                    }
+                },
+                None => properties.push(other_property.to_dict())
+            }
+        }
+        let mut dict = serde_json::Map::new();
+        if !properties.is_empty() {
+            dict.insert("properties".to_string(), serde_json::Value::Array(properties));
+        }
+        let self_order = self.properties.iter().map(|property| property.name.as_str());
+        let other_order: Vec<&str> = other.properties.iter().map(|property| property.name.as_str()).collect();
+        if let Some(order) = patched_order(self_order, &other_order) {
+            dict.insert("property_order".to_string(), order);
+        }
+        if self.name != other.name {
+            dict.insert("name".to_string(), serde_json::Value::String(other.name.clone()));
+        }
+        if dict.is_empty() {
+            return None;
+        }
+        dict.insert("class_name".to_string(), serde_json::Value::String(other.class_name.clone()));
+        return Some(serde_json::Value::Object(dict));
+    }
+
+    pub fn try_get_property(&self, name: &str) -> Option<&Property> {
+        return self.properties.iter().find(|property| property.name == name);
+    }
+
+    pub fn try_get_property_mut(&mut self, name: &str) -> Option<&mut Property> {
+        return self.properties.iter_mut().find(|property| property.name == name);
+    }
+
+    /// Replaces the property with the same name, or appends it if there is none.
+    pub fn set_property(&mut self, property: Property) {
+        match self.try_get_property_mut(&property.name) {
+            Some(self_property) => *self_property = property,
+            None => self.properties.push(property)
+        }
+    }
+
+    pub fn get_property(&self, name: &str) -> &Property {
+        match self.try_get_property(name) {
+            Some(property) => return property,
+            None => panic!("Property not found: {}", name)
+        }
     }
 
     pub fn get_property_value(&self, name: &str) -> &serde_json::Value {
@@ -1004,6 +2263,67 @@ impl Component {
     }
 }
 
+/// Builds a `Component` one field at a time. IDs default to 0, the name defaults to the friendly
+/// name of the class and the property list starts empty.
+/// 
+/// # Example
+/// 
+/// ```
+/// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, ID};
+/// let component = ComponentBuilder::new("JPSTransformationComponent")
+///     .link_id(ID::from_u32(5))
+///     .build();
+/// assert_eq!(component.name, "Transformation");
+/// ```
+pub struct ComponentBuilder {
+    component: Component
+}
+
+impl ComponentBuilder {
+    pub fn new(class_name: &str) -> ComponentBuilder {
+        let name = Component::get_name_for_class_name(class_name);
+        return ComponentBuilder {
+            component: Component::new(class_name.to_string(), name, ID::from_u32(0), ID::from_u32(0), ID::from_u32(0), Vec::new())
+        };
+    }
+
+    pub fn name(mut self, name: &str) -> ComponentBuilder {
+        self.component.name = name.to_string();
+        return self;
+    }
+
+    pub fn template_id(mut self, template_id: ID) -> ComponentBuilder {
+        self.component.template_id = template_id;
+        return self;
+    }
+
+    pub fn link_id(mut self, link_id: ID) -> ComponentBuilder {
+        self.component.link_id = link_id;
+        return self;
+    }
+
+    pub fn master_link_id(mut self, master_link_id: ID) -> ComponentBuilder {
+        self.component.master_link_id = master_link_id;
+        return self;
+    }
+
+    /// Appends a property.
+    pub fn property(mut self, property: Property) -> ComponentBuilder {
+        self.component.properties.push(property);
+        return self;
+    }
+
+    /// Replaces the property list.
+    pub fn properties(mut self, properties: Vec<Property>) -> ComponentBuilder {
+        self.component.properties = properties;
+        return self;
+    }
+
+    pub fn build(self) -> Component {
+        return self.component;
+    }
+}
+
 /// Represents an entity in a scene, a collection of components
 /// 
 /// # Fields
@@ -1015,6 +2335,7 @@ impl Component {
 /// * `unknown` - Unknown value
 /// * `unknown_em2` - Unknown value (only present in version 2 prototype and version 2)
 /// * `components` - The components of the entity
+#[derive(Clone)]
 pub struct Entity {
     pub class_name: String,
     pub name: String,
@@ -1039,22 +2360,21 @@ impl Entity {
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator, version: SceneFileVersion) {
-        self.class_name = "JPSGeneralEntity".to_string();
-        let mut name_offset = fm.r_u32();
-
-        match version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                name_offset += 4;
-            },
-            _ => {}
+        if let Err(error) = self.unpack_with_spans(fm, version, &ReadOptions::default(), &mut Vec::new()) {
+            panic!("{}", error);
         }
+    }
 
-        let pos = fm.tell();
-
-        fm.seek(name_offset as usize);
-        self.name = fm.r_str_jps();
+    // unpack, adding where each property value was in the data to value_spans
+    fn unpack_with_spans(&mut self, fm: &mut file_manipulator::FileManipulator, version: SceneFileVersion, options: &ReadOptions, value_spans: &mut Vec<std::ops::Range<usize>>) -> Result<(), SceneFileError> {
+        self.class_name = "JPSGeneralEntity".to_string();
+        let name_offset = fm.r_u32() as usize + version.header_adjustments().string_offset;
 
-        fm.seek(pos);
+        {
+            let mut fm = fm.save_position();
+            fm.seek_read(name_offset);
+            self.name = fm.r_str_jps();
+        }
 
         self.link_id = ID::from_u32(fm.r_u32());
         self.master_link_id = ID::from_u32(fm.r_u32());
@@ -1073,12 +2393,25 @@ impl Entity {
 
         for _ in 0..amount {
             let mut component = Component::new("".to_string(), "".to_string(), ID::new(0), ID::new(0), ID::new(0), Vec::new());
-            component.unpack(fm, version.clone()); // Clone the version variable
+            component.unpack_with_spans(fm, version, options, value_spans)?;
             self.components.push(component);
         }
+        return Ok(());
     }
 
     pub fn pack(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, version: SceneFileVersion) -> Vec<u8> {
+        return self.pack_with(endian_type, strings_offsets_map, version, file_manipulator::BoolRepresentation::AllBytes);
+    }
+
+    /// Like `pack`, but bools are written with `bool_representation`.
+    pub fn pack_with(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, version: SceneFileVersion, bool_representation: file_manipulator::BoolRepresentation) -> Vec<u8> {
+        return match self.try_pack_with(endian_type, strings_offsets_map, version, bool_representation) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    fn try_pack_with(&self, endian_type: file_manipulator::EndianType, strings_offsets_map: &mut std::collections::HashMap<String, u32>, version: SceneFileVersion, bool_representation: file_manipulator::BoolRepresentation) -> Result<Vec<u8>, SceneFileError> {
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
 
         // write name offset
@@ -1099,38 +2432,42 @@ impl Entity {
         }
 
         // write amount
-        fm.w_u32(self.components.len() as u32);
+        fm.w_u32(checked_count(self.components.len(), "component count")?);
 
         for component in &self.components {
-            fm.write(&component.pack(endian_type.clone(), strings_offsets_map));
+            fm.write(&component.try_pack_with(endian_type, strings_offsets_map, bool_representation)?);
         }
 
-        return fm.get_data().to_vec();
+        return Ok(fm.into_data());
     }
 
     pub fn to_dict(&self, version: SceneFileVersion) -> serde_json::Value {
+        return self.to_dict_with(version, false);
+    }
+
+    /// Like `to_dict`, but with `emit_defaults` set `master_link_id`, `unknown` and (in version 2)
+    /// `unknown_em2` are written even when they are 0, here and in the components.
+    pub fn to_dict_with(&self, version: SceneFileVersion, emit_defaults: bool) -> serde_json::Value {
         let mut dict = serde_json::Map::new();
         dict.insert("class_name".to_string(), serde_json::Value::String(self.class_name.clone()));
         dict.insert("name".to_string(), serde_json::Value::String(self.name.clone()));
         dict.insert("link_id".to_string(), serde_json::Value::Number(serde_json::Number::from(self.link_id.to_u32() as i64)));
         // if master link id is not 0
-        if self.master_link_id.to_u32() != 0 {
+        if emit_defaults || self.master_link_id.to_u32() != 0 {
             dict.insert("master_link_id".to_string(), serde_json::Value::Number(serde_json::Number::from(self.master_link_id.to_u32() as i64)));
         }
-        if self.unknown != 0 {
+        if emit_defaults || self.unknown != 0 {
             dict.insert("unknown".to_string(), serde_json::Value::Number(serde_json::Number::from(self.unknown as i64)));
         }
         match version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                if self.unknown_em2 != 0 {
-                    dict.insert("unknown_em2".to_string(), serde_json::Value::Number(serde_json::Number::from_str(&self.unknown_em2.to_string()).unwrap()));
-                }
+            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 if emit_defaults || self.unknown_em2 != 0 => {
+                dict.insert("unknown_em2".to_string(), serde_json::Value::Number(serde_json::Number::from_str(&self.unknown_em2.to_string()).unwrap()));
             },
             _ => {}
         }
         let mut components = Vec::new();
         for component in &self.components {
-            components.push(component.to_dict());
+            components.push(component.to_dict_with(emit_defaults));
         }
         dict.insert("components".to_string(), serde_json::Value::Array(components));
         return serde_json::Value::Object(dict)
@@ -1171,32 +2508,534 @@ impl Entity {
         return Entity::new(class_name, name, link_id, master_link_id, unknown, unknown_em2, components)
     }
 
+    fn validate_dict(dict: &serde_json::Value, path: &str) -> Result<(), SceneParseError> {
+        let fields = DictValidator::new(dict, path)?;
+        fields.string("class_name", true)?;
+        fields.string("name", false)?;
+        fields.u32("link_id", false)?;
+        fields.u32("master_link_id", true)?;
+        fields.u32("unknown", true)?;
+        fields.u32("unknown_em2", true)?;
+        for (component_path, component) in fields.array("components")? {
+            Component::validate_dict(component, &component_path)?;
+        }
+        return Ok(());
+    }
+
     pub fn merge_in_dict(&mut self, dict: &serde_json::Value) {
+        if let Err(error) = self.merge_in_dict_with_options(dict, &MergeOptions::default()) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Like `merge_in_dict`, passing `options` on to the components. Components are matched by
+    /// class name, and unmatched ones are only added if `options.add_unmatched` is set. The plain
+    /// fields (`class_name`, `link_id`, `master_link_id`, `unknown`, `unknown_em2`) are only
+    /// replaced when the dict lists them under `"overwrite"`, so merging a whole entity dict keeps
+    /// the IDs of this one. A `"component_order"` list of class names puts the components in
+    /// that order. If a property value doesn't fit its class name, the error is returned and the
+    /// entity is left unchanged.
+    pub fn merge_in_dict_with_options(&mut self, dict: &serde_json::Value, options: &MergeOptions) -> Result<(), SceneFileError> {
+        // merge into a copy, so a bad value leaves this one as it was
+        let mut merged = self.clone();
+        merged.merge_in_place(dict, options)?;
+        *self = merged;
+        return Ok(());
+    }
+
+    fn merge_in_place(&mut self, dict: &serde_json::Value, options: &MergeOptions) -> Result<(), SceneFileError> {
+        if let Some(overwrite) = dict.get("overwrite") {
+            if let Some(class_name) = overwrite.get("class_name") {
+                self.class_name = class_name.as_str().unwrap().to_string();
+            }
+            if let Some(link_id) = overwrite.get("link_id") {
+                self.link_id = ID::from_u32(link_id.as_u64().unwrap() as u32);
+            }
+            if let Some(master_link_id) = overwrite.get("master_link_id") {
+                self.master_link_id = ID::from_u32(master_link_id.as_u64().unwrap() as u32);
+            }
+            if let Some(unknown) = overwrite.get("unknown") {
+                self.unknown = unknown.as_u64().unwrap() as u32;
+            }
+            if let Some(unknown_em2) = overwrite.get("unknown_em2") {
+                self.unknown_em2 = unknown_em2.as_u64().unwrap() as u32;
+            }
+        }
         // if the components are present, merge them
         if dict.get("components").is_some() {
             for component in dict["components"].as_array().unwrap() {
                 let mut found = false;
                 for self_component in &mut self.components {
                     if self_component.class_name == component["class_name"].as_str().unwrap() {
-                        self_component.merge_in_dict(component);
+                        self_component.merge_in_place(component, options)?;
                         found = true;
                         break;
                     }
                 }
-                if !found {
-                    self.components.push(Component::from_dict(component));
+                if !found && options.add_unmatched {
+                    let component = Component::from_dict(component);
+                    component.check_values()?;
+                    self.components.push(component);
                 }
             }
         }
+        if let Some(order) = dict.get("component_order") {
+            let order: Vec<&str> = order.as_array().unwrap().iter().map(|class_name| class_name.as_str().unwrap()).collect();
+            // stable, so the unnamed components keep their order
+            self.components.sort_by_key(|component| order.iter().position(|class_name| *class_name == component.class_name).unwrap_or(order.len()));
+        }
+        return Ok(());
+    }
+
+    /// Returns a dict that turns this entity into `other` when passed to `merge_in_dict`, or
+    /// `None` if nothing changed. Components missing from `other` are not removed.
+    pub fn diff(&self, other: &Entity) -> Option<serde_json::Value> {
+        let mut components = Vec::new();
+        for other_component in &other.components {
+            match self.try_get_component(&other_component.class_name) {
+                Some(component) => {
+                    if let Some(component_diff) = component.diff(other_component) {
+                        components.push(component_diff);
+                    }
+                },
+                None => components.push(other_component.to_dict())
+            }
+        }
+        let mut dict = serde_json::Map::new();
+        if !components.is_empty() {
+            dict.insert("components".to_string(), serde_json::Value::Array(components));
+        }
+        let self_order = self.components.iter().map(|component| component.class_name.as_str());
+        let other_order: Vec<&str> = other.components.iter().map(|component| component.class_name.as_str()).collect();
+        if let Some(order) = patched_order(self_order, &other_order) {
+            dict.insert("component_order".to_string(), order);
+        }
+        let mut overwrite = serde_json::Map::new();
+        if self.class_name != other.class_name {
+            overwrite.insert("class_name".to_string(), serde_json::Value::String(other.class_name.clone()));
+        }
+        if self.link_id != other.link_id {
+            overwrite.insert("link_id".to_string(), serde_json::Value::from(other.link_id.to_u32()));
+        }
+        if self.master_link_id != other.master_link_id {
+            overwrite.insert("master_link_id".to_string(), serde_json::Value::from(other.master_link_id.to_u32()));
+        }
+        if self.unknown != other.unknown {
+            overwrite.insert("unknown".to_string(), serde_json::Value::from(other.unknown));
+        }
+        if self.unknown_em2 != other.unknown_em2 {
+            overwrite.insert("unknown_em2".to_string(), serde_json::Value::from(other.unknown_em2));
+        }
+        if !overwrite.is_empty() {
+            dict.insert("overwrite".to_string(), serde_json::Value::Object(overwrite));
+        }
+        if dict.is_empty() {
+            return None;
+        }
+        dict.insert("name".to_string(), serde_json::Value::String(other.name.clone()));
+        return Some(serde_json::Value::Object(dict));
+    }
+
+    pub fn try_get_component(&self, class_name: &str) -> Option<&Component> {
+        return self.components.iter().find(|component| component.class_name == class_name);
+    }
+
+    pub fn try_get_component_mut(&mut self, class_name: &str) -> Option<&mut Component> {
+        return self.components.iter_mut().find(|component| component.class_name == class_name);
+    }
+
+    pub fn add_component(&mut self, component: Component) {
+        self.components.push(component);
+    }
+
+    /// Removes the first component with the given class name and returns it.
+    pub fn remove_component(&mut self, class_name: &str) -> Option<Component> {
+        let index = self.components.iter().position(|component| component.class_name == class_name)?;
+        return Some(self.components.remove(index));
     }
 
     pub fn get_component(&self, class_name: &str) -> &Component {
+        match self.try_get_component(class_name) {
+            Some(component) => return component,
+            None => panic!("Component not found: {}", class_name)
+        }
+    }
+
+    // the parent link property of a component class, with links taking priority over PARENT_LINK_PROPERTIES
+    fn parent_link_property<'a>(class_name: &str, links: &[(&'a str, &'a str)]) -> Option<&'a str> {
+        return links.iter().chain(PARENT_LINK_PROPERTIES)
+            .find(|(known_class_name, _)| *known_class_name == class_name)
+            .map(|(_, property_name)| *property_name);
+    }
+
+    /// The link ID of the parent entity, from the first component with a parent link (see
+    /// `PARENT_LINK_PROPERTIES`) that is set. A pointer of 0 means no parent.
+    pub fn parent_link_id(&self) -> Option<ID> {
+        return self.parent_link_id_with(&[]);
+    }
+
+    /// Like `parent_link_id`, also following `links`, given as (component class name, property
+    /// name). A class in `links` uses that property instead of the one in `PARENT_LINK_PROPERTIES`.
+    pub fn parent_link_id_with(&self, links: &[(&str, &str)]) -> Option<ID> {
         for component in &self.components {
-            if component.class_name == class_name {
-                return component;
+            let parent = Entity::parent_link_property(&component.class_name, links)
+                .and_then(|property_name| component.try_get_property(property_name).and_then(Property::as_entity_pointer));
+            if let Some(parent) = parent {
+                if parent.to_u128() != 0 {
+                    return Some(parent);
+                }
             }
         }
-        panic!("Component not found: {}", class_name);
+        return None;
+    }
+}
+
+/// Builds an `Entity` one field at a time. IDs and the unknown values default to 0, the class
+/// name to "JPSGeneralEntity" and the component list starts empty.
+/// 
+/// # Example
+/// 
+/// ```
+/// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, ID};
+/// let entity = EntityBuilder::new("Gremlin")
+///     .link_id(ID::from_u32(12))
+///     .component(ComponentBuilder::new("NiActorComponent").build())
+///     .build();
+/// assert!(entity.master_link_id == ID::from_u32(0));
+/// ```
+pub struct EntityBuilder {
+    entity: Entity
+}
+
+impl EntityBuilder {
+    pub fn new(name: &str) -> EntityBuilder {
+        return EntityBuilder {
+            entity: Entity::new("JPSGeneralEntity".to_string(), name.to_string(), ID::from_u32(0), ID::from_u32(0), 0, 0, Vec::new())
+        };
+    }
+
+    pub fn class_name(mut self, class_name: &str) -> EntityBuilder {
+        self.entity.class_name = class_name.to_string();
+        return self;
+    }
+
+    pub fn link_id(mut self, link_id: ID) -> EntityBuilder {
+        self.entity.link_id = link_id;
+        return self;
+    }
+
+    pub fn master_link_id(mut self, master_link_id: ID) -> EntityBuilder {
+        self.entity.master_link_id = master_link_id;
+        return self;
+    }
+
+    pub fn unknown(mut self, unknown: u32) -> EntityBuilder {
+        self.entity.unknown = unknown;
+        return self;
+    }
+
+    pub fn unknown_em2(mut self, unknown_em2: u32) -> EntityBuilder {
+        self.entity.unknown_em2 = unknown_em2;
+        return self;
+    }
+
+    /// Appends a component.
+    pub fn component(mut self, component: Component) -> EntityBuilder {
+        self.entity.components.push(component);
+        return self;
+    }
+
+    /// Replaces the component list.
+    pub fn components(mut self, components: Vec<Component>) -> EntityBuilder {
+        self.entity.components = components;
+        return self;
+    }
+
+    pub fn build(self) -> Entity {
+        return self.entity;
+    }
+}
+
+/// An `Entity Pointer` property whose target is not an entity in the scene file
+/// 
+/// # Fields
+/// 
+/// * `entity_name` - The name of the entity holding the property
+/// * `component_name` - The name of the component holding the property
+/// * `property_name` - The name of the property
+/// * `target` - The link ID the property points to
+pub struct DanglingEntityPointer {
+    pub entity_name: String,
+    pub component_name: String,
+    pub property_name: String,
+    pub target: ID
+}
+
+/// An error produced while reading a scene file.
+#[derive(Debug, PartialEq)]
+pub enum SceneFileError {
+    /// The file starts with the version 2 marker but the version word after the string section
+    /// is not one this library knows.
+    UnknownVersion { marker: u32, version_word: u32 },
+    /// A version code (as used in JSON) that is not 1, 2 or 3.
+    InvalidVersionCode(u32),
+    /// An `Entity Pointer` property value that does not fit in the u32 it is stored as.
+    PointerOutOfRange { entity: String, property: String, value: u64 },
+    /// An offset or count written while packing does not fit in a u32.
+    SceneTooLarge { section: &'static str, offset: u64 },
+    /// A merged property value that can't be written as the property's class name.
+    ValueTypeMismatch { property: String, class_name: String, value: serde_json::Value },
+    /// A property value type this library doesn't know, with no size in `ReadOptions::raw_value_sizes`.
+    UnknownPropertyType { property: String, class_name: String },
+    /// A property whose data storage type (single, list, asset, ...) is not one of the 6 known ones.
+    UnknownStorageType { property: String, data_type: u32 },
+    /// A scene read as `raw_data` has other fields set, which packing would drop.
+    RawDataModified,
+    /// `to_binary_preserving` can't keep the original bytes, for this reason.
+    NotPreservable(String),
+}
+
+impl std::fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneFileError::UnknownVersion { marker, version_word } => write!(f, "Unknown scene file version: marker 0x{:08X}, version word 0x{:08X}", marker, version_word),
+            SceneFileError::InvalidVersionCode(version) => write!(f, "Unknown scene file version: {}", version),
+            SceneFileError::PointerOutOfRange { entity, property, value } => write!(f, "Entity Pointer {} in property \"{}\" of entity \"{}\" does not fit in 32 bits", value, property, entity),
+            SceneFileError::SceneTooLarge { section, offset } => write!(f, "Scene file too large: {} {} does not fit in a u32", section, offset),
+            SceneFileError::ValueTypeMismatch { property, class_name, value } => write!(f, "Value {} of property \"{}\" is not a valid {}", value, property, class_name),
+            SceneFileError::UnknownPropertyType { property, class_name } => write!(f, "Unknown value type \"{}\" of property \"{}\", give its size in ReadOptions::raw_value_sizes", class_name, property),
+            SceneFileError::UnknownStorageType { property, data_type } => write!(f, "Unknown data storage type {} of property \"{}\"", data_type, property),
+            SceneFileError::RawDataModified => write!(f, "Scene file of an unknown version was changed, only its raw data can be packed"),
+            SceneFileError::NotPreservable(reason) => write!(f, "Can't write the scene over its original bytes: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+/// An error in scene file JSON, from `SceneFile::try_from_json`
+/// 
+/// # Variants
+/// 
+/// * `InvalidJson` - The text is not JSON, with the parser's message
+/// * `InvalidField` - A field is missing or holds the wrong kind of value. `path` is where it is,
+///   such as `objects[3].components[1].properties[0].class_name`
+#[derive(Debug, PartialEq)]
+pub enum SceneParseError {
+    InvalidJson(String),
+    InvalidField { path: String, expected: String, found: String },
+}
+
+impl std::fmt::Display for SceneParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneParseError::InvalidJson(message) => write!(f, "Invalid scene JSON: {}", message),
+            SceneParseError::InvalidField { path, expected, found } => write!(f, "{} expected {}, found {}", path, expected, found),
+        }
+    }
+}
+
+impl std::error::Error for SceneParseError {}
+
+fn is_u32(value: &serde_json::Value) -> bool {
+    return value.as_u64().is_some_and(|value| u32::try_from(value).is_ok());
+}
+
+// checks the fields of a JSON object for the validate_dict functions, naming the path of a bad one
+struct DictValidator<'a> {
+    dict: &'a serde_json::Value,
+    path: &'a str
+}
+
+impl<'a> DictValidator<'a> {
+    fn new(dict: &'a serde_json::Value, path: &'a str) -> Result<DictValidator<'a>, SceneParseError> {
+        if !dict.is_object() {
+            let path = if path.is_empty() { "the root" } else { path };
+            return Err(DictValidator::error(path, "an object", Some(dict)));
+        }
+        return Ok(DictValidator { dict, path });
+    }
+
+    fn error(path: &str, expected: &str, found: Option<&serde_json::Value>) -> SceneParseError {
+        let found = match found {
+            None => "nothing".to_string(),
+            Some(serde_json::Value::Null) => "null".to_string(),
+            Some(serde_json::Value::Bool(_)) => "a bool".to_string(),
+            Some(serde_json::Value::Number(number)) => format!("the number {}", number),
+            Some(serde_json::Value::String(string)) => format!("the string \"{}\"", string),
+            Some(serde_json::Value::Array(_)) => "an array".to_string(),
+            Some(serde_json::Value::Object(_)) => "an object".to_string()
+        };
+        return SceneParseError::InvalidField { path: path.to_string(), expected: expected.to_string(), found };
+    }
+
+    fn path_of(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            return key.to_string();
+        }
+        return format!("{}.{}", self.path, key);
+    }
+
+    // the value of a field that must be there
+    fn required(&self, key: &str, expected: &str) -> Result<&'a serde_json::Value, SceneParseError> {
+        return self.dict.get(key).ok_or_else(|| DictValidator::error(&self.path_of(key), expected, None));
+    }
+
+    fn check(&self, key: &str, expected: &str, optional: bool, is_valid: impl Fn(&serde_json::Value) -> bool) -> Result<(), SceneParseError> {
+        let value = match self.dict.get(key) {
+            Some(value) => value,
+            None if optional => return Ok(()),
+            None => return Err(DictValidator::error(&self.path_of(key), expected, None))
+        };
+        if !is_valid(value) {
+            return Err(DictValidator::error(&self.path_of(key), expected, Some(value)));
+        }
+        return Ok(());
+    }
+
+    fn string(&self, key: &str, optional: bool) -> Result<(), SceneParseError> {
+        return self.check(key, "a string", optional, |value| value.is_string());
+    }
+
+    fn bool(&self, key: &str, optional: bool) -> Result<(), SceneParseError> {
+        return self.check(key, "a bool", optional, |value| value.is_boolean());
+    }
+
+    fn u32(&self, key: &str, optional: bool) -> Result<(), SceneParseError> {
+        return self.check(key, "a u32", optional, is_u32);
+    }
+
+    fn id(&self, key: &str, optional: bool) -> Result<(), SceneParseError> {
+        return self.check(key, "an ID string such as \"1,a2,ff\"", optional, |value| value.as_str().is_some_and(|value| ID::try_from_string(value).is_ok()));
+    }
+
+    fn base64(&self, key: &str) -> Result<(), SceneParseError> {
+        return self.check(key, "a base64 string", true, |value| value.as_str().and_then(base64_decode).is_some());
+    }
+
+    // each element of an optional array field, with its path
+    fn array(&self, key: &str) -> Result<Vec<(String, &'a serde_json::Value)>, SceneParseError> {
+        self.check(key, "an array", true, |value| value.is_array())?;
+        let values = match self.dict.get(key) {
+            Some(values) => values.as_array().unwrap(),
+            None => return Ok(Vec::new())
+        };
+        return Ok(values.iter().enumerate().map(|(i, value)| (format!("{}[{}]", self.path_of(key), i), value)).collect());
+    }
+}
+
+// offsets in a scene file are u32, refuse to wrap one that is larger
+fn checked_offset(offset: u64, section: &'static str) -> Result<u32, SceneFileError> {
+    return u32::try_from(offset).map_err(|_| SceneFileError::SceneTooLarge { section, offset });
+}
+
+fn checked_count(count: usize, section: &'static str) -> Result<u32, SceneFileError> {
+    return u32::try_from(count).map_err(|_| SceneFileError::SceneTooLarge { section, offset: count as u64 });
+}
+
+/// How `merge_in_dict_with_options` combines an array property value with the one being merged in
+/// 
+/// # Variants
+/// 
+/// * `Append` - Add every merged value to the end of the array
+/// * `Replace` - Replace the array with the merged one
+/// * `Union` - Add the merged values that are not already in the array
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ArrayMerge {
+    #[default]
+    Append,
+    Replace,
+    Union,
+}
+
+// the order list a diff needs when merging, which appends what is new, doesn't give `other` order
+fn patched_order<'a>(self_order: impl Iterator<Item = &'a str>, other_order: &[&str]) -> Option<serde_json::Value> {
+    let self_order: Vec<&str> = self_order.collect();
+    let mut merged_order: Vec<&str> = self_order.iter().copied().filter(|key| other_order.contains(key)).collect();
+    merged_order.extend(other_order.iter().copied().filter(|key| !self_order.contains(key)));
+    if merged_order == other_order {
+        return None;
+    }
+    return Some(serde_json::Value::Array(other_order.iter().map(|key| serde_json::Value::from(*key)).collect()));
+}
+
+/// What `merge_in_dict_with_options` matches merged entities against
+/// 
+/// # Variants
+/// 
+/// * `Name` - The entity name, compared case-insensitively
+/// * `LinkId` - The entity link ID
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum EntityMatchKey {
+    #[default]
+    Name,
+    LinkId,
+}
+
+/// Options for `SceneFile::merge_in_dict_with_options`. The default is the behaviour of
+/// `merge_in_dict`.
+/// 
+/// # Fields
+/// 
+/// * `array_merge` - How array property values are combined
+/// * `entity_match_key` - What entities are matched by
+/// * `add_unmatched` - Whether entities, components and properties with no match are added
+/// * `keep_property_order` - Whether added properties are placed by their order in the merged dict
+///   instead of at the end of the component
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MergeOptions {
+    pub array_merge: ArrayMerge,
+    pub entity_match_key: EntityMatchKey,
+    pub add_unmatched: bool,
+    pub keep_property_order: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        return MergeOptions { array_merge: ArrayMerge::Append, entity_match_key: EntityMatchKey::Name, add_unmatched: true, keep_property_order: false };
+    }
+}
+
+/// Options for `SceneFile::try_from_binary_with_options`. The default is the behaviour of
+/// `try_from_binary`.
+/// 
+/// # Fields
+/// 
+/// * `raw_value_sizes` - The size in bytes of one value of each property value type this library
+///   doesn't know, by class name. Their values are kept as base64 raw bytes, in the byte order of
+///   the file. A type not listed is an `UnknownPropertyType` error
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ReadOptions {
+    pub raw_value_sizes: std::collections::HashMap<String, usize>,
+}
+
+/// Counts of what a scene file holds, made by `SceneFile::summary`
+/// 
+/// # Fields
+/// 
+/// * `entities` - The number of entities
+/// * `components` - The number of components in all entities
+/// * `component_classes` - The number of components of each class name
+/// * `properties` - The number of properties in all components
+/// * `scene_ids` - The number of entity IDs in the scene list
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SceneSummary {
+    pub entities: usize,
+    pub components: usize,
+    pub component_classes: std::collections::BTreeMap<String, usize>,
+    pub properties: usize,
+    pub scene_ids: usize,
+}
+
+impl std::fmt::Display for SceneSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Entities: {}", self.entities)?;
+        writeln!(f, "Components: {}", self.components)?;
+        for (class_name, count) in &self.component_classes {
+            writeln!(f, "  {}: {}", class_name, count)?;
+        }
+        writeln!(f, "Properties: {}", self.properties)?;
+        write!(f, "Scene IDs: {}", self.scene_ids)
     }
 }
 
@@ -1209,12 +3048,35 @@ impl Entity {
 /// * `em2_extra_strings` - Extra strings for EM2 (only present in version 2 prototype and version 2)
 /// * `unique_id` - The unique ID of the scene (only present in version 1 and version 2 prototype)
 /// * `version` - The version of the scene file
+/// * `raw_data` - The whole file, for a file whose version word is not recognised. The body of
+///   such a file is not parsed and `pack` writes these bytes back unchanged; it returns an error
+///   if any other field was set on such a scene.
+/// * `unknown_trailer` - Bytes after the scene ID list, written back after it
+/// * `bool_representation` - How `pack` writes bools. Reading a file sets it from the first true
+///   bool that isn't `FF FF FF FF`
+/// * `preserved` - The file as read by `from_binary_preserving`, `None` otherwise
 pub struct SceneFile {
     pub objects: Vec<Entity>,
     pub scene: Vec<ID>,
     pub em2_extra_strings: Vec<String>,
     pub unique_id: ID,
-    pub version: SceneFileVersion
+    pub version: SceneFileVersion,
+    pub raw_data: Vec<u8>,
+    pub unknown_trailer: Vec<u8>,
+    pub bool_representation: file_manipulator::BoolRepresentation,
+    pub preserved: Option<PreservedBinary>
+}
+
+/// The bytes of a scene file read with `SceneFile::from_binary_preserving`, along with what was
+/// read from them, so `SceneFile::to_binary_preserving` can tell which property values changed.
+pub struct PreservedBinary {
+    data: Vec<u8>,
+    endian_type: file_manipulator::EndianType,
+    // the scene as read, with the property values replaced by placeholders
+    structure: serde_json::Value,
+    // the property values as read and where they are in data, in entity, component, property order
+    values: Vec<serde_json::Value>,
+    value_spans: Vec<std::ops::Range<usize>>
 }
 
 impl SceneFile {
@@ -1224,29 +3086,28 @@ impl SceneFile {
             scene,
             em2_extra_strings,
             unique_id,
-            version
+            version,
+            raw_data: Vec::new(),
+            unknown_trailer: Vec::new(),
+            bool_representation: file_manipulator::BoolRepresentation::AllBytes,
+            preserved: None
         }
     }
 
     pub fn unpack(&mut self, fm: &mut file_manipulator::FileManipulator) {
-        // if version 2
-        match self.version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                fm.move_pos(4);
-            },
-            _ => {}
-        }
-
-        let mut data_offset = fm.r_u32();
-
-        match self.version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                data_offset += 8;
-            },
-            _ => {}
+        if let Err(error) = self.unpack_with_spans(fm, &ReadOptions::default(), &mut Vec::new()) {
+            panic!("{}", error);
         }
+    }
 
-        fm.seek(data_offset as usize);
+    // unpack, adding where each property value was in the data to value_spans
+    fn unpack_with_spans(&mut self, fm: &mut file_manipulator::FileManipulator, options: &ReadOptions, value_spans: &mut Vec<std::ops::Range<usize>>) -> Result<(), SceneFileError> {
+        // reading a 1 style bool switches this, see read_value_for_type
+        fm.set_bool_representation(file_manipulator::BoolRepresentation::AllBytes);
+        let adjustments = self.version.header_adjustments();
+        fm.move_pos(adjustments.marker_size as isize);
+        let data_offset = fm.r_u32() as usize + adjustments.data_offset;
+        fm.seek_read(data_offset);
 
         match self.version {
             SceneFileVersion::Version1 | SceneFileVersion::Version2Prototype => {
@@ -1272,7 +3133,7 @@ impl SceneFile {
         self.objects = Vec::new();
         for _ in 0..entity_amount {
             let mut entity = Entity::new("".to_string(), "".to_string(), ID::new(0), ID::new(0), 0, 0, Vec::new());
-            entity.unpack(fm, self.version.clone()); // Clone the version variable
+            entity.unpack_with_spans(fm, self.version, options, value_spans)?;
             self.objects.push(entity);
         }
 
@@ -1280,78 +3141,170 @@ impl SceneFile {
         for _ in 0..ref_ids_amount {
             self.scene.push(ID::from_u32(fm.r_u32()));
         }
+
+        // keep anything after the scene so it survives a round-trip
+        self.unknown_trailer = fm.get_data()[fm.tell()..].to_vec();
+        self.bool_representation = fm.bool_representation;
+        return Ok(());
+    }
+
+    // entity pointers are written as u32, find one that would not fit before writing anything
+    fn check_entity_pointers(&self) -> Result<(), SceneFileError> {
+        for entity in &self.objects {
+            for component in &entity.components {
+                for property in &component.properties {
+                    if property.class_name != PropertyType::EntityPointer {
+                        continue;
+                    }
+                    let values = match property.value.as_array() {
+                        Some(values) => values.iter().collect(),
+                        None => vec![&property.value]
+                    };
+                    for value in values {
+                        if let Some(value) = value.as_u64().filter(|value| *value > u32::MAX as u64) {
+                            return Err(SceneFileError::PointerOutOfRange { entity: entity.name.clone(), property: property.name.clone(), value });
+                        }
+                    }
+                }
+            }
+        }
+        return Ok(());
     }
 
-    fn add_string(fm: &mut file_manipulator::FileManipulator, strings_offsets_map: &mut std::collections::HashMap<String, u32>, start_offset: u32, string: &str) {
+    fn add_string(fm: &mut file_manipulator::FileManipulator, strings_offsets_map: &mut std::collections::HashMap<String, u32>, start_offset: u32, string: &str) -> Result<(), SceneFileError> {
         if !strings_offsets_map.contains_key(string) {
-            strings_offsets_map.insert(string.to_string(), fm.get_size() as u32 + start_offset);
+            let offset = checked_offset(fm.get_size() as u64 + start_offset as u64, "string heap offset")?;
+            strings_offsets_map.insert(string.to_string(), offset);
             fm.w_str_jps(string);
-            
         }
+        return Ok(());
     }
 
-    fn build_strings_and_map(&self) -> (Vec<u8>, std::collections::HashMap<String, u32>) {
-        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), file_manipulator::EndianType::BIG, file_manipulator::WriteMode::OVERWRITE);
-        let mut strings_offsets_map = std::collections::HashMap::new();
-        let start_offset = 4;
+    // Strings are deduplicated by content alone. That is safe for every use (entity and property
+    // names, class names, template IDs and String values): each one is stored as a plain JPS string
+    // and read back by offset with r_str_jps, so nothing about an entry depends on what refers to it.
+    // Strings are written in the order the entities, components and properties are walked, and the
+    // map is only ever looked up, never iterated, so the same scene always packs to the same bytes.
+    fn heap_strings(&self) -> Vec<std::borrow::Cow<'_, str>> {
+        let mut strings = Vec::new();
         // for entities
         for entity in &self.objects {
             // add name
-            SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &entity.name);
+            strings.push(entity.name.as_str().into());
             // for components
             for component in &entity.components {
                 // add class name
-                SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &component.class_name);
+                strings.push(component.class_name.as_str().into());
                 // add template id
-                SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &component.template_id.to_string_no_leaders(4));
+                strings.push(component.template_id.to_string_no_leaders(4).into());
                 // for properties
                 for property in &component.properties {
                     // add name
-                    SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &property.name);
+                    strings.push(property.name.as_str().into());
                     // add class name
-                    SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &property.class_name);
+                    strings.push(property.class_name.as_str().into());
                     // if its a string
-                    if property.class_name == "String" {
+                    if property.class_name == PropertyType::String {
                         // if its a list
                         if property.value.is_array() {
                             for value in property.value.as_array().unwrap() {
-                                SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &value.as_str().unwrap());
+                                strings.push(value.as_str().unwrap().into());
                             }
                         } else {
-                            SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &property.value.as_str().unwrap());
+                            strings.push(property.value.as_str().unwrap().into());
                         }
                     }
                 }
             }
         }
+        return strings;
+    }
+
+    fn build_strings_and_map(&self, endian_type: file_manipulator::EndianType, base: Option<&SceneFile>) -> Result<(Vec<u8>, std::collections::HashMap<String, u32>), SceneFileError> {
+        let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        let mut strings_offsets_map = std::collections::HashMap::new();
+        // offsets count from the data offset word, less what reading adds to them
+        let adjustments = self.version.header_adjustments();
+        let start_offset = (4 + adjustments.marker_size - adjustments.string_offset) as u32;
+        if let Some(base) = base {
+            for string in base.heap_strings() {
+                SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &string)?;
+            }
+        }
+        for string in self.heap_strings() {
+            SceneFile::add_string(&mut fm, &mut strings_offsets_map, start_offset, &string)?;
+        }
         // return data and map
-        return (fm.get_data().to_vec(), strings_offsets_map);
+        return Ok((fm.into_data(), strings_offsets_map));
     }
 
     pub fn pack(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
-        let (strings_data, mut strings_offsets_map) = self.build_strings_and_map();
+        return match self.try_pack(endian_type, None) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `pack`, but the string heap starts with the strings of `base`, in the order `base`
+    /// packs them, so strings the two share keep the offsets they have in `base`'s binary. New
+    /// strings come after them. Strings of `base` this scene no longer uses stay in the heap.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::scene_file::{EntityBuilder, SceneFile, SceneFileVersion, ID};
+    /// let scene = |names: &[&str]| {
+    ///     let objects = names.iter().map(|name| EntityBuilder::new(name).build()).collect();
+    ///     return SceneFile::new(objects, vec![], vec![], ID::from_u32(0), SceneFileVersion::Version1);
+    /// };
+    /// let base = scene(&["Gus", "Oswald"]);
+    /// let patched = scene(&["Oswald", "Gus", "Mickey"]);
+    /// let base_data = base.pack(EndianType::BIG);
+    /// let patched_data = patched.pack_with_base(EndianType::BIG, &base);
+    /// // a version 1 file starts with the offset of the data, right after the string heap
+    /// let base_heap_end = u32::from_be_bytes(base_data[0..4].try_into().unwrap()) as usize;
+    /// assert_eq!(patched_data[4..base_heap_end], base_data[4..base_heap_end]);
+    /// assert_ne!(patched.pack(EndianType::BIG)[4..base_heap_end], base_data[4..base_heap_end]);
+    /// ```
+    pub fn pack_with_base(&self, endian_type: file_manipulator::EndianType, base: &SceneFile) -> Vec<u8> {
+        return match self.try_pack(endian_type, Some(base)) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    fn try_pack(&self, endian_type: file_manipulator::EndianType, base: Option<&SceneFile>) -> Result<Vec<u8>, SceneFileError> {
+        // the layout of an unrecognised version is unknown, so write it back as it was read
+        if !self.raw_data.is_empty() {
+            let unchanged = self.objects.is_empty() && self.scene.is_empty() && self.em2_extra_strings.is_empty()
+                && self.unique_id.to_u128() == 0 && self.unknown_trailer.is_empty() && self.version == SceneFileVersion::Version2;
+            if !unchanged {
+                return Err(SceneFileError::RawDataModified);
+            }
+            return Ok(self.raw_data.clone());
+        }
+        self.check_entity_pointers()?;
+        let (strings_data, mut strings_offsets_map) = self.build_strings_and_map(endian_type, base)?;
         let mut fm = file_manipulator::FileManipulator::new(Vec::new(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        let adjustments = self.version.header_adjustments();
 
-        match self.version {
-            SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                fm.w_u32(0x01000001);
-            },
-            _ => {}
+        if adjustments.marker_size > 0 {
+            fm.w_u32(0x01000001);
         }
         // write data offset (length of strings data + 4)
-        fm.w_u32(strings_data.len() as u32 + 4);
+        let data_offset = checked_offset(strings_data.len() as u64 + 4, "data offset")?;
+        fm.w_u32(data_offset);
 
         // write the strings section
         fm.write(&strings_data);
 
-        match self.version {
-            SceneFileVersion::Version2 => {
-                fm.w_u32(0x02000002);
-            },
-            SceneFileVersion::Version2Prototype => {
-                fm.w_u32(0x02000001);
-            },
-            _ => {}
+        if let Some(version_word) = adjustments.version_word {
+            fm.w_u32(version_word);
+        }
+        // fill any gap the version leaves before the data
+        while fm.tell() < data_offset as usize + adjustments.data_offset {
+            fm.w_u8(0);
         }
 
         match self.version {
@@ -1363,7 +3316,7 @@ impl SceneFile {
 
         match self.version {
             SceneFileVersion::Version2Prototype | SceneFileVersion::Version2 => {
-                fm.w_u32(self.em2_extra_strings.len() as u32);
+                fm.w_u32(checked_count(self.em2_extra_strings.len(), "extra string count")?);
                 for string in &self.em2_extra_strings {
                     fm.w_str_jps(string);
                 }
@@ -1372,42 +3325,52 @@ impl SceneFile {
         }
 
         // write amount of entities
-        fm.w_u32(self.objects.len() as u32);
+        fm.w_u32(checked_count(self.objects.len(), "entity count")?);
         
         // write amount of ref ids
-        fm.w_u32(self.scene.len() as u32);
+        fm.w_u32(checked_count(self.scene.len(), "scene id count")?);
 
         for entity in &self.objects {
-            fm.write(&entity.pack(endian_type.clone(), &mut strings_offsets_map, self.version.clone()));
+            fm.write(&entity.try_pack_with(endian_type, &mut strings_offsets_map, self.version, self.bool_representation)?);
         }
 
         for id in &self.scene {
             fm.w_u32(id.to_u32());
         }
 
-        return fm.get_data().to_vec();
+        fm.write(&self.unknown_trailer);
+
+        return Ok(fm.into_data());
     }
 
     pub fn to_dict(&self) -> serde_json::Value {
+        return self.to_dict_with(false);
+    }
+
+    /// Like `to_dict`, but with `emit_defaults` set every field the version stores is written,
+    /// even when it is empty or 0. The result loads back to the same scene file; use it to get
+    /// JSON that diffs field by field.
+    pub fn to_dict_with(&self, emit_defaults: bool) -> serde_json::Value {
         let mut dict = serde_json::Map::new();
         // if objects are present
-        if self.objects.len() > 0 {
+        if emit_defaults || !self.objects.is_empty() {
             let mut objects = Vec::new();
             for object in &self.objects {
-                objects.push(object.to_dict(self.version.clone()));
+                objects.push(object.to_dict_with(self.version, emit_defaults));
             }
             dict.insert("objects".to_string(), serde_json::Value::Array(objects));
         }
         // if scene is present
-        if self.scene.len() > 0 {
+        if emit_defaults || !self.scene.is_empty() {
             let mut scene = Vec::new();
             for id in &self.scene {
                 scene.push(serde_json::Value::Number(serde_json::Number::from(id.to_u32() as i64)));
             }
             dict.insert("scene".to_string(), serde_json::Value::Array(scene));
         }
+        let is_version2 = matches!(self.version, SceneFileVersion::Version2Prototype | SceneFileVersion::Version2);
         // if em2 extra strings are present
-        if self.em2_extra_strings.len() > 0 {
+        if (emit_defaults && is_version2) || !self.em2_extra_strings.is_empty() {
             let mut em2_extra_strings = Vec::new();
             for string in &self.em2_extra_strings {
                 em2_extra_strings.push(serde_json::Value::String(string.clone()));
@@ -1415,17 +3378,70 @@ impl SceneFile {
             dict.insert("em2_extra_strings".to_string(), serde_json::Value::Array(em2_extra_strings));
         }
         // if unique id is present
-        if self.unique_id.to_u128() != 0 {
+        let has_unique_id = matches!(self.version, SceneFileVersion::Version1 | SceneFileVersion::Version2Prototype);
+        if (emit_defaults && has_unique_id) || self.unique_id.to_u128() != 0 {
             dict.insert("unique_id".to_string(), serde_json::Value::String(self.unique_id.to_string(16)));
         }
         // version, integer
-        dict.insert("version".to_string(), serde_json::Value::Number(serde_json::Number::from(self.version.clone() as u32)));
+        dict.insert("version".to_string(), serde_json::Value::Number(serde_json::Number::from(self.version.as_u32())));
+        // raw bytes this library could not parse, as base64
+        if !self.raw_data.is_empty() {
+            dict.insert("raw_data".to_string(), serde_json::Value::String(base64_encode(&self.raw_data)));
+        }
+        if !self.unknown_trailer.is_empty() {
+            dict.insert("unknown_trailer".to_string(), serde_json::Value::String(base64_encode(&self.unknown_trailer)));
+        }
+        if self.bool_representation == file_manipulator::BoolRepresentation::LeastSignificantByte {
+            dict.insert("bool_representation".to_string(), serde_json::Value::String("least_significant_byte".to_string()));
+        }
         return serde_json::Value::Object(dict);
     }
 
+    /// Counts the entities, components (in total and by class name), properties and scene IDs.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, ID, SceneFile, SceneFileVersion};
+    /// let entity = EntityBuilder::new("door")
+    ///     .component(ComponentBuilder::new("NiActorComponent").build())
+    ///     .component(ComponentBuilder::new("JPSTransformationComponent").build())
+    ///     .build();
+    /// let scene_file = SceneFile::new(vec![entity], vec![ID::new(1)], Vec::new(), ID::new(0), SceneFileVersion::Version2);
+    /// let summary = scene_file.summary();
+    /// assert_eq!(summary.components, 2);
+    /// assert_eq!(summary.component_classes["NiActorComponent"], 1);
+    /// println!("{}", summary);
+    /// ```
+    pub fn summary(&self) -> SceneSummary {
+        let mut summary = SceneSummary { entities: self.objects.len(), scene_ids: self.scene.len(), ..SceneSummary::default() };
+        for entity in &self.objects {
+            for component in &entity.components {
+                summary.components += 1;
+                *summary.component_classes.entry(component.class_name.clone()).or_insert(0) += 1;
+                summary.properties += component.properties.len();
+            }
+        }
+        return summary;
+    }
+
     pub fn to_json(&self) -> String {
-        // dont sort keys
-        return serde_json::to_string_pretty(&self.to_dict()).unwrap();
+        return self.to_json_with(true);
+    }
+
+    /// Like `to_json`, but without indentation or line breaks.
+    pub fn to_json_compact(&self) -> String {
+        return self.to_json_with(false);
+    }
+
+    pub fn to_json_with(&self, pretty: bool) -> String {
+        if pretty {
+            return serde_json::to_string_pretty(&self.to_dict()).unwrap();
+        }
+        return serde_json::to_string(&self.to_dict()).unwrap();
+    }
+
+    /// Like `to_json`, but built with `to_dict_with(true)` so fields that are 0 or empty are kept.
+    pub fn to_json_explicit(&self) -> String {
+        return serde_json::to_string_pretty(&self.to_dict_with(true)).unwrap();
     }
 
     pub fn to_json_path(&self, path: String) {
@@ -1433,10 +3449,31 @@ impl SceneFile {
         std::fs::write(path, data).unwrap();
     }
 
+    /// Writes the scene file. The output depends only on the scene, so packing the same scene
+    /// twice, or on another machine, gives the same bytes.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, ID, SceneFile, SceneFileVersion};
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// let objects = (0..20).map(|i| {
+    ///     let component = ComponentBuilder::new("NiActorComponent").name(&format!("actor {}", i)).build();
+    ///     EntityBuilder::new(&format!("entity {}", i)).component(component).build()
+    /// }).collect();
+    /// let scene_file = SceneFile::new(objects, Vec::new(), Vec::new(), ID::new(0), SceneFileVersion::Version2);
+    /// assert_eq!(scene_file.to_binary(EndianType::BIG), scene_file.to_binary(EndianType::BIG));
+    /// assert_eq!(SceneFile::from_json(&scene_file.to_json()).to_binary(EndianType::BIG), scene_file.to_binary(EndianType::BIG));
+    /// ```
     pub fn to_binary(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
         return self.pack(endian_type);
     }
 
+    /// Like `to_binary`, but returns an error instead of panicking when the string heap offsets or
+    /// the entity, component or value counts don't fit in a u32 (`SceneTooLarge`) or an `Entity Pointer` value is larger than
+    /// a u32 (`PointerOutOfRange`).
+    pub fn try_to_binary(&self, endian_type: file_manipulator::EndianType) -> Result<Vec<u8>, SceneFileError> {
+        return self.try_pack(endian_type, None);
+    }
+
     pub fn to_binary_path(&self, path: String, endian_type: file_manipulator::EndianType) {
         let data = self.to_binary(endian_type);
         std::fs::write(path, data).unwrap();
@@ -1474,7 +3511,43 @@ impl SceneFile {
         if dict.get("version").is_some() {
             version = SceneFileVersion::from_u32(dict["version"].as_u64().unwrap() as u32);
         }
-        return SceneFile::new(objects, scene, em2_extra_strings, unique_id, version);
+        let mut scene_file = SceneFile::new(objects, scene, em2_extra_strings, unique_id, version);
+        if let Some(raw_data) = dict["raw_data"].as_str() {
+            scene_file.raw_data = base64_decode(raw_data).expect("raw_data is not valid base64");
+        }
+        if let Some(unknown_trailer) = dict["unknown_trailer"].as_str() {
+            scene_file.unknown_trailer = base64_decode(unknown_trailer).expect("unknown_trailer is not valid base64");
+        }
+        if dict["bool_representation"].as_str() == Some("least_significant_byte") {
+            scene_file.bool_representation = file_manipulator::BoolRepresentation::LeastSignificantByte;
+        }
+        return scene_file;
+    }
+
+    /// Like `from_dict`, but checks the dict first and returns the path of the first field that
+    /// is missing or has the wrong type instead of panicking. Property values are checked against
+    /// their class names too.
+    pub fn try_from_dict(dict: &serde_json::Value) -> Result<SceneFile, SceneParseError> {
+        let fields = DictValidator::new(dict, "")?;
+        for (object_path, object) in fields.array("objects")? {
+            Entity::validate_dict(object, &object_path)?;
+        }
+        for (id_path, id) in fields.array("scene")? {
+            if !is_u32(id) {
+                return Err(DictValidator::error(&id_path, "a u32", Some(id)));
+            }
+        }
+        for (string_path, string) in fields.array("em2_extra_strings")? {
+            if !string.is_string() {
+                return Err(DictValidator::error(&string_path, "a string", Some(string)));
+            }
+        }
+        fields.id("unique_id", true)?;
+        fields.check("version", "a version code (1, 2 or 3)", true, |version| version.as_u64().is_some_and(|version| (1..=3).contains(&version)))?;
+        fields.base64("raw_data")?;
+        fields.base64("unknown_trailer")?;
+        fields.check("bool_representation", "\"all_bytes\" or \"least_significant_byte\"", true, |value| matches!(value.as_str(), Some("all_bytes" | "least_significant_byte")))?;
+        return Ok(SceneFile::from_dict(dict));
     }
 
     pub fn from_json(json: &str) -> SceneFile {
@@ -1482,6 +3555,20 @@ impl SceneFile {
         return SceneFile::from_dict(&dict);
     }
 
+    /// Like `from_json`, but returns an error naming the first bad field instead of panicking.
+    /// 
+    /// ```
+    /// use epic_mickey_lib_rs::scene_file::SceneFile;
+    /// 
+    /// let json = r#"{ "objects": [{ "name": "door", "link_id": 1, "components": [{ "clas_name": "NiActorComponent" }] }] }"#;
+    /// let error = SceneFile::try_from_json(json).err().unwrap();
+    /// assert_eq!(error.to_string(), "objects[0].components[0].class_name expected a string, found nothing");
+    /// ```
+    pub fn try_from_json(json: &str) -> Result<SceneFile, SceneParseError> {
+        let dict: serde_json::Value = serde_json::from_str(json).map_err(|error| SceneParseError::InvalidJson(error.to_string()))?;
+        return SceneFile::try_from_dict(&dict);
+    }
+
     pub fn from_json_path(path: String) -> SceneFile {
         // check if file exists
         if !std::path::Path::new(&path).exists() {
@@ -1491,28 +3578,219 @@ impl SceneFile {
         return SceneFile::from_json(&data);
     }
 
-    pub fn from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> SceneFile {
+    /// Detects the version of a binary scene file from its magic words. Version 2 files start with
+    /// 0x01000001 and have a version word after the string section, anything else is version 1.
+    pub fn detect_version(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<SceneFileVersion, SceneFileError> {
         let mut fm = file_manipulator::FileManipulator::new(data.to_vec(), endian_type, file_manipulator::WriteMode::OVERWRITE);
         let first_four_bytes = fm.r_u32();
-        let version = match first_four_bytes {
+        return match first_four_bytes {
             0x01000001 => {
-                let offset = fm.r_u32() + 4;
-                fm.seek(offset as usize);
-                let num = fm.r_u32();
-                match num {
-                    0x02000002 => SceneFileVersion::Version2,
-                    0x02000001 => SceneFileVersion::Version2Prototype,
-                    _ => panic!("Unknown version!!!")
+                // all version 2 variants have the same marker, the version word after the strings tells them apart
+                let string_section_end = fm.r_u32() as usize;
+                let mut version_word = 0;
+                for version in [SceneFileVersion::Version2, SceneFileVersion::Version2Prototype] {
+                    let adjustments = version.header_adjustments();
+                    fm.seek_read(string_section_end + adjustments.marker_size);
+                    version_word = fm.r_u32();
+                    if adjustments.version_word == Some(version_word) {
+                        return Ok(version);
+                    }
                 }
+                Err(SceneFileError::UnknownVersion { marker: first_four_bytes, version_word })
             },
-            _ => SceneFileVersion::Version1
+            _ => Ok(SceneFileVersion::Version1)
+        };
+    }
+
+    /// Detects the byte order of a binary scene file the way `detect_version` reads it. The version 2
+    /// marker reads the same either way, so the byte order is the one in which the string section
+    /// end it is followed by points at a known version word. Version 1 files have no marker, so
+    /// they give `None`, as does a file that matches both ways.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::scene_file::SceneFile;
+    /// // the marker, the end of an empty string section, and the version 2 prototype's version word
+    /// let big = [1, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 0, 2, 0, 0, 1];
+    /// let little = [1, 0, 0, 1, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 2];
+    /// assert!(SceneFile::detect_endian(&big) == Some(EndianType::BIG));
+    /// assert!(SceneFile::detect_endian(&little) == Some(EndianType::LITTLE));
+    /// assert!(SceneFile::detect_endian(&[0, 0, 0, 0]).is_none());
+    /// ```
+    pub fn detect_endian(data: &[u8]) -> Option<file_manipulator::EndianType> {
+        let has_version_word = |endian_type: file_manipulator::EndianType| -> bool {
+            if file_manipulator::read_u32_at(data, 0, endian_type) != Some(0x01000001) {
+                return false;
+            }
+            let Some(string_section_end) = file_manipulator::read_u32_at(data, 4, endian_type) else {
+                return false;
+            };
+            return [SceneFileVersion::Version2, SceneFileVersion::Version2Prototype].iter().any(|version| {
+                let adjustments = version.header_adjustments();
+                let version_word = file_manipulator::read_u32_at(data, string_section_end as usize + adjustments.marker_size, endian_type);
+                return version_word.is_some() && version_word == adjustments.version_word;
+            });
+        };
+        return match (has_version_word(file_manipulator::EndianType::BIG), has_version_word(file_manipulator::EndianType::LITTLE)) {
+            (true, false) => Some(file_manipulator::EndianType::BIG),
+            (false, true) => Some(file_manipulator::EndianType::LITTLE),
+            _ => None
+        };
+    }
+
+    /// Like `from_binary`, but returns an error for a file whose version is not recognised
+    /// instead of keeping it as `raw_data`, and for a property value type this library
+    /// doesn't know.
+    pub fn try_from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<SceneFile, SceneFileError> {
+        return SceneFile::try_from_binary_with_options(data, endian_type, &ReadOptions::default());
+    }
+
+    /// Like `try_from_binary`, with `options.raw_value_sizes` giving the size of property value
+    /// types this library doesn't know, so their values can be kept as raw bytes.
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, ID, Property, PropertyType, ReadOptions, SceneFile, SceneFileError, SceneFileVersion};
+    /// let raw = Property::new(PropertyType::Unknown("Vector4".to_string()), "Offset".to_string(), false, false, false, serde_json::json!("AAAAAAAAAAAAAAAAAAAAAA=="));
+    /// let entity = EntityBuilder::new("Door").component(ComponentBuilder::new("NiActorComponent").property(raw).build()).build();
+    /// let data = SceneFile::new(vec![entity], vec![], vec![], ID::from_u32(0), SceneFileVersion::Version1).pack(EndianType::BIG);
+    /// 
+    /// assert!(matches!(SceneFile::try_from_binary(&data, EndianType::BIG), Err(SceneFileError::UnknownPropertyType { .. })));
+    /// let mut options = ReadOptions::default();
+    /// options.raw_value_sizes.insert("Vector4".to_string(), 16);
+    /// let scene = SceneFile::try_from_binary_with_options(&data, EndianType::BIG, &options).unwrap();
+    /// assert_eq!(scene.pack(EndianType::BIG), data);
+    /// ```
+    pub fn try_from_binary_with_options(data: &[u8], endian_type: file_manipulator::EndianType, options: &ReadOptions) -> Result<SceneFile, SceneFileError> {
+        let version = SceneFile::detect_version(data, endian_type)?;
+        let mut fm = file_manipulator::FileManipulator::new(data.to_vec(), endian_type, file_manipulator::WriteMode::OVERWRITE);
+        let mut scene_file = SceneFile::new(Vec::new(), Vec::new(), Vec::new(), ID::new(0), version);
+        scene_file.unpack_with_spans(&mut fm, options, &mut Vec::new())?;
+        return Ok(scene_file);
+    }
+
+    /// Reads a binary scene file. A file with an unrecognised version word is not parsed: its
+    /// bytes are kept in `raw_data` (with `version` set to Version2, the layout that has
+    /// a version word) so it can still be written back unchanged.
+    pub fn from_binary(data: &[u8], endian_type: file_manipulator::EndianType) -> SceneFile {
+        return match SceneFile::try_from_binary(data, endian_type) {
+            Ok(scene_file) => scene_file,
+            Err(SceneFileError::UnknownVersion { .. }) => {
+                let mut scene_file = SceneFile::new(Vec::new(), Vec::new(), Vec::new(), ID::new(0), SceneFileVersion::Version2);
+                scene_file.raw_data = data.to_vec();
+                scene_file
+            }
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Reads a binary scene file like `from_binary` and keeps its bytes in `preserved`, so that
+    /// `to_binary_preserving` can write edited property values back into them.
+    pub fn from_binary_preserving(data: &[u8], endian_type: file_manipulator::EndianType) -> SceneFile {
+        let version = match SceneFile::detect_version(data, endian_type) {
+            Ok(version) => version,
+            // an unrecognised version is written back unchanged anyway
+            Err(_) => return SceneFile::from_binary(data, endian_type)
         };
-        fm.seek(0);
+        let mut fm = file_manipulator::FileManipulator::new(data.to_vec(), endian_type, file_manipulator::WriteMode::OVERWRITE);
         let mut scene_file = SceneFile::new(Vec::new(), Vec::new(), Vec::new(), ID::new(0), version);
-        scene_file.unpack(&mut fm);
+        let mut value_spans = Vec::new();
+        if let Err(error) = scene_file.unpack_with_spans(&mut fm, &ReadOptions::default(), &mut value_spans) {
+            panic!("{}", error);
+        }
+        let (structure, values) = scene_file.structure_and_values();
+        scene_file.preserved = Some(PreservedBinary { data: data.to_vec(), endian_type, structure, values, value_spans });
         return scene_file;
     }
 
+    /// Writes a scene file read with `from_binary_preserving` back over its original bytes. When
+    /// the only changes are to property values that keep their size (anything but strings and raw
+    /// values, with lists keeping their length), just those values are overwritten and every other
+    /// byte stays as it was read. Panics on any other change, a different `endian_type` or a scene
+    /// that was not read with `from_binary_preserving`; use `try_to_binary_preserving` to fall back
+    /// to `to_binary` yourself.
+    pub fn to_binary_preserving(&self, endian_type: file_manipulator::EndianType) -> Vec<u8> {
+        return match self.try_to_binary_preserving(endian_type) {
+            Ok(data) => data,
+            Err(error) => panic!("{}", error)
+        };
+    }
+
+    /// Like `to_binary_preserving`, but returns `NotPreservable` with the reason instead of
+    /// panicking when the original bytes can't be kept. A scene of an unknown version is written
+    /// back from its `raw_data`.
+    pub fn try_to_binary_preserving(&self, endian_type: file_manipulator::EndianType) -> Result<Vec<u8>, SceneFileError> {
+        let preserved = match &self.preserved {
+            Some(preserved) => preserved,
+            None if !self.raw_data.is_empty() => return self.try_to_binary(endian_type),
+            None => return Err(SceneFileError::NotPreservable("the scene was not read with from_binary_preserving".to_string()))
+        };
+        if preserved.endian_type != endian_type {
+            return Err(SceneFileError::NotPreservable("the endian type differs from the one the scene was read with".to_string()));
+        }
+        return self.patch_preserved(preserved);
+    }
+
+    // overwrite the changed property values in the preserved bytes
+    fn patch_preserved(&self, preserved: &PreservedBinary) -> Result<Vec<u8>, SceneFileError> {
+        let (structure, values) = self.structure_and_values();
+        if structure != preserved.structure {
+            return Err(SceneFileError::NotPreservable("something other than property values changed".to_string()));
+        }
+        let mut data = preserved.data.clone();
+        let mut index = 0;
+        for entity in &self.objects {
+            for component in &entity.components {
+                for property in &component.properties {
+                    let span = preserved.value_spans[index].clone();
+                    let changed = values[index] != preserved.values[index];
+                    index += 1;
+                    if !changed {
+                        continue;
+                    }
+                    // strings are offsets into the string heap and raw values have no known layout
+                    if matches!(property.class_name, PropertyType::String | PropertyType::Unknown(_)) {
+                        return Err(SceneFileError::NotPreservable(format!("the {} value of property \"{}\" changed", property.class_name, property.name)));
+                    }
+                    let mut fm = file_manipulator::FileManipulator::new(Vec::new(), preserved.endian_type, file_manipulator::WriteMode::OVERWRITE);
+                    fm.set_bool_representation(self.bool_representation);
+                    let property_values = match property.value.as_array() {
+                        Some(property_values) => property_values.iter().collect(),
+                        None => vec![&property.value]
+                    };
+                    for value in property_values {
+                        Property::write_value_for_type(&mut fm, &mut std::collections::HashMap::new(), value, &property.class_name);
+                    }
+                    if fm.get_size() != span.len() {
+                        return Err(SceneFileError::NotPreservable(format!("the value of property \"{}\" changed size", property.name)));
+                    }
+                    data[span].copy_from_slice(fm.get_data());
+                }
+            }
+        }
+        return Ok(data);
+    }
+
+    // the dict of the scene with each property value replaced by a placeholder that only keeps
+    // whether it is a list and how long, and the values themselves in order
+    fn structure_and_values(&self) -> (serde_json::Value, Vec<serde_json::Value>) {
+        let mut dict = self.to_dict();
+        let mut values = Vec::new();
+        for entity in dict["objects"].as_array_mut().into_iter().flatten() {
+            for component in entity["components"].as_array_mut().into_iter().flatten() {
+                for property in component["properties"].as_array_mut().into_iter().flatten() {
+                    let value = property["value"].take();
+                    property["value"] = match value.as_array() {
+                        Some(list) => serde_json::Value::Array(vec![serde_json::Value::Null; list.len()]),
+                        None => serde_json::Value::Null
+                    };
+                    values.push(value);
+                }
+            }
+        }
+        return (dict, values);
+    }
+
     pub fn from_binary_path(path: String, endian_type: file_manipulator::EndianType) -> SceneFile {
         // check if file exists
         if !std::path::Path::new(&path).exists() {
@@ -1522,35 +3800,58 @@ impl SceneFile {
         return SceneFile::from_binary(&data, endian_type);
     }
 
+    /// Merges a scene file dict into this one. Panics if a merged property value doesn't fit the
+    /// property's class name.
     pub fn merge_in_dict(&mut self, dict: &serde_json::Value) {
-        // if the objects are present, merge them
+        if let Err(error) = self.merge_in_dict_with_options(dict, &MergeOptions::default()) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Like `merge_in_dict`, with `options` deciding how entities are matched, how array property
+    /// values are combined and whether unmatched entities, components and properties are added.
+    /// With `ArrayMerge::Replace` or `ArrayMerge::Union`, merging the same dict twice gives the
+    /// same result as merging it once. Returns `SceneFileError::ValueTypeMismatch` for the first
+    /// property value that doesn't fit its class name, and leaves the scene unchanged.
+    pub fn merge_in_dict_with_options(&mut self, dict: &serde_json::Value, options: &MergeOptions) -> Result<(), SceneFileError> {
+        // if the objects are present, merge them into a copy, so a bad value leaves the scene as it was
         if dict.get("objects").is_some() {
+            let mut objects = self.objects.clone();
             for object in dict["objects"].as_array().unwrap() {
                 let mut found = false;
-                for self_object in &mut self.objects {
-                    if self_object.name.to_lowercase() == object["name"].as_str().unwrap().to_lowercase() {
-                        self_object.merge_in_dict(object);
+                for self_object in &mut objects {
+                    let matches = match options.entity_match_key {
+                        EntityMatchKey::Name => self_object.name.to_lowercase() == object["name"].as_str().unwrap().to_lowercase(),
+                        // an object without a link ID matches nothing
+                        EntityMatchKey::LinkId => object["link_id"].as_u64().is_some_and(|link_id| self_object.link_id == ID::new(link_id as u128))
+                    };
+                    if matches {
+                        self_object.merge_in_place(object, options)?;
                         found = true;
                         break;
                     }
                 }
-                if !found {
-                    self.objects.push(Entity::from_dict(object));
+                if !found && options.add_unmatched {
+                    let object = Entity::from_dict(object);
+                    for component in &object.components {
+                        component.check_values()?;
+                    }
+                    objects.push(object);
                 }
             }
+            self.objects = objects;
+        }
+        if let Some(order) = dict.get("object_order") {
+            let order: Vec<String> = order.as_array().unwrap().iter().map(|name| name.as_str().unwrap().to_lowercase()).collect();
+            // stable, so the unnamed entities keep their order
+            self.objects.sort_by_key(|object| order.iter().position(|name| *name == object.name.to_lowercase()).unwrap_or(order.len()));
         }
         // if the scene is present, merge it
         if dict.get("scene").is_some() {
             for id in dict["scene"].as_array().unwrap() {
-                let mut found = false;
-                for self_id in &mut self.scene {
-                    if self_id.to_u32() == id.as_u64().unwrap() as u32 {
-                        found = true;
-                        break;
-                    }
-                }
-                if !found {
-                    self.scene.push(ID::from_u32(id.as_u64().unwrap() as u32));
+                let id = ID::from_u32(id.as_u64().unwrap() as u32);
+                if !self.scene.contains(&id) {
+                    self.scene.push(id);
                 }
             }
         }
@@ -1577,6 +3878,7 @@ impl SceneFile {
         if dict.get("version").is_some() {
             self.version = SceneFileVersion::from_u32(dict["version"].as_u64().unwrap() as u32);
         }
+        return Ok(());
     }
 
     pub fn merge_in_json(&mut self, json: &str) {
@@ -1584,6 +3886,329 @@ impl SceneFile {
         self.merge_in_dict(&dict);
     }
 
+    /// Builds a patch in the shape `merge_in_dict` takes, holding only what `other` changes or adds
+    /// compared to this scene file. Merging the patch into a copy of this scene file gives `other`
+    /// as long as `other` only changes, adds or reorders things: `merge_in_dict` can't remove
+    /// entities, components, properties, scene IDs or extra strings, so those removals are not in
+    /// the patch. A changed order is given as `"object_order"`, `"component_order"` and
+    /// `"property_order"` lists.
+    pub fn diff(&self, other: &SceneFile) -> serde_json::Value {
+        let mut dict = serde_json::Map::new();
+        let mut objects = Vec::new();
+        for other_object in &other.objects {
+            match self.get_entity_by_name(&other_object.name) {
+                Some(object) => {
+                    if let Some(object_diff) = object.diff(other_object) {
+                        objects.push(object_diff);
+                    }
+                },
+                None => objects.push(other_object.to_dict(other.version))
+            }
+        }
+        if !objects.is_empty() {
+            dict.insert("objects".to_string(), serde_json::Value::Array(objects));
+        }
+        let self_order: Vec<String> = self.objects.iter().map(|object| object.name.to_lowercase()).collect();
+        let other_order: Vec<String> = other.objects.iter().map(|object| object.name.to_lowercase()).collect();
+        let other_order: Vec<&str> = other_order.iter().map(|name| name.as_str()).collect();
+        if let Some(order) = patched_order(self_order.iter().map(|name| name.as_str()), &other_order) {
+            dict.insert("object_order".to_string(), order);
+        }
+        let scene: Vec<serde_json::Value> = other.scene.iter().filter(|id| !self.scene.contains(id)).map(|id| serde_json::Value::from(id.to_u32())).collect();
+        if !scene.is_empty() {
+            dict.insert("scene".to_string(), serde_json::Value::Array(scene));
+        }
+        let em2_extra_strings: Vec<serde_json::Value> = other.em2_extra_strings.iter().filter(|string| !self.em2_extra_strings.contains(string)).map(|string| serde_json::Value::String(string.clone())).collect();
+        if !em2_extra_strings.is_empty() {
+            dict.insert("em2_extra_strings".to_string(), serde_json::Value::Array(em2_extra_strings));
+        }
+        if self.unique_id != other.unique_id {
+            dict.insert("unique_id".to_string(), serde_json::Value::String(other.unique_id.to_string(16)));
+        }
+        if self.version != other.version {
+            dict.insert("version".to_string(), serde_json::Value::from(other.version.as_u32()));
+        }
+        return serde_json::Value::Object(dict);
+    }
+
+    pub fn add_entity(&mut self, entity: Entity) {
+        self.objects.push(entity);
+    }
+
+    /// Removes the entity with the given link ID and returns it. If `remove_from_scene` is set,
+    /// the ID is also removed from `scene` so no reference to the removed entity is left behind.
+    pub fn remove_entity_by_link_id(&mut self, id: u32, remove_from_scene: bool) -> Option<Entity> {
+        let id = ID::from_u32(id);
+        let index = self.objects.iter().position(|entity| entity.link_id == id)?;
+        if remove_from_scene {
+            self.scene.retain(|scene_id| *scene_id != id);
+        }
+        return Some(self.objects.remove(index));
+    }
+
+    /// Finds an entity by name, ignoring case (the same matching `merge_in_dict` uses).
+    pub fn get_entity_by_name(&self, name: &str) -> Option<&Entity> {
+        return self.objects.iter().find(|entity| entity.name.to_lowercase() == name.to_lowercase());
+    }
+
+    pub fn get_entity_by_name_mut(&mut self, name: &str) -> Option<&mut Entity> {
+        return self.objects.iter_mut().find(|entity| entity.name.to_lowercase() == name.to_lowercase());
+    }
+
+    pub fn get_entity_by_link_id(&self, id: u32) -> Option<&Entity> {
+        return self.objects.iter().find(|entity| entity.link_id == ID::from_u32(id));
+    }
+
+    pub fn get_entity_by_link_id_mut(&mut self, id: u32) -> Option<&mut Entity> {
+        return self.objects.iter_mut().find(|entity| entity.link_id == ID::from_u32(id));
+    }
+
+    /// Returns the entities that have a component with the given class name, such as "NiLightComponent".
+    pub fn entities_with_component(&self, class_name: &str) -> Vec<&Entity> {
+        return self.objects.iter().filter(|entity| entity.try_get_component(class_name).is_some()).collect();
+    }
+
+    /// Returns the entities whose parent link (see `Entity::parent_link_id`) points to `link_id`.
+    pub fn children_of(&self, link_id: u32) -> Vec<&Entity> {
+        return self.children_of_with(link_id, &[]);
+    }
+
+    /// Like `children_of`, also following `links` (see `Entity::parent_link_id_with`).
+    pub fn children_of_with(&self, link_id: u32, links: &[(&str, &str)]) -> Vec<&Entity> {
+        let link_id = ID::from_u32(link_id);
+        return self.objects.iter()
+            .filter(|entity| entity.link_id != link_id && entity.parent_link_id_with(links) == Some(link_id))
+            .collect();
+    }
+
+    /// Returns the entities without a parent. A parent link to an entity that isn't in the scene,
+    /// or to the entity itself, counts as no parent.
+    pub fn roots(&self) -> Vec<&Entity> {
+        return self.roots_with(&[]);
+    }
+
+    /// Like `roots`, also following `links` (see `Entity::parent_link_id_with`).
+    pub fn roots_with(&self, links: &[(&str, &str)]) -> Vec<&Entity> {
+        let link_ids: std::collections::HashSet<ID> = self.objects.iter().map(|entity| entity.link_id).collect();
+        return self.objects.iter()
+            .filter(|entity| match entity.parent_link_id_with(links) {
+                Some(parent) => parent == entity.link_id || !link_ids.contains(&parent),
+                None => true
+            })
+            .collect();
+    }
+
+    /// Visits the entity hierarchy depth first, starting from each of the `roots`, with the depth
+    /// of every entity (0 for a root). Children are visited in `objects` order. Entities whose
+    /// parent links form a loop have no root above them and aren't visited.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, Property, PropertyType, SceneFile, SceneFileVersion, ID};
+    /// let child_of = |name: &str, link_id: u32, parent: u32| {
+    ///     let source = Property::new(PropertyType::EntityPointer, "Source Entity".to_string(), false, false, false, serde_json::json!(parent));
+    ///     return EntityBuilder::new(name)
+    ///         .link_id(ID::from_u32(link_id))
+    ///         .component(ComponentBuilder::new("NiInheritedTransformationComponent").property(source).build())
+    ///         .build();
+    /// };
+    /// let objects = vec![
+    ///     child_of("Hand", 3, 2),
+    ///     EntityBuilder::new("Mickey").link_id(ID::from_u32(1)).build(),
+    ///     child_of("Arm", 2, 1),
+    ///     child_of("Brush", 4, 3),
+    /// ];
+    /// let scene = SceneFile::new(objects, vec![], vec![], ID::from_u32(0), SceneFileVersion::Version1);
+    /// assert_eq!(scene.children_of(2)[0].name, "Hand");
+    /// 
+    /// let mut tree = Vec::new();
+    /// scene.walk(|entity, depth| tree.push(format!("{}{}", "  ".repeat(depth), entity.name)));
+    /// assert_eq!(tree, ["Mickey", "  Arm", "    Hand", "      Brush"]);
+    /// ```
+    pub fn walk(&self, visitor: impl FnMut(&Entity, usize)) {
+        self.walk_with(&[], visitor);
+    }
+
+    /// Like `walk`, also following `links` (see `Entity::parent_link_id_with`).
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, Property, PropertyType, SceneFile, SceneFileVersion, ID};
+    /// // a made-up link, for a component this library doesn't know
+    /// let parent = Property::new(PropertyType::EntityPointer, "Parent".to_string(), false, false, false, serde_json::json!(1));
+    /// let objects = vec![
+    ///     EntityBuilder::new("Mickey").link_id(ID::from_u32(1)).build(),
+    ///     EntityBuilder::new("Hat").link_id(ID::from_u32(2)).component(ComponentBuilder::new("JPSAttachComponent").property(parent).build()).build(),
+    /// ];
+    /// let scene = SceneFile::new(objects, vec![], vec![], ID::from_u32(0), SceneFileVersion::Version1);
+    /// assert_eq!(scene.roots().len(), 2);
+    /// 
+    /// let mut tree = Vec::new();
+    /// scene.walk_with(&[("JPSAttachComponent", "Parent")], |entity, depth| tree.push(format!("{}{}", "  ".repeat(depth), entity.name)));
+    /// assert_eq!(tree, ["Mickey", "  Hat"]);
+    /// ```
+    pub fn walk_with(&self, links: &[(&str, &str)], mut visitor: impl FnMut(&Entity, usize)) {
+        let mut children: std::collections::HashMap<ID, Vec<&Entity>> = std::collections::HashMap::new();
+        for entity in &self.objects {
+            if let Some(parent) = entity.parent_link_id_with(links) {
+                if parent != entity.link_id {
+                    children.entry(parent).or_default().push(entity);
+                }
+            }
+        }
+        let mut visited = std::collections::HashSet::new();
+        // children are pushed in reverse so they come off the stack in order
+        let mut stack: Vec<(&Entity, usize)> = self.roots_with(links).into_iter().rev().map(|entity| (entity, 0)).collect();
+        while let Some((entity, depth)) = stack.pop() {
+            // entities sharing a link ID would otherwise be visited again under each other
+            if !visited.insert(entity as *const Entity) {
+                continue;
+            }
+            visitor(entity, depth);
+            if let Some(entity_children) = children.get(&entity.link_id) {
+                stack.extend(entity_children.iter().rev().map(|child| (*child, depth + 1)));
+            }
+        }
+    }
+
+    /// Calls `f` with every value of every `String` property, in both single and list values, so
+    /// they can be read or rewritten. Rewritten strings are written to the string heap on the next
+    /// `pack`.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// # use epic_mickey_lib_rs::file_manipulator::EndianType;
+    /// # use epic_mickey_lib_rs::scene_file::{ComponentBuilder, EntityBuilder, Property, PropertyType, SceneFile, SceneFileVersion, ID};
+    /// let labels = Property::new(PropertyType::String, "Labels".to_string(), false, false, false, serde_json::json!(["Hello", "Bye"]));
+    /// let entity = EntityBuilder::new("Sign").component(ComponentBuilder::new("JPSSignComponent").property(labels).build()).build();
+    /// let mut scene = SceneFile::new(vec![entity], vec![], vec![], ID::from_u32(0), SceneFileVersion::Version1);
+    /// scene.for_each_string_value(|text| *text = text.replace("Hello", "Bonjour"));
+    /// 
+    /// let scene = SceneFile::from_binary(&scene.pack(EndianType::BIG), EndianType::BIG);
+    /// let value = &scene.objects[0].components[0].get_property("Labels").value;
+    /// assert_eq!(value, &serde_json::json!(["Bonjour", "Bye"]));
+    /// ```
+    pub fn for_each_string_value(&mut self, mut f: impl FnMut(&mut String)) {
+        for entity in &mut self.objects {
+            for component in &mut entity.components {
+                for property in &mut component.properties {
+                    if property.class_name != PropertyType::String {
+                        continue;
+                    }
+                    match &mut property.value {
+                        serde_json::Value::String(text) => f(text),
+                        serde_json::Value::Array(values) => {
+                            for value in values {
+                                if let serde_json::Value::String(text) = value {
+                                    f(text);
+                                }
+                            }
+                        },
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the entities with a property of the given name on any of their components.
+    pub fn entities_with_property(&self, property_name: &str) -> Vec<&Entity> {
+        return self.objects.iter()
+            .filter(|entity| entity.components.iter().any(|component| component.try_get_property(property_name).is_some()))
+            .collect();
+    }
+
+    fn used_link_ids(&self) -> Vec<u128> {
+        let mut ids = Vec::new();
+        for entity in &self.objects {
+            ids.push(entity.link_id.to_u128());
+            for component in &entity.components {
+                ids.push(component.link_id.to_u128());
+            }
+        }
+        return ids;
+    }
+
+    /// Returns the smallest non-zero u32 not used as a link ID by any entity or component.
+    pub fn next_free_link_id(&self) -> ID {
+        let used: std::collections::HashSet<u128> = self.used_link_ids().into_iter().collect();
+        let mut id: u32 = 1;
+        while used.contains(&(id as u128)) {
+            id = id.checked_add(1).expect("Every 32 bit link ID is in use");
+        }
+        return ID::from_u32(id);
+    }
+
+    /// Gives the entity the next free link ID, adds it and returns the assigned ID.
+    pub fn add_entity_autoid(&mut self, mut entity: Entity) -> ID {
+        let id = self.next_free_link_id();
+        entity.link_id = id;
+        self.add_entity(entity);
+        return id;
+    }
+
+    /// Returns the link IDs shared by more than one entity or component, in ascending order.
+    pub fn find_id_collisions(&self) -> Vec<u32> {
+        let mut counts: std::collections::BTreeMap<u128, usize> = std::collections::BTreeMap::new();
+        for id in self.used_link_ids() {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+        let mut collisions = Vec::new();
+        for (id, count) in counts {
+            if count > 1 && id <= u32::MAX as u128 {
+                collisions.push(id as u32);
+            }
+        }
+        return collisions;
+    }
+
+    /// Returns the IDs in `scene` that don't match the link ID of any entity in `objects`.
+    pub fn validate_references(&self) -> Vec<ID> {
+        let link_ids: std::collections::HashSet<ID> = self.objects.iter().map(|entity| entity.link_id).collect();
+        let mut missing = Vec::new();
+        for id in &self.scene {
+            if !link_ids.contains(id) {
+                missing.push(*id);
+            }
+        }
+        return missing;
+    }
+
+    /// Returns every `Entity Pointer` property value that points to a link ID no entity has, once
+    /// for each such value of a list. A pointer of 0 is treated as unset and never reported.
+    pub fn find_dangling_entity_pointers(&self) -> Vec<DanglingEntityPointer> {
+        let link_ids: std::collections::HashSet<ID> = self.objects.iter().map(|entity| entity.link_id).collect();
+        let mut dangling = Vec::new();
+        for entity in &self.objects {
+            for component in &entity.components {
+                for property in &component.properties {
+                    if property.class_name != PropertyType::EntityPointer {
+                        continue;
+                    }
+                    let values = match property.value.as_array() {
+                        Some(values) => values.iter().collect(),
+                        None => vec![&property.value]
+                    };
+                    // compared as the full ID, a pointer too large for the file is still dangling
+                    for target in values.into_iter().filter_map(|value| value.as_u64()).map(|value| ID::new(value as u128)) {
+                        if target.to_u128() != 0 && !link_ids.contains(&target) {
+                            dangling.push(DanglingEntityPointer {
+                                entity_name: entity.name.clone(),
+                                component_name: component.name.clone(),
+                                property_name: property.name.clone(),
+                                target
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        return dangling;
+    }
+
     pub fn merge_in_json_path(&mut self, path: String) {
         // check if file exists
         if !std::path::Path::new(&path).exists() {
@@ -1592,4 +4217,288 @@ impl SceneFile {
         let data = std::fs::read_to_string(path).unwrap();
         self.merge_in_json(&data);
     }
-}
\ No newline at end of file
+}
+
+/// Parses a binary scene file and writes it back, returning the offset of the first byte where
+/// the output differs from `data`. See `asset::assert_roundtrip`.
+pub fn assert_roundtrip(data: &[u8], endian_type: file_manipulator::EndianType) -> Result<(), usize> {
+    return asset::assert_roundtrip::<SceneFile>(data, endian_type);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_manipulator::EndianType;
+
+    const VERSIONS: [SceneFileVersion; 3] = [SceneFileVersion::Version1, SceneFileVersion::Version2Prototype, SceneFileVersion::Version2];
+
+    fn property(class_name: PropertyType, name: &str, value: serde_json::Value) -> Property {
+        return Property::new(class_name, name.to_string(), false, false, false, value);
+    }
+
+    fn sample_scene(version: SceneFileVersion) -> SceneFile {
+        let door = EntityBuilder::new("Door")
+            .link_id(ID::from_u32(1))
+            .component(ComponentBuilder::new("JPSTransformationComponent")
+                .property(property(PropertyType::Point3, "Position", serde_json::json!([{ "x": 1.0, "y": 2.0, "z": 3.0 }])))
+                .build())
+            .component(ComponentBuilder::new("JPSSignComponent")
+                .property(property(PropertyType::String, "Labels", serde_json::json!(["Open", "Shut", "Open"])))
+                .property(property(PropertyType::Integer, "Count", serde_json::json!([-2])))
+                .build())
+            .build();
+        let key = EntityBuilder::new("Key")
+            .link_id(ID::from_u32(2))
+            .component(ComponentBuilder::new("JPSSignComponent")
+                .property(property(PropertyType::EntityPointer, "Door", serde_json::json!([1])))
+                .build())
+            .build();
+        let em2_extra_strings = match version {
+            SceneFileVersion::Version1 => Vec::new(),
+            _ => vec!["extra".to_string()]
+        };
+        return SceneFile::new(vec![door, key], vec![ID::from_u32(1)], em2_extra_strings, ID::from_u32(0), version);
+    }
+
+    // the string section, between the data offset word and the data
+    fn string_section(data: &[u8], version: SceneFileVersion, endian_type: EndianType) -> &[u8] {
+        let start = version.header_adjustments().marker_size;
+        let data_offset = file_manipulator::read_u32_at(data, start, endian_type).unwrap() as usize;
+        return &data[start + 4..start + data_offset];
+    }
+
+    #[test]
+    fn packs_both_endians() {
+        for version in VERSIONS {
+            let scene = sample_scene(version);
+            let big = scene.pack(EndianType::BIG);
+            let little = scene.pack(EndianType::LITTLE);
+            assert_eq!(big.len(), little.len());
+            // the heap holds only strings and their byte lengths, so byte order doesn't change it
+            assert_eq!(string_section(&big, version, EndianType::BIG), string_section(&little, version, EndianType::LITTLE));
+            assert_ne!(big, little);
+
+            let from_big = SceneFile::from_binary(&big, EndianType::BIG);
+            let from_little = SceneFile::from_binary(&little, EndianType::LITTLE);
+            assert_eq!(from_big.to_dict(), from_little.to_dict());
+            assert_eq!(from_big.pack(EndianType::BIG), big);
+            assert_eq!(from_little.pack(EndianType::LITTLE), little);
+            assert_eq!(from_big.pack(EndianType::LITTLE), little);
+        }
+    }
+
+    #[test]
+    fn names_component_classes() {
+        assert_eq!(Component::get_name_for_class_name("NiInheritedTransformationComponent"), "Inherited Transformation");
+        assert_eq!(Component::get_name_for_class_name("JPSSceneGraphComponent"), "Scene Graph");
+        assert_eq!(Component::get_name_for_class_name("JPSBossFightComponent"), "Boss Fight");
+        assert_eq!(Component::get_name_for_class_name("JPSUIButtonComponent"), "UI Button");
+        assert_eq!(Component::get_name_for_class_name("Nickname"), "Nickname");
+        assert_eq!(Component::get_name_for_class_name("Component"), "Unknown");
+        // a name stored in JSON wins over the derived one
+        let component = Component::from_dict(&serde_json::json!({ "class_name": "NiActorComponent", "name": "Player", "template_id": "00,00,00,00", "link_id": 1 }));
+        assert_eq!(component.name, "Player");
+    }
+
+    #[test]
+    fn finds_dangling_pointers_in_lists_and_past_u32() {
+        let mut scene = sample_scene(SceneFileVersion::Version1);
+        let pointers = property(PropertyType::EntityPointer, "Targets", serde_json::json!([1, 0, 3, 0x1_0000_0001u64]));
+        scene.objects[1].components[0].properties.push(pointers);
+        let targets: Vec<u128> = scene.find_dangling_entity_pointers().iter().map(|pointer| pointer.target.to_u128()).collect();
+        // 0x1_0000_0001 would be 1, a real entity, if it were cut to 32 bits
+        assert_eq!(targets, [3, 0x1_0000_0001]);
+    }
+
+    #[test]
+    fn bools_round_trip_in_each_representation() {
+        let encodings = [
+            (file_manipulator::BoolRepresentation::AllBytes, [0xFF, 0xFF, 0xFF, 0xFF]),
+            (file_manipulator::BoolRepresentation::LeastSignificantByte, [0x00, 0x00, 0x00, 0x01])
+        ];
+        for (bool_representation, true_bytes) in encodings {
+            let mut scene = sample_scene(SceneFileVersion::Version1);
+            scene.objects[0].components[1].properties.push(property(PropertyType::Boolean, "Locked", serde_json::json!([false, true])));
+            scene.bool_representation = bool_representation;
+            let data = scene.pack(EndianType::BIG);
+            let mut values = [0; 8];
+            values[4..].copy_from_slice(&true_bytes);
+            assert!(data.windows(8).any(|window| window == values));
+
+            let read = SceneFile::from_binary(&data, EndianType::BIG);
+            assert!(read.bool_representation == bool_representation);
+            assert_eq!(read.objects[0].components[1].properties[2].value, serde_json::json!([false, true]));
+            assert_eq!(read.pack(EndianType::BIG), data);
+            // the JSON keeps it too
+            assert_eq!(SceneFile::from_dict(&read.to_dict()).pack(EndianType::BIG), data);
+        }
+    }
+
+    #[test]
+    fn diff_merges_back_to_the_other_scene() {
+        let base = sample_scene(SceneFileVersion::Version2);
+        let mut modified = sample_scene(SceneFileVersion::Version2);
+        modified.objects.swap(0, 1);
+        let door = &mut modified.objects[1];
+        door.link_id = ID::from_u32(7);
+        door.unknown = 3;
+        door.components.swap(0, 1);
+        let sign = &mut door.components[0];
+        sign.properties.swap(0, 1);
+        sign.properties.insert(1, property(PropertyType::Float, "Width", serde_json::json!([1.5])));
+        sign.properties[2].value = serde_json::json!(["Shut"]);
+        modified.objects.push(EntityBuilder::new("Lamp").link_id(ID::from_u32(3)).build());
+
+        let mut merged = sample_scene(SceneFileVersion::Version2);
+        merged.merge_in_dict(&base.diff(&modified));
+        assert_eq!(merged.to_dict(), modified.to_dict());
+        assert_eq!(merged.diff(&modified), serde_json::json!({}));
+    }
+
+    #[test]
+    fn merging_an_entity_dict_keeps_its_ids() {
+        let mut scene = sample_scene(SceneFileVersion::Version1);
+        let mut door = scene.objects[0].to_dict(SceneFileVersion::Version1);
+        door["link_id"] = serde_json::json!(9);
+        door["master_link_id"] = serde_json::json!(9);
+        scene.merge_in_dict(&serde_json::json!({ "objects": [door] }));
+        assert!(scene.objects[0].link_id == ID::from_u32(1));
+        assert!(scene.objects[0].master_link_id == ID::from_u32(0));
+
+        scene.merge_in_dict(&serde_json::json!({ "objects": [{ "name": "Door", "overwrite": { "link_id": 9 } }] }));
+        assert!(scene.objects[0].link_id == ID::from_u32(9));
+    }
+
+    #[test]
+    fn storage_types_round_trip() {
+        let mut representable = 0;
+        for flags in 0..16 {
+            let (list, asset, palette, template) = (flags & 1 != 0, flags & 2 != 0, flags & 4 != 0, flags & 8 != 0);
+            let value = if list { serde_json::json!([5, 6]) } else { serde_json::json!(5) };
+            let stored = Property::new(PropertyType::Integer, "Flags".to_string(), asset, palette, template, value);
+            if stored.data_type().is_none() {
+                continue;
+            }
+            representable += 1;
+            let mut scene = sample_scene(SceneFileVersion::Version1);
+            scene.objects[1].components[0].properties.push(Property::from_dict(&stored.to_dict()));
+            let read = SceneFile::from_binary(&scene.pack(EndianType::BIG), EndianType::BIG);
+            let read = read.objects[1].components[0].get_property("Flags");
+            // template values are stored as a list, so a single one reads back as a list of one
+            let mut expected = stored.clone();
+            if template && !list {
+                expected.value = serde_json::json!([5]);
+            }
+            assert_eq!(read.to_dict(), expected.to_dict(), "list {}, asset {}, palette {}, template {}", list, asset, palette, template);
+        }
+        // single, list, asset, asset list, palette, template and template list
+        assert_eq!(representable, 7);
+    }
+
+    #[test]
+    fn shared_heap_strings_round_trip() {
+        for version in VERSIONS {
+            let mut scene = sample_scene(version);
+            let template_id = ID::from_u32(0x12345678);
+            let template_text = template_id.to_string_no_leaders(4);
+            let mut clash = ComponentBuilder::new("JPSClashComponent")
+                .template_id(template_id)
+                .property(property(PropertyType::String, "Integer", serde_json::json!(["JPSSignComponent", "Labels", "Integer", template_text.as_str()])))
+                .build();
+            // a property named after another property's class and a class named after a value
+            clash.properties.push(property(PropertyType::Integer, "String", serde_json::json!([1])));
+            scene.objects[0].components.push(clash);
+            for endian_type in [EndianType::BIG, EndianType::LITTLE] {
+                let data = scene.pack(endian_type);
+                let read = SceneFile::from_binary(&data, endian_type);
+                assert_eq!(read.to_dict(), scene.to_dict());
+                assert_eq!(read.pack(endian_type), data);
+            }
+        }
+    }
+
+    #[test]
+    fn raw_scenes_pack_only_unchanged() {
+        let mut data = sample_scene(SceneFileVersion::Version2).pack(EndianType::BIG);
+        // the version word after the string section
+        let data_offset = file_manipulator::read_u32_at(&data, 4, EndianType::BIG).unwrap() as usize;
+        data[4 + data_offset..8 + data_offset].copy_from_slice(&0x02000009u32.to_be_bytes());
+        let mut scene = SceneFile::from_binary(&data, EndianType::BIG);
+        assert_eq!(scene.raw_data, data);
+        assert_eq!(scene.pack(EndianType::BIG), data);
+
+        scene.add_entity(EntityBuilder::new("Lamp").build());
+        assert!(matches!(scene.try_pack(EndianType::BIG, None), Err(SceneFileError::RawDataModified)));
+    }
+
+    #[test]
+    fn link_id_matching_compares_full_ids() {
+        let mut scene = sample_scene(SceneFileVersion::Version1);
+        let options = MergeOptions { entity_match_key: EntityMatchKey::LinkId, add_unmatched: false, ..MergeOptions::default() };
+        // 0x1_0000_0001 is the Door's link ID 1 cut to 32 bits, it must not match
+        let patch = serde_json::json!({ "objects": [{ "name": "Other", "link_id": 0x1_0000_0001u64, "overwrite": { "unknown": 5 } }] });
+        scene.merge_in_dict_with_options(&patch, &options).unwrap();
+        assert_eq!(scene.objects[0].unknown, 0);
+
+        let patch = serde_json::json!({ "objects": [{ "name": "Other", "link_id": 1, "overwrite": { "unknown": 5 } }] });
+        scene.merge_in_dict_with_options(&patch, &options).unwrap();
+        assert_eq!(scene.objects[0].unknown, 5);
+    }
+
+    #[test]
+    fn preserving_reports_changes_it_cannot_keep() {
+        let data = sample_scene(SceneFileVersion::Version2).pack(EndianType::BIG);
+        let mut scene = SceneFile::from_binary_preserving(&data, EndianType::BIG);
+        assert_eq!(scene.try_to_binary_preserving(EndianType::BIG).unwrap(), data);
+
+        scene.objects[0].components[1].properties[1].value = serde_json::json!([7]);
+        let patched = scene.try_to_binary_preserving(EndianType::BIG).unwrap();
+        assert_eq!(patched.len(), data.len());
+        assert_eq!(patched.iter().zip(&data).filter(|(a, b)| a != b).count(), 4);
+        assert_eq!(SceneFile::from_binary(&patched, EndianType::BIG).objects[0].components[1].properties[1].value, serde_json::json!([7]));
+
+        assert!(matches!(scene.try_to_binary_preserving(EndianType::LITTLE), Err(SceneFileError::NotPreservable(_))));
+        scene.objects[0].components[1].properties[0].value = serde_json::json!(["Shut", "Shut", "Open"]);
+        assert!(matches!(scene.try_to_binary_preserving(EndianType::BIG), Err(SceneFileError::NotPreservable(_))));
+        assert!(matches!(sample_scene(SceneFileVersion::Version2).try_to_binary_preserving(EndianType::BIG), Err(SceneFileError::NotPreservable(_))));
+    }
+
+    #[test]
+    fn failed_merges_change_nothing() {
+        let mut scene = sample_scene(SceneFileVersion::Version2);
+        let before = scene.to_dict();
+        // the door merges fine, then the key's pointer gets a string
+        let patch = serde_json::json!({ "objects": [
+            { "name": "Door", "components": [{ "class_name": "JPSSignComponent", "properties": [{ "name": "Count", "value": [5] }] }] },
+            { "name": "Key", "components": [{ "class_name": "JPSSignComponent", "properties": [{ "name": "Door", "value": ["Door"] }] }] }
+        ], "scene": [9] });
+        assert!(matches!(scene.merge_in_dict_with_options(&patch, &MergeOptions::default()), Err(SceneFileError::ValueTypeMismatch { .. })));
+        assert_eq!(scene.to_dict(), before);
+
+        // appending floats under a new class name has to fit the strings already there
+        let mut labels = property(PropertyType::String, "Labels", serde_json::json!(["Open"]));
+        let result = labels.merge_in_dict_with_options(&serde_json::json!({ "class_name": "Float", "value": [1.5] }), &MergeOptions::default());
+        assert!(matches!(result, Err(SceneFileError::ValueTypeMismatch { .. })));
+        assert!(labels.class_name == PropertyType::String);
+        assert_eq!(labels.value, serde_json::json!(["Open"]));
+        let result = labels.merge_in_dict_with_options(&serde_json::json!({ "class_name": "Float" }), &MergeOptions::default());
+        assert!(matches!(result, Err(SceneFileError::ValueTypeMismatch { .. })));
+        assert!(labels.class_name == PropertyType::String);
+    }
+
+    #[test]
+    fn header_adjustments_drive_reading_and_writing() {
+        for version in VERSIONS {
+            let data = sample_scene(version).pack(EndianType::BIG);
+            assert!(SceneFile::detect_version(&data, EndianType::BIG).unwrap() == version);
+            let adjustments = version.header_adjustments();
+            let Some(version_word) = adjustments.version_word else { continue };
+            // the version word sits right after the string section
+            let position = adjustments.marker_size + string_section(&data, version, EndianType::BIG).len() + 4;
+            assert_eq!(file_manipulator::read_u32_at(&data, position, EndianType::BIG), Some(version_word));
+            let mut unknown = data.clone();
+            unknown[position..position + 4].copy_from_slice(&0x02000009u32.to_be_bytes());
+            assert_eq!(SceneFile::detect_version(&unknown, EndianType::BIG), Err(SceneFileError::UnknownVersion { marker: 0x01000001, version_word: 0x02000009 }));
+        }
+    }
+}